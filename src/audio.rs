@@ -0,0 +1,214 @@
+
+use bevy::prelude::*;
+use bevy::asset::LoadState;
+use bevy::audio::{PlaybackMode, Volume, VolumeLevel};
+use rand_core::RngCore;
+use crate::*;
+
+pub static CROSSFADE_TIME: f32 = 1.5;
+pub static DUCK_LEVEL: f32 = 0.3;
+pub static SLAM_DUCK_TIME: f32 = 0.8;
+pub static LOSS_DUCK_TIME: f32 = 2.5;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AudioChannel {
+    Music,
+    Sfx,
+    Ambience,
+}
+
+//A looping music or ambience track, fading in until it's replaced and then fading out
+#[derive(Component)]
+pub struct ChannelPlayer {
+    pub channel: AudioChannel,
+    pub name: String,
+    pub level: f32,
+    pub fading_out: bool,
+}
+
+//Everything that wants a sound asks here, audio_system does the spawning and mixing
+#[derive(Resource)]
+#[derive(Default)]
+pub struct AudioManager {
+    pub song: Option<String>,
+    pub ambience: Option<String>,
+    pub sfx: Vec<String>,
+    pub duck_time: f32,
+    pub duck_level: f32,
+}
+
+impl AudioManager {
+    pub fn new() -> Self {
+        return AudioManager { duck_level: 1.0, ..default() };
+    }
+
+    //Crossfades over from whatever was playing, asking for the same song again keeps it going
+    pub fn play_song(&mut self, song: &str) {
+        self.song = Some(song.to_owned());
+    }
+
+    pub fn play_ambience(&mut self, ambience: Option<&str>) {
+        self.ambience = ambience.map(|ambience| ambience.to_owned());
+    }
+
+    pub fn play_sfx(&mut self, sound: &str) {
+        self.sfx.push(sound.to_owned());
+    }
+
+    //Pulls the music down for a moment so a big event can be heard
+    pub fn duck(&mut self, seconds: f32) {
+        self.duck_time = self.duck_time.max(seconds);
+    }
+
+    pub fn on_board_event(&mut self, event: &BoardEvent, rng: &mut impl RngCore) {
+        match *event {
+            BoardEvent::Moved { entity_type, state, .. } => {
+                if state == EntityState::Sliding {
+                    self.play_sfx(&format!("Mud{}", (rng.next_u32() % 4) + 1));
+                }
+                let sound = match entity_type {
+                    EntityType::Chicken => {
+                        if state == EntityState::Special {
+                            format!("ChickenFly{}", (rng.next_u32() % 4) + 1)
+                        } else {
+                            format!("Chicken{}", (rng.next_u32() % 4) + 1)
+                        }
+                    }
+                    EntityType::Horse => {format!("Horse{}", (rng.next_u32() % 4) + 1)}
+                    EntityType::Pig => {format!("Pig{}", (rng.next_u32() % 4) + 1)}
+                    EntityType::Goat => {format!("Goat{}", (rng.next_u32() % 4) + 1)}
+                    EntityType::Wagon => {format!("Cart{}", (rng.next_u32() % 3) + 1)}
+                    EntityType::Duck => {format!("Duck{}", (rng.next_u32() % 4) + 1)}
+                    EntityType::Sheep => {
+                        //One bleat for the whole flock
+                        if self.sfx.iter().any(|sound| sound.starts_with("Sheep")) {
+                            return;
+                        }
+                        format!("Sheep{}", (rng.next_u32() % 4) + 1)
+                    }
+                    _ => {return;}
+                };
+                self.play_sfx(&sound);
+            }
+            BoardEvent::Slammed { .. } => {
+                self.play_sfx("GoatCrash");
+                self.duck(SLAM_DUCK_TIME);
+            }
+            BoardEvent::Cracked { .. } => {
+                self.play_sfx(&format!("IceCrack{}", (rng.next_u32() % 2) + 1));
+            }
+            BoardEvent::Herded { .. } => {
+                self.play_sfx(&format!("Dog{}", (rng.next_u32() % 4) + 1));
+            }
+            BoardEvent::Spooked { .. } => {
+                //One clap for the whole herd
+                if !self.sfx.iter().any(|sound| sound.starts_with("Thunder")) {
+                    self.play_sfx(&format!("Thunder{}", (rng.next_u32() % 3) + 1));
+                    self.duck(SLAM_DUCK_TIME);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn volume(&self, channel: AudioChannel, settings: &Settings) -> f32 {
+        return match channel {
+            AudioChannel::Music => settings.music_volume * self.duck_level,
+            AudioChannel::Ambience => settings.ambience_volume,
+            AudioChannel::Sfx => settings.sfx_volume,
+        };
+    }
+
+    fn track(&self, channel: AudioChannel) -> Option<&String> {
+        return match channel {
+            AudioChannel::Music => self.song.as_ref(),
+            AudioChannel::Ambience => self.ambience.as_ref(),
+            AudioChannel::Sfx => None,
+        };
+    }
+}
+
+pub fn audio_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+    music: Res<GameMusic>,
+    sounds: Res<Sounds>,
+    mut audio: ResMut<AudioManager>,
+    mut players: Query<(Entity, &mut ChannelPlayer, Option<&AudioSink>)>,){
+    for sound in std::mem::take(&mut audio.sfx) {
+        if let Some(source) = sounds.sounds.get(&sound) {
+            //A missing file would never play, and so never despawn either
+            if asset_server.get_load_state(source) == LoadState::Failed {
+                continue;
+            }
+            commands.spawn(AudioBundle {
+                source: source.to_owned(),
+                settings: PlaybackSettings{
+                    mode: PlaybackMode::Despawn,
+                    volume: Volume::new_relative(audio.volume(AudioChannel::Sfx, &settings)),
+                    ..default()
+                },
+                ..default()
+            });
+        } else {
+            println!("No sound called {}", sound);
+        }
+    }
+
+    for channel in [AudioChannel::Music, AudioChannel::Ambience] {
+        let wanted = audio.track(channel).cloned();
+        if players.iter().any(|(_, player, _)| player.channel == channel && !player.fading_out && Some(&player.name) == wanted.as_ref()) {
+            continue;
+        }
+        for (_, mut player, _) in &mut players {
+            if player.channel == channel {
+                player.fading_out = true;
+            }
+        }
+        let Some(name) = wanted else {
+            continue;
+        };
+        let source = match channel {
+            AudioChannel::Music => music.songs.get(&name),
+            _ => sounds.sounds.get(&name),
+        };
+        if let Some(source) = source {
+            commands.spawn((AudioBundle {
+                source: source.to_owned(),
+                settings: PlaybackSettings{
+                    mode: PlaybackMode::Loop,
+                    volume: Volume::Absolute(VolumeLevel::new(0.0)),
+                    ..default()
+                },
+                ..default()
+            }, ChannelPlayer { channel, name, level: 0.0, fading_out: false }));
+        } else {
+            println!("No song called {}", name);
+            match channel {
+                AudioChannel::Music => {audio.song = None;}
+                _ => {audio.ambience = None;}
+            }
+        }
+    }
+
+    audio.duck_time = (audio.duck_time - time.delta_seconds()).max(0.0);
+    let duck_target = if audio.duck_time > 0.0 {DUCK_LEVEL} else {1.0};
+    //Duck fast, come back up slowly
+    let duck_step = if duck_target < audio.duck_level {time.delta_seconds() * 4.0} else {time.delta_seconds()};
+    audio.duck_level += (duck_target - audio.duck_level).clamp(-duck_step, duck_step);
+
+    let fade_step = time.delta_seconds() / CROSSFADE_TIME;
+    for (entity, mut player, sink) in &mut players {
+        player.level = if player.fading_out {player.level - fade_step} else {(player.level + fade_step).min(1.0)};
+        if player.fading_out && player.level <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let volume = player.level * audio.volume(player.channel, &settings);
+        if let Some(sink) = sink {
+            sink.set_volume(volume);
+        }
+    }
+}
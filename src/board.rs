@@ -0,0 +1,2089 @@
+use crate::level::*;
+
+use std::collections::{BTreeMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+pub static CHANNELS: usize = 4;
+
+//Foods the player can take from the tray, one of each unless the level hands out more
+pub static TRAY_FOODS: [EntityType; 5] = [EntityType::ChickenFood, EntityType::HorseFood, EntityType::PigFood, EntityType::DuckFood, EntityType::AllFood];
+
+//In the same order as the rows of the flag sheet, so a Flag's index is its place in here
+pub static FLAGS: [EntityType; 36] = [
+    EntityType::FlagChicken1, EntityType::FlagChicken2, EntityType::FlagChicken3, EntityType::FlagChicken4,
+    EntityType::FlagHorse1, EntityType::FlagHorse2, EntityType::FlagHorse3, EntityType::FlagHorse4,
+    EntityType::FlagPig1, EntityType::FlagPig2, EntityType::FlagPig3, EntityType::FlagPig4,
+    EntityType::FlagGoat1, EntityType::FlagGoat2, EntityType::FlagGoat3, EntityType::FlagGoat4,
+    EntityType::FlagWagon1, EntityType::FlagWagon2, EntityType::FlagWagon3, EntityType::FlagWagon4,
+    EntityType::Flag1, EntityType::Flag2, EntityType::Flag3, EntityType::Flag4,
+    EntityType::FlagDuck1, EntityType::FlagDuck2, EntityType::FlagDuck3, EntityType::FlagDuck4,
+    EntityType::FlagSheep1, EntityType::FlagSheep2, EntityType::FlagSheep3, EntityType::FlagSheep4,
+    EntityType::FlagSheepdog1, EntityType::FlagSheepdog2, EntityType::FlagSheepdog3, EntityType::FlagSheepdog4,
+];
+
+//A headless copy of the playing field. Everything the simulation needs lives here,
+//so levels can be stepped without an App, sprites or audio.
+
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Piece {
+    pub id: usize,
+    pub entity_type: EntityType,
+    pub location: Location,
+    pub target_location: Location,
+    pub state: EntityState,
+    pub last_direction: MoveDirection,
+    pub pen: Option<usize>,
+}
+
+impl Piece {
+    pub fn to_entity(&self) -> GameEntity {
+        GameEntity {
+            entity_type: self.entity_type,
+            location: self.location,
+            target_location: self.target_location,
+            state: self.state,
+            last_direction: self.last_direction,
+            pen: self.pen,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Hash)]
+pub struct Cell {
+    pub tile: Tile,
+    pub button: Option<Piece>,
+    pub food: Option<Piece>,
+    pub animal: Option<Piece>,
+    //Chickens mid-flight share a tile with whatever they are flying over
+    pub flying: Option<Piece>,
+    pub flag: Option<Flag>,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq)]
+pub enum BoardEvent {
+    Moved { id: usize, entity_type: EntityType, from: Location, to: Location, state: EntityState },
+    Slammed { id: usize, target: usize, from: Location, to: Location },
+    Pulled { id: usize, target: usize, from: Location, to: Location },
+    Ate { id: usize, food: usize, at: Location },
+    Failed { id: usize, at: Location },
+    Celebrated { id: usize },
+    Muddied { at: Location },
+    ReachedFlag { id: usize, flag: Flag },
+    Toggled { at: Location, tile_type: TileType },
+    Spooked { id: usize, at: Location },
+    Herded { id: usize, target: usize, from: Location, to: Location },
+    Cracked { at: Location, tile_type: TileType },
+    Carried { id: usize, from: Location, to: Location },
+}
+
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq)]
+pub enum StepOutcome {
+    Running,
+    Settled,
+    Won,
+    Lost,
+}
+
+pub struct StepResult {
+    pub events: Vec<BoardEvent>,
+    pub outcome: StepOutcome,
+}
+
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Board {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<Vec<Cell>>,
+    pub rules: WeatherRules,
+    //The last round start_round was called for, 0 after loading
+    pub round: usize,
+    //Every food the player can have out at once, in TRAY_FOODS order
+    pub tray: Vec<EntityType>,
+    next_id: usize,
+    //Flags still out for each species, None for the plain ones, so moves don't have to look over the whole field
+    flags_left: BTreeMap<Option<EntityType>, usize>,
+    //A hash of the field after every conveyor tick this round, to catch riders being handed round in circles
+    conveyed: HashSet<u64>,
+}
+
+impl Board {
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut cells = vec![];
+        for x in 0..width {
+            cells.push(vec![]);
+            for y in 0..height {
+                cells[x].push(Cell {
+                    tile: Tile { tile_type: TileType::Grass, location: Location { x, y, z: 0 }, pen: None },
+                    button: None,
+                    food: None,
+                    animal: None,
+                    flying: None,
+                    flag: None,
+                });
+            }
+        }
+        Board { width, height, cells, rules: WeatherRules::default(), round: 0, tray: TRAY_FOODS.to_vec(), next_id: 0, flags_left: BTreeMap::new(), conveyed: HashSet::new() }
+    }
+
+    pub fn load(&mut self, save: &SaveFile) {
+        self.round = 0;
+        self.conveyed.clear();
+        for savetile in &save.tiles {
+            if let Some(tile) = savetile.0 {
+                self.set_tile(tile);
+                self.set_entity(EntityType::None, tile.location.x, tile.location.y);
+            }
+            if let Some(entity) = savetile.1 {
+                self.set_entity(entity.entity_type, entity.location.x, entity.location.y);
+            }
+            if let Some(entity) = savetile.2 {
+                self.set_entity(entity.entity_type, entity.location.x, entity.location.y);
+            }
+            if let Some(entity) = savetile.3 {
+                self.set_entity(entity.entity_type, entity.location.x, entity.location.y);
+                self.set_animal_pen(entity.pen, entity.location.x, entity.location.y);
+            }
+            if let Some(flag) = savetile.4 {
+                self.set_flag(flag.index, flag.location.x, flag.location.y);
+            }
+        }
+        //Anyone starting out on a button is already holding it down
+        for column in &mut self.cells {
+            for cell in column {
+                if let Some(button) = cell.button.as_mut() {
+                    button.state = if cell.animal.is_some() {EntityState::Special} else {EntityState::Idle};
+                }
+            }
+        }
+    }
+
+    pub fn to_save(&self) -> SaveFile {
+        let mut save = SaveFile { version: SAVE_VERSION, width: self.width, height: self.height, tiles: vec![], meta: None };
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let cell = &self.cells[x][y];
+                save.tiles.push((
+                    Some(cell.tile),
+                    cell.button.map(|piece| piece.to_entity()),
+                    cell.food.map(|piece| piece.to_entity()),
+                    cell.animal.map(|piece| piece.to_entity()),
+                    cell.flag,
+                ));
+            }
+        }
+        return save;
+    }
+
+    pub fn can_get_tile(&self, x: usize, y: usize) -> bool {
+        return x < self.cells.len() && y < self.cells[x].len();
+    }
+
+    //What the tile plays as, so a closed gate comes back as a Fence and an open one as Grass
+    pub fn get_tile_type(&self, x: usize, y: usize) -> Option<TileType> {
+        if self.can_get_tile(x, y) {
+            return Some(self.cells[x][y].tile.tile_type.acts_as());
+        }
+        return None;
+    }
+
+    pub fn get_entity_type(&self, x: usize, y: usize) -> Option<EntityType> {
+        if self.can_get_tile(x, y) {
+            if let Some(entity) = self.cells[x][y].animal {
+                return Some(entity.entity_type);
+            } else if let Some(entity) = self.cells[x][y].food {
+                return Some(entity.entity_type);
+            }
+        }
+        return None;
+    }
+
+    //Whether the player can put something down here, the same with the mouse, the keyboard and in the solver.
+    //Closed gates count as the Fence they act as.
+    pub fn can_place_food(&self, x: usize, y: usize) -> bool {
+        if !self.can_get_tile(x, y) || self.get_entity_type(x, y) != None {
+            return false;
+        }
+        return !matches!(self.get_tile_type(x, y), Some(TileType::Fence) | Some(TileType::Ditch));
+    }
+
+    pub fn set_tile(&mut self, tile: Tile) {
+        if self.can_get_tile(tile.location.x, tile.location.y) {
+            self.cells[tile.location.x][tile.location.y].tile = tile;
+        }
+    }
+
+    //Clears every entity layer on the tile and places the new entity, if any.
+    //Returns the ids of the pieces that were removed.
+    pub fn set_entity(&mut self, entity_type: EntityType, x: usize, y: usize) -> Vec<usize> {
+        let mut removed = vec![];
+        if !self.can_get_tile(x, y) {
+            return removed;
+        }
+        let cell = &mut self.cells[x][y];
+        //Buttons sit underneath everything else and only replace each other
+        if let EntityType::Button(_) = entity_type {
+            removed.extend(cell.button.take().map(|piece| piece.id));
+        } else {
+            for piece in [cell.food.take(), cell.animal.take(), cell.flying.take()].into_iter().flatten() {
+                removed.push(piece.id);
+            }
+        }
+        //Flags sit underneath too, only clearing the tile gets rid of them
+        if entity_type == EntityType::None {
+            removed.extend(cell.button.take().map(|piece| piece.id));
+            let flag = cell.flag.take();
+            self.forget_flag(flag);
+        }
+        let z = match entity_type {
+            EntityType::Chicken => 39,
+            EntityType::Horse | EntityType::Pig | EntityType::Goat | EntityType::Wagon | EntityType::Duck | EntityType::Sheepdog | EntityType::Sheep => 38,
+            EntityType::ChickenFood | EntityType::HorseFood | EntityType::PigFood | EntityType::AllFood | EntityType::WagonFood | EntityType::DuckFood => 37,
+            EntityType::Button(_) => 33,
+            _ => return removed,
+        };
+        let piece = Piece {
+            id: self.next_id,
+            entity_type,
+            location: Location { x, y, z },
+            target_location: Location { x, y, z: 0 },
+            state: EntityState::Idle,
+            last_direction: MoveDirection::None,
+            pen: None,
+        };
+        self.next_id += 1;
+        if z == 33 {
+            self.cells[x][y].button = Some(piece);
+        } else if z == 37 {
+            self.cells[x][y].food = Some(piece);
+        } else {
+            self.cells[x][y].animal = Some(piece);
+        }
+        return removed;
+    }
+
+    pub fn set_flag(&mut self, index: usize, x: usize, y: usize) {
+        if self.can_get_tile(x, y) {
+            let flag = Flag { location: Location { x, y, z: 32 }, index };
+            let old = self.cells[x][y].flag.replace(flag);
+            self.forget_flag(old);
+            *self.flags_left.entry(flag.species()).or_default() += 1;
+        }
+    }
+
+    fn forget_flag(&mut self, flag: Option<Flag>) {
+        if let Some(left) = flag.and_then(|flag| self.flags_left.get_mut(&flag.species())) {
+            *left -= 1;
+        }
+    }
+
+    //How many flags are still out for the species, or the plain ones for None
+    pub fn flags_left(&self, species: Option<EntityType>) -> usize {
+        return self.flags_left.get(&species).copied().unwrap_or(0);
+    }
+
+    //Hands out the level's food, the counts default to one of each.
+    //Bread only comes by default when there's a Duck to feed.
+    pub fn stock_tray(&mut self, food: Option<&BTreeMap<EntityType, usize>>) {
+        let has_duck = self.get_entities().iter().any(|entity| entity.entity_type == EntityType::Duck);
+        self.tray = vec![];
+        for tray_food in TRAY_FOODS {
+            let default = if tray_food == EntityType::DuckFood && !has_duck {0} else {1};
+            let count = food.map_or(default, |food| food.get(&tray_food).copied().unwrap_or(default));
+            self.tray.extend(std::iter::repeat(tray_food).take(count));
+        }
+    }
+
+    //What's left on the tray once the foods already out on the field are taken off it
+    pub fn refill_tray(&self) -> Vec<EntityType> {
+        let mut ret_val = self.tray.clone();
+        for column in &self.cells {
+            for cell in column {
+                if let Some(food) = cell.food {
+                    if let Some(index) = ret_val.iter().position(|tray_food| *tray_food == food.entity_type) {
+                        ret_val.remove(index);
+                    }
+                }
+            }
+        }
+        return ret_val;
+    }
+
+    pub fn set_animal_pen(&mut self, pen: Option<usize>, x: usize, y: usize) -> Option<Piece> {
+        if self.can_get_tile(x, y) {
+            if let Some(animal) = self.cells[x][y].animal.as_mut() {
+                animal.pen = pen;
+                return Some(*animal);
+            }
+        }
+        return None;
+    }
+
+    //Numbers the whole pen the tile is part of, returning every tile that changed
+    pub fn set_pen(&mut self, pen: Option<usize>, x: usize, y: usize) -> Vec<Tile> {
+        let mut ret_val = vec![];
+        if !self.can_get_tile(x, y) {
+            return ret_val;
+        }
+        let tile_type = self.cells[x][y].tile.tile_type;
+        if !tile_type.is_pen() {
+            return ret_val;
+        }
+        let mut open = vec![(x, y)];
+        while let Some((x, y)) = open.pop() {
+            let tile = &mut self.cells[x][y].tile;
+            if tile.tile_type != tile_type || ret_val.iter().any(|done: &Tile| done.location.x == x && done.location.y == y) {
+                continue;
+            }
+            tile.pen = pen;
+            ret_val.push(*tile);
+            if x > 0 {open.push((x - 1, y));}
+            if y > 0 {open.push((x, y - 1));}
+            if x + 1 < self.width {open.push((x + 1, y));}
+            if y + 1 < self.height {open.push((x, y + 1));}
+        }
+        return ret_val;
+    }
+
+    pub fn take_button(&mut self, x: usize, y: usize) -> Option<Piece> {
+        if self.can_get_tile(x, y) {
+            return self.cells[x][y].button.take();
+        }
+        return None;
+    }
+
+    pub fn take_flag(&mut self, x: usize, y: usize) -> Option<Flag> {
+        if self.can_get_tile(x, y) {
+            let flag = self.cells[x][y].flag.take();
+            self.forget_flag(flag);
+            return flag;
+        }
+        return None;
+    }
+
+    pub fn flags(&self) -> Vec<Flag> {
+        let mut ret_val = vec![];
+        for column in &self.cells {
+            for cell in column {
+                ret_val.extend(cell.flag);
+            }
+        }
+        return ret_val;
+    }
+
+    pub fn take_food(&mut self, x: usize, y: usize) -> Option<Piece> {
+        if self.can_get_tile(x, y) {
+            return self.cells[x][y].food.take();
+        }
+        return None;
+    }
+
+    pub fn take_animal(&mut self, x: usize, y: usize) -> Option<Piece> {
+        if self.can_get_tile(x, y) {
+            return self.cells[x][y].animal.take();
+        }
+        return None;
+    }
+
+    pub fn pieces(&self) -> Vec<Piece> {
+        let mut ret_val = vec![];
+        for column in &self.cells {
+            for cell in column {
+                ret_val.extend([cell.button, cell.food, cell.animal, cell.flying].into_iter().flatten());
+            }
+        }
+        return ret_val;
+    }
+
+    pub fn get_entities(&self) -> Vec<Piece> {
+        let mut ret_val = vec![];
+        for column in &self.cells {
+            for cell in column {
+                if let Some(entity) = cell.animal {
+                    ret_val.push(entity);
+                }
+                if let Some(entity) = cell.flying {
+                    ret_val.push(entity);
+                }
+            }
+        }
+        ret_val.sort_by_key(|e| e.entity_type);
+        return ret_val;
+    }
+
+    fn piece_mut(&mut self, id: usize) -> Option<&mut Piece> {
+        for column in &mut self.cells {
+            for cell in column {
+                if let Some(piece) = cell.animal.as_mut().filter(|piece| piece.id == id) {
+                    return Some(piece);
+                }
+                if let Some(piece) = cell.flying.as_mut().filter(|piece| piece.id == id) {
+                    return Some(piece);
+                }
+            }
+        }
+        return None;
+    }
+
+    pub fn likes_food_on_tile(&self, animal: Piece, x: usize, y: usize) -> bool {
+        if self.can_get_tile(x, y) {
+            if let Some(entity) = self.cells[x][y].food {
+                if (animal.entity_type != EntityType::Wagon && animal.entity_type != EntityType::Sheepdog && entity.entity_type == EntityType::AllFood) || entity.entity_type == match animal.entity_type {
+                    EntityType::Chicken => { EntityType::ChickenFood }
+                    EntityType::Pig => { EntityType::PigFood }
+                    EntityType::Horse => { EntityType::HorseFood }
+                    EntityType::Goat => { entity.entity_type }
+                    EntityType::Wagon => { EntityType::WagonFood }
+                    EntityType::Duck => { EntityType::DuckFood }
+                    _ => { EntityType::AllFood }
+                } {
+                    return true;
+                }
+            }
+        }
+        return false;
+    }
+
+    pub fn can_see_food(&self, animal: Piece) -> Location {
+        let animalx = animal.location.x;
+        let animaly = animal.location.y;
+        let mut best = (Location{x: animalx, y: animaly, z:0}, 999);
+        let canfly = animal.entity_type == EntityType::Chicken;
+        for direction in [MoveDirection::Right, MoveDirection::Up, MoveDirection::Left, MoveDirection::Down] {
+            let (mut x, mut y) = (animalx, animaly);
+            match direction {
+                MoveDirection::Right => {x += 1;}
+                MoveDirection::Up => {y += 1;}
+                MoveDirection::Left => {if animalx > 0 {x -= 1;}}
+                MoveDirection::Down => {if animaly > 0 {y -= 1;}}
+                MoveDirection::None => {}
+            }
+            let mut fly = canfly;
+            while self.can_get_tile(x, y) {
+                if self.rules.sight.map_or(false, |sight| x.abs_diff(animalx) + y.abs_diff(animaly) > sight) {
+                    break;
+                }
+                let mut has_flown = false;
+                match self.cells[x][y].tile.tile_type.acts_as() {
+                    tile_type if tile_type.blocks_from(animal.entity_type, direction) => {
+                        break;
+                    }
+                    TileType::Ditch if animal.entity_type == EntityType::Duck => {}
+                    TileType::Ditch => {
+                        if fly {
+                            has_flown = true;
+                        }else{
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+                if self.cells[x][y].animal.is_some() {
+                    if fly {
+                        has_flown = true;
+                    }else{
+                        break;
+                    }
+                }
+                if self.likes_food_on_tile(animal, x, y) {
+                    if let Some(entity) = self.cells[x][y].food {
+                        let distance = x.abs_diff(animalx) + y.abs_diff(animaly);
+                        if best.1 > distance {best = (entity.location, distance);}
+                        if best.1 == distance {
+                            //Goats prefer Mixed Food, everyone else prefers their own.
+                            //Vertical finds are recorded with the horizontal distance (0), same as always.
+                            let mixed = self.get_entity_type(x, y) == Some(EntityType::AllFood);
+                            if mixed == (animal.entity_type == EntityType::Goat) {
+                                best = (entity.location, x.abs_diff(animalx));
+                            }
+                        }
+                        break;
+                    }
+                }
+                match direction {
+                    MoveDirection::Right => {x += 1;}
+                    MoveDirection::Up => {y += 1;}
+                    MoveDirection::Left => {if x == 0 {break;} x -= 1;}
+                    MoveDirection::Down => {if y == 0 {break;} y -= 1;}
+                    MoveDirection::None => {break;}
+                }
+                if has_flown {
+                    fly = false;
+                } else {
+                    fly = canfly;
+                }
+            }
+        }
+        return best.0;
+    }
+
+    //The tile one step away in that direction, if it's on the board
+    pub fn next_tile(&self, x: usize, y: usize, direction: MoveDirection) -> Option<(usize, usize)> {
+        let (x, y) = match direction {
+            MoveDirection::Right => (x + 1, y),
+            MoveDirection::Up => (x, y + 1),
+            MoveDirection::Left if x > 0 => (x - 1, y),
+            MoveDirection::Down if y > 0 => (x, y - 1),
+            _ => return None,
+        };
+        if !self.can_get_tile(x, y) {
+            return None;
+        }
+        return Some((x, y));
+    }
+
+    //The nearest animal a Sheepdog can see in a straight line, if any. Animals settled in their pens
+    //are left alone, and the dog won't look across anything it couldn't walk over.
+    pub fn can_see_animal(&self, dog: Piece) -> Option<Location> {
+        let mut best: Option<(Location, usize)> = None;
+        for direction in [MoveDirection::Right, MoveDirection::Up, MoveDirection::Left, MoveDirection::Down] {
+            let (mut x, mut y) = (dog.location.x, dog.location.y);
+            loop {
+                match direction {
+                    MoveDirection::Right => {x += 1;}
+                    MoveDirection::Up => {y += 1;}
+                    MoveDirection::Left => {if x == 0 {break;} x -= 1;}
+                    MoveDirection::Down => {if y == 0 {break;} y -= 1;}
+                    MoveDirection::None => {break;}
+                }
+                if !self.can_get_tile(x, y) {
+                    break;
+                }
+                let distance = x.abs_diff(dog.location.x) + y.abs_diff(dog.location.y);
+                if self.rules.sight.map_or(false, |sight| distance > sight) {
+                    break;
+                }
+                let tile_type = self.cells[x][y].tile.tile_type.acts_as();
+                if tile_type.blocks_from(dog.entity_type, direction) || tile_type == TileType::Ditch {
+                    break;
+                }
+                if let Some(animal) = self.cells[x][y].animal {
+                    if animal.state != EntityState::Celebrating && best.map_or(true, |(_, best_distance)| distance < best_distance) {
+                        best = Some((animal.location, distance));
+                    }
+                    break;
+                }
+            }
+        }
+        return best.map(|(location, _)| location);
+    }
+
+    //Sheepdogs walk up to the nearest animal they can see and nudge it one tile further away.
+    //A nudge into a fence, water or another animal just doesn't happen, but anywhere else the animal
+    //lands like it walked there. Returns None if the dog had nothing to do, otherwise whether it went well.
+    fn herd(&mut self, dog: Piece, events: &mut Vec<BoardEvent>) -> Option<bool> {
+        let Some(target) = self.can_see_animal(dog) else {
+            self.set_state(dog.location.x, dog.location.y, EntityState::Idle);
+            return None;
+        };
+        if target.x.abs_diff(dog.location.x) + target.y.abs_diff(dog.location.y) > 1 {
+            return Some(self.move_entity(dog, target, events));
+        }
+        let direction = MoveDirection::towards(dog.location, target);
+        self.set_state(dog.location.x, dog.location.y, EntityState::Idle);
+        let (x, y) = self.next_tile(target.x, target.y, direction)?;
+        if self.cells[x][y].animal.is_some() {
+            return None;
+        }
+        let mut herded = self.cells[target.x][target.y].animal?;
+        if self.cells[x][y].tile.tile_type.acts_as().blocks_from(herded.entity_type, direction) {
+            return None;
+        }
+        herded.location.x = x;
+        herded.location.y = y;
+        herded.last_direction = direction;
+        let landed = self.land(&mut herded);
+        self.leave_tile(target.x, target.y, events);
+        self.cells[x][y].animal = Some(herded);
+        events.push(BoardEvent::Herded { id: dog.id, target: herded.id, from: target, to: herded.location });
+        return Some(landed);
+    }
+
+    //Sets up an animal that was nudged or carried onto the tile it's now on, instead of walking there.
+    //Returns false if that's somewhere fatal.
+    fn land(&self, piece: &mut Piece) -> bool {
+        piece.target_location = piece.location;
+        piece.state = EntityState::Idle;
+        let cell = &self.cells[piece.location.x][piece.location.y];
+        match cell.tile.tile_type.acts_as() {
+            TileType::Ditch => {
+                return piece.entity_type == EntityType::Duck;
+            }
+            tile_type @ (TileType::Mud | TileType::MuddyRocks) => {
+                piece.state = match piece.entity_type {
+                    EntityType::Pig => EntityState::Idle,
+                    EntityType::Duck => EntityState::Special,
+                    _ => EntityState::Sliding,
+                };
+                return !(piece.entity_type == EntityType::Wagon && tile_type == TileType::MuddyRocks);
+            }
+            TileType::Rocks => {
+                return piece.entity_type != EntityType::Wagon;
+            }
+            TileType::Ice | TileType::CrackedIce => {
+                piece.state = EntityState::Sliding;
+            }
+            _ => {
+                if self.settles_in(*piece, piece.location.x, piece.location.y) {
+                    piece.state = EntityState::Celebrating;
+                }
+            }
+        }
+        return true;
+    }
+
+    //Conveyors all carry whoever is standing on them, and any food left on them, a tile their way at once, before
+    //anyone else moves. Someone with an animal in front can still go if that one is carried out of the way. Food
+    //stops at anything it can't be put down on, like an animal, another food, a fence or a ditch.
+    //Returns false if anyone gets carried somewhere fatal.
+    fn run_conveyors(&mut self, has_simulated: &mut bool, events: &mut Vec<BoardEvent>) -> bool {
+        //Who is on each conveyor, and whether it's a food
+        let mut riders = vec![];
+        for x in 0..self.width {
+            for y in 0..self.height {
+                if let TileType::Conveyor(direction) = self.cells[x][y].tile.tile_type {
+                    if let Some(animal) = self.cells[x][y].animal.filter(|animal| animal.state != EntityState::Celebrating) {
+                        riders.push((animal, direction, false));
+                    }
+                    if let Some(food) = self.cells[x][y].food {
+                        riders.push((food, direction, true));
+                    }
+                }
+            }
+        }
+        let mut carrying = true;
+        while carrying {
+            carrying = false;
+            let mut i = 0;
+            while i < riders.len() {
+                let (rider, direction, is_food) = riders[i];
+                let target = self.next_tile(rider.location.x, rider.location.y, direction)
+                    .filter(|(x, y)| !self.cells[*x][*y].tile.tile_type.acts_as().blocks_from(rider.entity_type, direction))
+                    .filter(|(x, y)| !is_food || self.cells[*x][*y].tile.tile_type.acts_as() != TileType::Ditch);
+                let Some((x, y)) = target else {
+                    riders.remove(i);
+                    continue;
+                };
+                if self.cells[x][y].animal.is_some() || (is_food && self.cells[x][y].food.is_some()) {
+                    i += 1;
+                    continue;
+                }
+                riders.remove(i);
+                carrying = true;
+                *has_simulated = true;
+                let mut carried = rider;
+                carried.location.x = x;
+                carried.location.y = y;
+                carried.last_direction = direction;
+                if is_food {
+                    carried.target_location = carried.location;
+                    self.cells[rider.location.x][rider.location.y].food = None;
+                    self.cells[x][y].food = Some(carried);
+                    events.push(BoardEvent::Carried { id: carried.id, from: rider.location, to: carried.location });
+                    continue;
+                }
+                let landed = self.land(&mut carried);
+                self.leave_tile(rider.location.x, rider.location.y, events);
+                self.cells[x][y].animal = Some(carried);
+                events.push(BoardEvent::Carried { id: carried.id, from: rider.location, to: carried.location });
+                if !landed {
+                    events.push(BoardEvent::Failed { id: carried.id, at: carried.location });
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+
+    //Sheep that touch (not diagonally) make up a flock, and each flock takes its turn as one. It heads for
+    //the nearest food any of them can see and they all step the same way at once. A sheep that walks into
+    //a fence or off the edge stays behind and the flock splits, but another animal in the way stops the
+    //whole flock. Returns false if a sheep steps somewhere fatal.
+    fn move_flocks(&mut self, has_simulated: &mut bool, events: &mut Vec<BoardEvent>) -> bool {
+        //Sheep that made it into their pen have left the flock for good
+        let sheep: Vec<Piece> = self.get_entities().into_iter().filter(|entity| entity.entity_type == EntityType::Sheep && entity.state != EntityState::Celebrating).collect();
+
+        //Anyone knocked into the mud has to find their feet before the flock can go anywhere
+        let sliding: Vec<Piece> = sheep.iter().copied().filter(|entity| entity.state == EntityState::Sliding && entity.last_direction != MoveDirection::None).collect();
+        for entity in &sliding {
+            if !self.slide_entity(*entity, entity.last_direction, events) {
+                events.push(BoardEvent::Failed { id: entity.id, at: entity.location });
+                return false;
+            }
+        }
+        if !sliding.is_empty() {
+            *has_simulated = true;
+            return true;
+        }
+
+        let mut flocks: Vec<Vec<Piece>> = vec![];
+        let mut flocked: Vec<usize> = vec![];
+        for entity in &sheep {
+            if flocked.contains(&entity.id) {
+                continue;
+            }
+            flocked.push(entity.id);
+            let mut flock = vec![*entity];
+            let mut i = 0;
+            while i < flock.len() {
+                let member = flock[i];
+                for other in &sheep {
+                    if !flocked.contains(&other.id) && other.location.x.abs_diff(member.location.x) + other.location.y.abs_diff(member.location.y) == 1 {
+                        flocked.push(other.id);
+                        flock.push(*other);
+                    }
+                }
+                i += 1;
+            }
+            flocks.push(flock);
+        }
+
+        for flock in flocks {
+            //A flock that stopped to eat waits for everyone to finish
+            if flock.iter().any(|member| member.state == EntityState::Eating) {
+                for member in &flock {
+                    self.set_state(member.location.x, member.location.y, EntityState::Idle);
+                }
+                *has_simulated = true;
+                continue;
+            }
+
+            let mut heading: Option<(usize, Location, MoveDirection)> = None;
+            for member in &flock {
+                let food = self.can_see_food(*member);
+                let distance = food.x.abs_diff(member.location.x) + food.y.abs_diff(member.location.y);
+                if distance > 0 && heading.map_or(true, |(best, _, _)| distance < best) {
+                    heading = Some((distance, food, MoveDirection::towards(member.location, food)));
+                }
+            }
+            let Some((_, food, direction)) = heading else {
+                for member in &flock {
+                    self.set_state(member.location.x, member.location.y, EntityState::Idle);
+                }
+                continue;
+            };
+
+            let mut targets: Vec<Option<(usize, usize)>> = flock.iter()
+                .map(|member| self.next_tile(member.location.x, member.location.y, direction))
+                .map(|target| target.filter(|(x, y)| !self.cells[*x][*y].tile.tile_type.acts_as().blocks_from(EntityType::Sheep, direction)))
+                .collect();
+            let outsider = targets.iter().flatten().any(|(x, y)| {
+                self.cells[*x][*y].animal.map_or(false, |animal| !flock.iter().any(|member| member.id == animal.id))
+            });
+            //A sheep can only step where a flockmate is if that one is moving too
+            let mut changed = !outsider;
+            while changed {
+                changed = false;
+                for i in 0..flock.len() {
+                    let Some((x, y)) = targets[i] else {
+                        continue;
+                    };
+                    if let Some(ahead) = flock.iter().position(|member| member.location.x == x && member.location.y == y) {
+                        if targets[ahead].is_none() {
+                            targets[i] = None;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            if outsider || targets.iter().all(|target| target.is_none()) {
+                for member in &flock {
+                    self.set_state(member.location.x, member.location.y, EntityState::Idle);
+                }
+                continue;
+            }
+
+            *has_simulated = true;
+            for (member, target) in flock.iter().zip(&targets) {
+                if target.is_some() {
+                    self.leave_tile(member.location.x, member.location.y, events);
+                } else {
+                    self.set_state(member.location.x, member.location.y, EntityState::Idle);
+                }
+            }
+            let mut landed = true;
+            for (member, target) in flock.iter().zip(&targets) {
+                let Some((x, y)) = *target else {
+                    continue;
+                };
+                let mut moved = *member;
+                moved.location.x = x;
+                moved.location.y = y;
+                moved.target_location = food;
+                moved.last_direction = direction;
+                moved.state = EntityState::Walking;
+                match self.cells[x][y].tile.tile_type.acts_as() {
+                    //Water here means the ice just gave way under the one in front
+                    TileType::Ditch | TileType::Water => {
+                        if landed {
+                            events.push(BoardEvent::Failed { id: moved.id, at: moved.location });
+                        }
+                        landed = false;
+                    }
+                    TileType::Mud | TileType::MuddyRocks | TileType::Ice | TileType::CrackedIce => {
+                        moved.state = EntityState::Sliding;
+                    }
+                    _ => {
+                        if self.settles_in(moved, x, y) {
+                            moved.state = EntityState::Celebrating;
+                        }
+                    }
+                }
+                if let Some(food_entity) = self.cells[x][y].food.filter(|_| self.likes_food_on_tile(moved, x, y)) {
+                    self.cells[x][y].food = None;
+                    moved.state = EntityState::Eating;
+                    events.push(BoardEvent::Ate { id: moved.id, food: food_entity.id, at: food_entity.location });
+                }
+                self.cells[x][y].animal = Some(moved);
+                events.push(BoardEvent::Moved { id: moved.id, entity_type: moved.entity_type, from: member.location, to: moved.location, state: moved.state });
+            }
+            if !landed {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    //Whether an animal on (x, y) is home for good. Being in its own pen isn't enough while there are still
+    //flags around it might need, since it would never move again to go and get them. Numbered pens also need the
+    //animal's number to match.
+    fn settles_in(&self, piece: Piece, x: usize, y: usize) -> bool {
+        let tile = self.cells[x][y].tile;
+        if !tile.tile_type.acts_as().is_pen_for(piece.entity_type) || tile.pen != piece.pen {
+            return false;
+        }
+        return self.flags_left(None) == 0 && self.flags_left(Some(piece.entity_type)) == 0;
+    }
+
+    //Animals standing on the next flag they need pick it up. Flags for one animal have to be taken in order,
+    //and the plain flags can be taken by anyone, also in order.
+    fn reach_flags(&mut self, events: &mut Vec<BoardEvent>) {
+        if self.flags_left.values().all(|left| *left == 0) {
+            return;
+        }
+        let flags = self.flags();
+        let mut reached = vec![];
+        for column in &mut self.cells {
+            for cell in column {
+                let (Some(animal), Some(flag)) = (cell.animal, cell.flag) else {
+                    continue;
+                };
+                if flag.species().map_or(false, |species| species != animal.entity_type) {
+                    continue;
+                }
+                if flags.iter().any(|other| other.species() == flag.species() && other.order() < flag.order()) {
+                    continue;
+                }
+                cell.flag = None;
+                reached.push(flag);
+                events.push(BoardEvent::ReachedFlag { id: animal.id, flag });
+            }
+        }
+        for flag in reached {
+            self.forget_flag(Some(flag));
+        }
+    }
+
+    //Stepping onto a button flips every gate on its channel, and it has to be stepped off and on again
+    //to flip them back. Two buttons on the same channel pressed at once still only flip them once.
+    //An open gate with something in it is left open.
+    fn press_buttons(&mut self, events: &mut Vec<BoardEvent>) {
+        let mut channels = vec![];
+        for column in &mut self.cells {
+            for cell in column {
+                let Some(button) = cell.button.as_mut() else {
+                    continue;
+                };
+                let pressed = cell.animal.is_some();
+                if let (true, EntityState::Idle, EntityType::Button(channel)) = (pressed, button.state, button.entity_type) {
+                    if !channels.contains(&channel) {
+                        channels.push(channel);
+                    }
+                }
+                button.state = if pressed {EntityState::Special} else {EntityState::Idle};
+            }
+        }
+        for column in &mut self.cells {
+            for cell in column {
+                let occupied = cell.animal.is_some() || cell.flying.is_some() || cell.food.is_some();
+                let tile_type = match cell.tile.tile_type {
+                    TileType::Gate(channel) if channels.contains(&channel) => TileType::OpenGate(channel),
+                    TileType::OpenGate(channel) if channels.contains(&channel) && !occupied => TileType::Gate(channel),
+                    _ => continue,
+                };
+                cell.tile.tile_type = tile_type;
+                events.push(BoardEvent::Toggled { at: cell.tile.location, tile_type });
+            }
+        }
+    }
+
+    //Every pen needs its animal, and nobody counts as home until they've been past all their flags
+    pub fn check_win(&mut self, events: &mut Vec<BoardEvent>) -> bool {
+        let mut ret_val = false;
+        if self.flags_left(None) > 0 {
+            return false;
+        }
+        let flags_left = self.flags_left.clone();
+        for column in &mut self.cells {
+            for cell in column {
+                let resident = match cell.tile.tile_type {
+                    TileType::ChickenPen => EntityType::Chicken,
+                    TileType::HorsePen => EntityType::Horse,
+                    TileType::PigPen => EntityType::Pig,
+                    TileType::GoatPen => EntityType::Goat,
+                    TileType::Corral => EntityType::Wagon,
+                    TileType::DuckPen => EntityType::Duck,
+                    TileType::SheepPen => EntityType::Sheep,
+                    _ => continue,
+                };
+                if let Some(entity) = cell.animal.as_mut() {
+                    if entity.entity_type == resident && entity.pen == cell.tile.pen && flags_left.get(&Some(resident)).map_or(true, |left| *left == 0) {
+                        ret_val = true;
+                        if entity.state != EntityState::Celebrating {
+                            events.push(BoardEvent::Celebrated { id: entity.id });
+                        }
+                        entity.state = EntityState::Celebrating;
+                        continue;
+                    }
+                }
+                return false;
+            }
+        }
+        return ret_val;
+    }
+
+    fn celebrate_all(&mut self, events: &mut Vec<BoardEvent>) {
+        for column in &mut self.cells {
+            for cell in column {
+                for piece in [&mut cell.button, &mut cell.food, &mut cell.animal, &mut cell.flying].into_iter().flatten() {
+                    if piece.state != EntityState::Celebrating {
+                        events.push(BoardEvent::Celebrated { id: piece.id });
+                    }
+                    piece.state = EntityState::Celebrating;
+                }
+            }
+        }
+    }
+
+    //Called once when Start is pressed, before the round's first step. This is where rain and thunder
+    //change the field, so it can be lost before anyone takes a turn.
+    pub fn start_round(&mut self, round: usize) -> StepResult {
+        let mut events = vec![];
+        self.round = round;
+        self.conveyed.clear();
+        if self.rules.mud_every.map_or(false, |every| every > 0 && round % every == 0) {
+            self.spread_mud(&mut events);
+        }
+        if self.rules.spook_every.map_or(false, |every| every > 0 && round % every == 0) {
+            if !self.spook(&mut events) {
+                return StepResult { events, outcome: StepOutcome::Lost };
+            }
+        }
+        return StepResult { events, outcome: StepOutcome::Running };
+    }
+
+    //Grass next to a puddle turns to mud
+    fn spread_mud(&mut self, events: &mut Vec<BoardEvent>) {
+        let mut muddied = vec![];
+        for x in 0..self.width {
+            for y in 0..self.height {
+                if self.cells[x][y].tile.tile_type != TileType::Grass {
+                    continue;
+                }
+                let neighbours = [(x + 1, y), (x, y + 1), (x.wrapping_sub(1), y), (x, y.wrapping_sub(1))];
+                if neighbours.iter().any(|(x, y)| matches!(self.get_tile_type(*x, *y), Some(TileType::Mud) | Some(TileType::MuddyRocks))) {
+                    muddied.push((x, y));
+                }
+            }
+        }
+        for (x, y) in muddied {
+            self.cells[x][y].tile.tile_type = TileType::Mud;
+            events.push(BoardEvent::Muddied { at: self.cells[x][y].tile.location });
+        }
+    }
+
+    //Every animal that has moved and isn't settled in a pen bolts one tile back the way it came.
+    //Returns false if one of them bolts somewhere fatal.
+    fn spook(&mut self, events: &mut Vec<BoardEvent>) -> bool {
+        for id in self.get_entities().iter().map(|entity| entity.id) {
+            //An earlier animal may have knocked this one somewhere else
+            let Some(entity) = self.piece_mut(id).copied() else {
+                continue;
+            };
+            if entity.entity_type == EntityType::Wagon || entity.last_direction == MoveDirection::None {
+                continue;
+            }
+            if let EntityState::Celebrating | EntityState::Special = entity.state {
+                continue;
+            }
+            events.push(BoardEvent::Spooked { id, at: entity.location });
+            let bolting = Piece { state: EntityState::Sliding, ..entity };
+            if !self.slide_entity(bolting, entity.last_direction.opposite(), events) {
+                events.push(BoardEvent::Failed { id, at: entity.location });
+                return false;
+            }
+            //They stop where they land unless it's slippery
+            if let Some(entity) = self.piece_mut(id) {
+                if let EntityState::Walking | EntityState::Eating = entity.state {
+                    entity.state = EntityState::Idle;
+                    entity.target_location = entity.location;
+                }
+            }
+        }
+        return true;
+    }
+
+    //Runs one simulation tick: at the start of a pass the conveyors go if anyone is riding one, otherwise the next
+    //species in turn order (Sheepdog, Goat, Horse, Pig, Chicken, Duck, Sheep, Wagon) that has something to do takes its turn. simulation_step is EntityType::None between rounds.
+    pub fn step(&mut self, simulation_step: &mut EntityType) -> StepResult {
+        let mut events = vec![];
+        let mut full_simulation = false;
+        if *simulation_step == EntityType::None {
+            *simulation_step = EntityType::Sheepdog;
+            full_simulation = true;
+        }
+        let mut has_simulated = false;
+        if full_simulation {
+            if !self.run_conveyors(&mut has_simulated, &mut events) {
+                return StepResult { events, outcome: StepOutcome::Lost };
+            }
+            //Riding a conveyor takes up the whole tick, the animals go next time
+            if has_simulated {
+                //Conveyors facing each other or in a ring would carry them forever, so once the field
+                //comes round to how it was after an earlier tick it's as settled as it's going to get
+                let mut hasher = DefaultHasher::new();
+                self.cells.hash(&mut hasher);
+                if !self.conveyed.insert(hasher.finish()) {
+                    return StepResult { events, outcome: StepOutcome::Settled };
+                }
+                self.reach_flags(&mut events);
+                self.press_buttons(&mut events);
+                if self.check_win(&mut events) {
+                    self.celebrate_all(&mut events);
+                    return StepResult { events, outcome: StepOutcome::Won };
+                }
+                return StepResult { events, outcome: StepOutcome::Running };
+            }
+        }
+        while *simulation_step != EntityType::None && !has_simulated {
+            if *simulation_step == EntityType::Sheep && !self.move_flocks(&mut has_simulated, &mut events) {
+                return StepResult { events, outcome: StepOutcome::Lost };
+            }
+            for entity in self.get_entities() {
+                if entity.entity_type != *simulation_step {
+                    continue;
+                }
+                let mut state = entity.state;
+                if entity.state == EntityState::Special {
+                    state = match entity.entity_type {
+                        EntityType::Chicken => {EntityState::Sliding}
+                        EntityType::Pig => {EntityState::Idle}
+                        EntityType::Horse => {EntityState::Idle}
+                        EntityType::Goat => {EntityState::Eating}
+                        //Ducks stay Special while they wade through Mud
+                        _ => {entity.state}
+                    };
+                }
+                let success = match entity.entity_type {
+                    EntityType::Chicken | EntityType::Pig | EntityType::Horse | EntityType::Goat | EntityType::Wagon | EntityType::Duck | EntityType::Sheepdog => {
+                        match state {
+                            EntityState::Idle | EntityState::Walking if entity.entity_type == EntityType::Sheepdog => {
+                                match self.herd(entity, &mut events) {
+                                    Some(success) => {
+                                        has_simulated = true;
+                                        success
+                                    }
+                                    None => {true}
+                                }
+                            }
+                            EntityState::Eating => {
+                                if self.can_get_tile(entity.location.x, entity.location.y) {
+                                    if let Some(entity) = self.cells[entity.location.x][entity.location.y].animal.as_mut() {
+                                        entity.state = EntityState::Idle;
+                                        has_simulated = true;
+                                    }
+                                }
+                                true
+                            }
+                            EntityState::Idle => {
+                                let target_location = self.can_see_food(entity);
+                                if target_location.x != entity.location.x || target_location.y != entity.location.y {
+                                    has_simulated = true;
+                                    self.move_entity(entity, target_location, &mut events)
+                                } else {
+                                    true
+                                }
+                            }
+                            EntityState::Walking => {
+                                has_simulated = true;
+                                self.move_entity(entity, entity.target_location, &mut events)
+                            }
+                            EntityState::Sliding => {
+                                if entity.last_direction != MoveDirection::None {
+                                    has_simulated = true;
+                                    self.slide_entity(entity, entity.last_direction, &mut events)
+                                } else {
+                                    true
+                                }
+                            }
+                            EntityState::Special => {
+                                //A Duck in the Mud spends a turn getting its feet free before it can go on
+                                if entity.entity_type == EntityType::Duck {
+                                    if let Some(duck) = self.cells[entity.location.x][entity.location.y].animal.as_mut() {
+                                        duck.state = if duck.target_location.x != duck.location.x || duck.target_location.y != duck.location.y {EntityState::Walking} else {EntityState::Idle};
+                                        has_simulated = true;
+                                    }
+                                }
+                                true
+                            }
+                            _ => {true}
+                        }
+                    }
+                    _ => {true}
+                };
+                if !success {
+                    events.push(BoardEvent::Failed { id: entity.id, at: entity.location });
+                    return StepResult { events, outcome: StepOutcome::Lost };
+                }
+            }
+            self.reach_flags(&mut events);
+            self.press_buttons(&mut events);
+            if self.check_win(&mut events) {
+                self.celebrate_all(&mut events);
+                return StepResult { events, outcome: StepOutcome::Won };
+            }
+            *simulation_step = simulation_step.next_in_turn();
+            if *simulation_step == EntityType::None && !has_simulated && full_simulation {
+                return StepResult { events, outcome: StepOutcome::Settled };
+            }
+        }
+        return StepResult { events, outcome: StepOutcome::Running };
+    }
+
+    pub fn slide_entity(&mut self,
+        entity: Piece,
+        slide_direction: MoveDirection,
+        events: &mut Vec<BoardEvent>) -> bool{
+
+        let startx = entity.location.x;
+        let starty = entity.location.y;
+
+        let (xoffset, yoffset) = match slide_direction {
+            MoveDirection::Left => {(-1, 0)}
+            MoveDirection::Right => {(1, 0)}
+            MoveDirection::Down => {(0, -1)}
+            MoveDirection::Up => {(0, 1)}
+            _ => {(0, 0)}
+        };
+
+        if (startx as isize) < -xoffset || (starty as isize) < -yoffset || !self.can_get_tile(((startx as isize) + xoffset) as usize, ((starty as isize) + yoffset) as usize) {
+            //SLID OUT OF BOUNDS
+            if entity.entity_type == EntityType::Chicken && entity.state == EntityState::Special {
+                return false;
+            }
+            if let Some(sliding_entity) = self.cells[startx][starty].animal.as_mut() {
+                sliding_entity.state = EntityState::Idle;
+            }
+            return true;
+        }
+        let x: usize = ((startx as isize) + xoffset) as usize;
+        let y: usize = ((starty as isize) + yoffset) as usize;
+        return self.move_entity(entity, Location{x, y, z:0}, events)
+    }
+
+    //Returns false if the move ends in a failure state
+    pub fn move_entity(&mut self,
+        entity: Piece,
+        target_location: Location,
+        events: &mut Vec<BoardEvent>) -> bool{
+
+        let startx = entity.location.x;
+        let starty = entity.location.y;
+
+        let mut target = (target_location.x as isize, target_location.y as isize);
+        if (target.0 - (startx as isize)).abs() > (target.1 - (starty as isize)).abs() {target.1 = starty as isize;} else {target.0 = startx as isize;}
+
+        let (xoffset, yoffset): (isize, isize) = ((target.0 - (startx as isize)).signum(), (target.1 - (starty as isize)).signum());
+
+        let move_direction =
+            if xoffset < 0 {
+                MoveDirection::Left
+            }else if xoffset > 0 {
+                MoveDirection::Right
+            }else if yoffset < 0 {
+                MoveDirection::Down
+            }else if yoffset > 0 {
+                MoveDirection::Up
+            }else{
+                MoveDirection::None
+            };
+
+        if move_direction == MoveDirection::None ||
+            (
+                entity.state != EntityState::Sliding &&
+                !(entity.state == EntityState::Special && entity.entity_type == EntityType::Chicken) &&
+                entity.entity_type != EntityType::Sheepdog &&
+                !self.likes_food_on_tile(entity, target_location.x, target_location.y)
+            ) {
+            if let Some(moving_entity) = self.cells[startx][starty].animal.as_mut() {
+                moving_entity.state = EntityState::Idle;
+                moving_entity.target_location = moving_entity.location;
+            }
+            return true;
+        }
+
+        if (startx as isize) < -xoffset || (starty as isize) < -yoffset {
+            if let Some(moving_entity) = self.cells[startx][starty].animal.as_mut() {
+                moving_entity.state = EntityState::Idle;
+            }
+            return true;
+        }
+        let x: usize = ((startx as isize) + xoffset) as usize;
+        let y: usize = ((starty as isize) + yoffset) as usize;
+
+        let tile_in_front = (x as isize) >= -xoffset && (y as isize) >= -yoffset;
+        let frontx: usize = if tile_in_front {((x as isize) + xoffset) as usize} else {0};
+        let fronty: usize = if tile_in_front {((y as isize) + yoffset) as usize} else {0};
+
+        let tile_in_back = (startx as isize) >= xoffset && (starty as isize) >= yoffset;
+        let backx: usize = if tile_in_back {((startx as isize) - xoffset) as usize} else {0};
+        let backy: usize = if tile_in_back {((starty as isize) - yoffset) as usize} else {0};
+
+        if !(self.can_get_tile(x, y) && self.can_get_tile(startx, starty)) {
+            //Flew off the edge of the map
+            return !(entity.entity_type == EntityType::Chicken && entity.state == EntityState::Special);
+        }
+
+        if entity.entity_type == EntityType::Chicken && entity.state == EntityState::Special {
+            //Landing
+            if let Some(mut moving_entity) = self.cells[startx][starty].flying {
+                match self.cells[x][y].tile.tile_type.acts_as() {
+                    TileType::Ditch => {
+                        return false;
+                    }
+                    tile_type if tile_type.blocks_from(moving_entity.entity_type, move_direction) => {
+                        return false;
+                    }
+                    TileType::Mud | TileType::MuddyRocks | TileType::Ice | TileType::CrackedIce => {
+                        moving_entity.state = EntityState::Sliding;
+                    }
+                    _ => {
+                        moving_entity.state = if self.settles_in(moving_entity, x, y) {EntityState::Celebrating} else {EntityState::Walking};
+                    }
+                }
+                moving_entity.target_location = target_location;
+                if moving_entity.target_location.x == moving_entity.location.x && moving_entity.target_location.y == moving_entity.location.y {
+                    moving_entity.state = EntityState::Idle;
+                }
+                moving_entity.last_direction = move_direction;
+                moving_entity.location.x = x;
+                moving_entity.location.y = y;
+                if self.cells[x][y].animal.is_some() {
+                    return false;
+                }
+                self.cells[x][y].animal = Some(moving_entity);
+                self.cells[startx][starty].flying = None;
+                events.push(BoardEvent::Moved { id: moving_entity.id, entity_type: moving_entity.entity_type, from: entity.location, to: moving_entity.location, state: moving_entity.state });
+
+                if let Some(food_entity) = self.cells[x][y].food {
+                    if food_entity.entity_type == EntityType::AllFood || food_entity.entity_type == EntityType::ChickenFood {
+                        self.cells[x][y].food = None;
+                        events.push(BoardEvent::Ate { id: moving_entity.id, food: food_entity.id, at: food_entity.location });
+                        match self.cells[x][y].tile.tile_type.acts_as() {
+                            TileType::Mud | TileType::MuddyRocks => {}
+                            _ => {
+                                if let Some(moving_entity) = self.cells[x][y].animal.as_mut() {
+                                    moving_entity.state = EntityState::Eating;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            return true;
+        }
+
+        let entity_id = match self.cells[startx][starty].animal {
+            Some(moving_entity) => moving_entity.id,
+            None => return true,
+        };
+
+        if let (Some(moving_entity), Some(target_entity)) = (self.cells[startx][starty].animal, self.cells[x][y].animal) {
+            //check for other animals in the way
+            if target_entity.entity_type != EntityType::None {
+                if moving_entity.entity_type == EntityType::Chicken && moving_entity.state != EntityState::Special {
+                    self.set_state(startx, starty, EntityState::Special);
+                } else {
+                    self.set_state(startx, starty, EntityState::Idle);
+                    if moving_entity.entity_type == EntityType::Wagon {
+                        if let TileType::MuddyRocks | TileType::Rocks = self.cells[startx][starty].tile.tile_type.acts_as() {
+                            return false;
+                        }
+                    }
+                    if moving_entity.entity_type == EntityType::Goat && target_entity.state != EntityState::Celebrating {
+                        let tile_slam_target = (frontx as isize) > -xoffset || (fronty as isize) > -yoffset;
+                        if tile_slam_target &&
+                            self.can_get_tile(((frontx as isize) + xoffset) as usize, ((fronty as isize) + yoffset) as usize) {
+                            let tile_slam_target_x = ((frontx as isize) + xoffset) as usize;
+                            let tile_slam_target_y = ((fronty as isize) + yoffset) as usize;
+                            if self.cells[tile_slam_target_x][tile_slam_target_y].animal.is_some() {
+                                return false;
+                            }
+                            if let Some(tile_type) = self.get_tile_type(frontx, fronty) {
+                                if tile_type.blocks_from(target_entity.entity_type, move_direction) || tile_type == TileType::Ditch {
+                                    return false;
+                                }
+                            }
+                            //SLAM
+                            self.set_state(startx, starty, EntityState::Special);
+                            if !self.slam(target_entity, tile_slam_target_x, tile_slam_target_y, move_direction, entity_id, events) {
+                                return false;
+                            }
+                        }
+                    }
+                    return true;
+                }
+            }
+        }
+
+        let mut eating = false;
+        if let Some(food_entity) = self.cells[x][y].food {
+            let eats = if entity.entity_type != EntityType::Wagon && entity.entity_type != EntityType::Sheepdog && food_entity.entity_type == EntityType::AllFood {
+                true
+            } else {
+                match entity.entity_type {
+                    EntityType::Chicken => {food_entity.entity_type == EntityType::ChickenFood}
+                    EntityType::Pig => {food_entity.entity_type == EntityType::PigFood}
+                    EntityType::Horse => {food_entity.entity_type == EntityType::HorseFood}
+                    EntityType::Goat => {true}
+                    EntityType::Wagon => {food_entity.entity_type == EntityType::WagonFood}
+                    EntityType::Duck => {food_entity.entity_type == EntityType::DuckFood}
+                    _ => {false}
+                }
+            };
+            if eats {
+                self.cells[x][y].food = None;
+                eating = true;
+                events.push(BoardEvent::Ate { id: entity_id, food: food_entity.id, at: food_entity.location });
+            }
+        }
+
+        if let Some(mut moving_entity) = self.cells[startx][starty].animal {
+            if !(moving_entity.entity_type == EntityType::Chicken && moving_entity.state == EntityState::Special){
+                match self.cells[x][y].tile.tile_type.acts_as() {
+                    tile_type if tile_type.blocks_from(moving_entity.entity_type, move_direction) => {
+                        self.set_state(startx, starty, EntityState::Idle);
+                        if entity.entity_type == EntityType::Wagon {
+                            if let TileType::MuddyRocks | TileType::Rocks = self.cells[startx][starty].tile.tile_type.acts_as() {
+                                return false;
+                            }
+                        }
+                        return true;
+                    }
+                    TileType::Mud => {
+                        //set state as muddy
+                        if moving_entity.entity_type == EntityType::Pig {
+                            moving_entity.state = EntityState::Walking;
+                        }else if moving_entity.entity_type == EntityType::Duck {
+                            moving_entity.state = EntityState::Special;
+                        }else{
+                            moving_entity.state = EntityState::Sliding;
+                        }
+                    }
+                    TileType::MuddyRocks => {
+                        if moving_entity.entity_type == EntityType::Wagon {
+                            return false;
+                        }
+                        //set state as muddy
+                        if moving_entity.entity_type == EntityType::Pig {
+                            moving_entity.state = EntityState::Walking;
+                        }else if moving_entity.entity_type == EntityType::Duck {
+                            moving_entity.state = EntityState::Special;
+                        }else{
+                            moving_entity.state = EntityState::Sliding;
+                        }
+                    }
+                    TileType::Rocks => {
+                        if moving_entity.entity_type == EntityType::Wagon {
+                            return false;
+                        }
+                        moving_entity.state = EntityState::Walking;
+                    }
+                    TileType::Ice | TileType::CrackedIce => {
+                        //Unlike Mud, nobody keeps their footing here. Not even Pigs.
+                        moving_entity.state = EntityState::Sliding;
+                    }
+                    TileType::Ditch => {
+                        if moving_entity.entity_type == EntityType::Chicken {
+                            moving_entity.state = EntityState::Special;
+                        } else if moving_entity.entity_type == EntityType::Duck {
+                            moving_entity.state = EntityState::Walking;
+                        } else {
+                            return false;
+                        }
+                    }
+                    _ => {
+                        moving_entity.state = if self.settles_in(moving_entity, x, y) {
+                            EntityState::Celebrating
+                        } else {
+                            EntityState::Walking
+                        };
+                    }
+                }
+                if entity.state != EntityState::Sliding {
+                    moving_entity.target_location = target_location;
+                    if moving_entity.target_location.x == moving_entity.location.x && moving_entity.target_location.y == moving_entity.location.y {
+                        moving_entity.state = EntityState::Idle;
+                    }
+                    if eating {
+                        moving_entity.state = EntityState::Eating;
+                    }
+                }
+            }
+
+            moving_entity.last_direction = move_direction;
+            moving_entity.location.x = x;
+            moving_entity.location.y = y;
+            if moving_entity.entity_type == EntityType::Chicken && moving_entity.state == EntityState::Special {
+                if self.cells[x][y].flying.is_some() {
+                    return false;
+                }
+                self.cells[x][y].flying = Some(moving_entity);
+            }else{
+                self.cells[x][y].animal = Some(moving_entity);
+            }
+            self.leave_tile(startx, starty, events);
+            events.push(BoardEvent::Moved { id: moving_entity.id, entity_type: moving_entity.entity_type, from: entity.location, to: moving_entity.location, state: moving_entity.state });
+        }
+
+        if tile_in_front && self.can_get_tile(frontx, fronty) {
+            if let Some(slam_entity) = self.cells[frontx][fronty].animal {
+                if entity.entity_type == EntityType::Goat && slam_entity.state != EntityState::Celebrating {
+                    let tile_slam_target = (frontx as isize) > -xoffset*2 || (fronty as isize) > -yoffset*2;
+                    if tile_slam_target &&
+                    self.can_get_tile(((frontx as isize) + xoffset*2) as usize, ((fronty as isize) + yoffset*2) as usize) {
+                        let tile_slam_target_x = ((frontx as isize) + xoffset*2) as usize;
+                        let tile_slam_target_y = ((fronty as isize) + yoffset*2) as usize;
+                        if let Some(tile_type) = self.get_tile_type(((frontx as isize) + xoffset) as usize, ((fronty as isize) + yoffset) as usize) {
+                            if tile_type.blocks_from(slam_entity.entity_type, move_direction) {
+                                return false;
+                            }
+                        }
+                        if self.cells[tile_slam_target_x][tile_slam_target_y].animal.is_some() {
+                            return false;
+                        }
+                        if let Some(tile_type) = self.get_tile_type(frontx, fronty) {
+                            if tile_type.blocks_from(slam_entity.entity_type, move_direction) || tile_type == TileType::Ditch {
+                                return false;
+                            }
+                        }
+                        //SLAM
+                        if let Some(goat) = self.piece_mut(entity_id) {
+                            goat.state = EntityState::Special;
+                        }
+                        if !self.slam(slam_entity, tile_slam_target_x, tile_slam_target_y, move_direction, entity_id, events) {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+        if tile_in_back && self.can_get_tile(backx, backy) {
+            if let Some(mut pull_entity) = self.cells[backx][backy].animal {
+                if entity.entity_type == EntityType::Horse && pull_entity.entity_type == EntityType::Wagon && pull_entity.state != EntityState::Celebrating && self.cells[startx][starty].animal.is_none() {
+                    pull_entity.last_direction = move_direction;
+                    pull_entity.location.x = startx;
+                    pull_entity.location.y = starty;
+                    let tile_type = self.cells[startx][starty].tile.tile_type.acts_as();
+                    if self.settles_in(pull_entity, startx, starty) {
+                        pull_entity.state = EntityState::Celebrating;
+                    }
+                    if let TileType::Mud | TileType::MuddyRocks | TileType::Ice | TileType::CrackedIce = tile_type {
+                        pull_entity.state = EntityState::Sliding;
+                    }
+                    self.cells[startx][starty].animal = Some(pull_entity);
+                    self.leave_tile(backx, backy, events);
+                    events.push(BoardEvent::Pulled { id: entity_id, target: pull_entity.id, from: Location { x: backx, y: backy, z: pull_entity.location.z }, to: pull_entity.location });
+                    //The Horse can crack the ice out from under the cart
+                    if tile_type == TileType::Rocks || tile_type == TileType::Water {
+                        return false;
+                    }
+                }
+            }
+        }
+        return true;
+    }
+
+    //Takes the animal off (x, y). Ice cracks as they step off it and gives way the second time.
+    fn leave_tile(&mut self, x: usize, y: usize, events: &mut Vec<BoardEvent>) {
+        self.cells[x][y].animal = None;
+        let tile_type = match self.cells[x][y].tile.tile_type {
+            TileType::Ice => TileType::CrackedIce,
+            TileType::CrackedIce => TileType::Water,
+            _ => return,
+        };
+        self.cells[x][y].tile.tile_type = tile_type;
+        events.push(BoardEvent::Cracked { at: self.cells[x][y].tile.location, tile_type });
+    }
+
+    fn set_state(&mut self, x: usize, y: usize, state: EntityState) {
+        if let Some(entity) = self.cells[x][y].animal.as_mut() {
+            entity.state = state;
+        }
+    }
+
+    //Knocks target_entity from wherever it stands to (x, y). Returns false if it lands somewhere fatal.
+    fn slam(&mut self, mut target_entity: Piece, x: usize, y: usize, move_direction: MoveDirection, goat_id: usize, events: &mut Vec<BoardEvent>) -> bool {
+        let from = target_entity.location;
+        target_entity.last_direction = move_direction;
+        target_entity.location.x = x;
+        target_entity.location.y = y;
+        target_entity.target_location = target_entity.location;
+        target_entity.state = EntityState::Idle;
+        let mut landed = true;
+        match self.cells[x][y].tile.tile_type.acts_as() {
+            TileType::Ditch if target_entity.entity_type == EntityType::Duck => {}
+            TileType::Ditch => {
+                landed = false;
+            }
+            tile_type if tile_type.blocks_from(target_entity.entity_type, move_direction) => {
+                landed = false;
+            }
+            TileType::Mud | TileType::MuddyRocks => {
+                if target_entity.entity_type == EntityType::Duck {
+                    target_entity.state = EntityState::Special;
+                } else if target_entity.entity_type != EntityType::Pig {
+                    target_entity.state = EntityState::Sliding;
+                }
+            }
+            TileType::Rocks => {
+                if target_entity.entity_type == EntityType::Wagon {
+                    landed = false;
+                }
+            }
+            TileType::Ice | TileType::CrackedIce => {
+                target_entity.state = EntityState::Sliding;
+            }
+            _ => {
+                if self.settles_in(target_entity, x, y) {
+                    target_entity.state = EntityState::Celebrating;
+                }
+            }
+        }
+        self.leave_tile(from.x, from.y, events);
+        self.cells[x][y].animal = Some(target_entity);
+        events.push(BoardEvent::Slammed { id: goat_id, target: target_entity.id, from, to: target_entity.location });
+        return landed;
+    }
+}
+
+impl EntityType {
+    pub fn flag_index(&self) -> Option<usize> {
+        return FLAGS.iter().position(|flag| flag == self);
+    }
+
+    //Which species goes after this one in a step, None after the last
+    pub fn next_in_turn(&self) -> EntityType {
+        return match self {
+            EntityType::Sheepdog => {EntityType::Goat}
+            EntityType::Goat => {EntityType::Horse}
+            EntityType::Horse => {EntityType::Pig}
+            EntityType::Pig => {EntityType::Chicken}
+            EntityType::Chicken => {EntityType::Duck}
+            EntityType::Duck => {EntityType::Sheep}
+            EntityType::Sheep => {EntityType::Wagon}
+            EntityType::Wagon => {EntityType::None}
+            _ => {EntityType::None}
+        };
+    }
+}
+
+impl Flag {
+    pub fn entity_type(&self) -> EntityType {
+        return FLAGS.get(self.index).copied().unwrap_or(EntityType::None);
+    }
+
+    //Who has to reach it, None for the plain flags anyone can take
+    pub fn species(&self) -> Option<EntityType> {
+        return match self.index / 4 {
+            0 => Some(EntityType::Chicken),
+            1 => Some(EntityType::Horse),
+            2 => Some(EntityType::Pig),
+            3 => Some(EntityType::Goat),
+            4 => Some(EntityType::Wagon),
+            6 => Some(EntityType::Duck),
+            7 => Some(EntityType::Sheep),
+            8 => Some(EntityType::Sheepdog),
+            _ => None,
+        };
+    }
+
+    //0 for the first flag in line
+    pub fn order(&self) -> usize {
+        return self.index % 4;
+    }
+}
+
+impl TileType {
+    pub fn acts_as(&self) -> TileType {
+        return match self {
+            TileType::Gate(_) => TileType::Fence,
+            TileType::OpenGate(_) => TileType::Grass,
+            tile_type => *tile_type,
+        };
+    }
+
+    pub fn is_pen(&self) -> bool {
+        return matches!(self, TileType::ChickenPen | TileType::PigPen | TileType::GoatPen | TileType::HorsePen | TileType::Corral | TileType::DuckPen | TileType::SheepPen);
+    }
+
+    //Tiles that stop an animal in its tracks, and that it can't see food past
+    pub fn blocks(&self, entity_type: EntityType) -> bool {
+        return match self {
+            TileType::Fence => true,
+            TileType::Water => entity_type != EntityType::Duck,
+            _ => false,
+        };
+    }
+
+    //Same as blocks, plus one-way gates for anyone not heading through them the right way
+    pub fn blocks_from(&self, entity_type: EntityType, direction: MoveDirection) -> bool {
+        return match self {
+            TileType::OneWay(way) => *way != direction,
+            tile_type => tile_type.blocks(entity_type),
+        };
+    }
+
+    //Directional tiles a quarter turn clockwise, everything else stays as it is
+    pub fn rotated(&self) -> TileType {
+        return match self {
+            TileType::OneWay(direction) => TileType::OneWay(direction.clockwise()),
+            TileType::Conveyor(direction) => TileType::Conveyor(direction.clockwise()),
+            tile_type => *tile_type,
+        };
+    }
+
+    //Only checks the species, numbered pens are matched up in settles_in and check_win
+    pub fn is_pen_for(&self, entity_type: EntityType) -> bool {
+        return match self {
+            TileType::ChickenPen => entity_type == EntityType::Chicken,
+            TileType::PigPen => entity_type == EntityType::Pig,
+            TileType::HorsePen => entity_type == EntityType::Horse,
+            TileType::GoatPen => entity_type == EntityType::Goat,
+            TileType::Corral => entity_type == EntityType::Wagon,
+            TileType::DuckPen => entity_type == EntityType::Duck,
+            TileType::SheepPen => entity_type == EntityType::Sheep,
+            _ => false,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(board: &mut Board, tile_type: TileType, x: usize, y: usize) {
+        board.set_tile(Tile { tile_type, location: Location { x, y, z: 0 }, pen: None });
+    }
+
+    //Steps a round through to the end, the same as pressing Start once
+    fn play(board: &mut Board) -> (Vec<BoardEvent>, StepOutcome) {
+        let mut events = vec![];
+        let mut simulation_step = EntityType::None;
+        for _ in 0..100 {
+            let result = board.step(&mut simulation_step);
+            events.extend(result.events);
+            if result.outcome != StepOutcome::Running {
+                return (events, result.outcome);
+            }
+        }
+        panic!("round never settled");
+    }
+
+    #[test]
+    fn walks_to_food_and_eats_it() {
+        let mut board = Board::new(5, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert_eq!(events.iter().filter(|event| matches!(event, BoardEvent::Moved { .. })).count(), 3);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Ate { at: Location { x: 3, y: 0, .. }, .. })));
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Pig));
+        assert!(board.cells[3][0].food.is_none());
+    }
+
+    #[test]
+    fn ignores_food_it_doesnt_like() {
+        let mut board = Board::new(5, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::HorseFood, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.is_empty());
+        assert_eq!(board.get_entity_type(0, 0), Some(EntityType::Pig));
+    }
+
+    #[test]
+    fn goat_slams_whoever_is_in_front() {
+        let mut board = Board::new(6, 1);
+        board.set_entity(EntityType::Goat, 0, 0);
+        board.set_entity(EntityType::PigFood, 1, 0);
+        board.set_entity(EntityType::Pig, 2, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Slammed { from: Location { x: 2, .. }, to: Location { x: 4, .. }, .. })));
+        assert_eq!(board.get_entity_type(1, 0), Some(EntityType::Goat));
+        assert_eq!(board.get_entity_type(2, 0), None);
+        assert_eq!(board.get_entity_type(4, 0), Some(EntityType::Pig));
+    }
+
+    #[test]
+    fn horse_pulls_the_wagon_behind_it() {
+        let mut board = Board::new(5, 1);
+        board.set_entity(EntityType::Wagon, 0, 0);
+        board.set_entity(EntityType::Horse, 1, 0);
+        board.set_entity(EntityType::HorseFood, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert_eq!(events.iter().filter(|event| matches!(event, BoardEvent::Pulled { .. })).count(), 2);
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Horse));
+        assert_eq!(board.get_entity_type(2, 0), Some(EntityType::Wagon));
+    }
+
+    #[test]
+    fn slamming_into_a_crowd_loses() {
+        let mut board = Board::new(6, 1);
+        board.set_entity(EntityType::Goat, 0, 0);
+        board.set_entity(EntityType::PigFood, 1, 0);
+        board.set_entity(EntityType::Pig, 2, 0);
+        board.set_entity(EntityType::Chicken, 4, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Lost);
+        assert!(matches!(events.last(), Some(BoardEvent::Failed { .. })));
+    }
+
+    #[test]
+    fn wagon_pulled_onto_rocks_loses() {
+        let mut board = Board::new(5, 1);
+        tile(&mut board, TileType::Rocks, 1, 0);
+        board.set_entity(EntityType::Wagon, 0, 0);
+        board.set_entity(EntityType::Horse, 1, 0);
+        board.set_entity(EntityType::HorseFood, 3, 0);
+        let (_, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Lost);
+    }
+
+    #[test]
+    fn filling_every_pen_wins() {
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::PigPen, 3, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Won);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Celebrated { .. })));
+        assert_eq!(board.cells[3][0].animal.map(|pig| pig.state), Some(EntityState::Celebrating));
+    }
+
+    #[test]
+    fn pen_without_its_animal_doesnt_win() {
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::PigPen, 3, 0);
+        tile(&mut board, TileType::HorsePen, 0, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 3, 0);
+        let (_, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+    }
+
+    #[test]
+    fn flags_have_to_be_reached_before_winning() {
+        let mut board = Board::new(5, 1);
+        tile(&mut board, TileType::DuckPen, 4, 0);
+        board.set_entity(EntityType::Duck, 0, 0);
+        board.set_flag(EntityType::FlagDuck1.flag_index().unwrap(), 2, 0);
+        board.set_entity(EntityType::DuckFood, 4, 0);
+        assert_eq!(board.flags_left(Some(EntityType::Duck)), 1);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Won);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::ReachedFlag { flag: Flag { location: Location { x: 2, .. }, .. }, .. })));
+        assert_eq!(board.flags_left(Some(EntityType::Duck)), 0);
+    }
+
+    #[test]
+    fn conveyors_facing_each_other_settle() {
+        let mut board = Board::new(2, 1);
+        tile(&mut board, TileType::Conveyor(MoveDirection::Right), 0, 0);
+        tile(&mut board, TileType::Conveyor(MoveDirection::Left), 1, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.iter().filter(|event| matches!(event, BoardEvent::Carried { .. })).count() >= 2);
+    }
+
+    #[test]
+    fn conveyor_ring_settles() {
+        let mut board = Board::new(2, 2);
+        tile(&mut board, TileType::Conveyor(MoveDirection::Right), 0, 0);
+        tile(&mut board, TileType::Conveyor(MoveDirection::Up), 1, 0);
+        tile(&mut board, TileType::Conveyor(MoveDirection::Left), 1, 1);
+        tile(&mut board, TileType::Conveyor(MoveDirection::Down), 0, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::Horse, 1, 1);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.iter().filter(|event| matches!(event, BoardEvent::Carried { .. })).count() >= 4);
+    }
+
+    #[test]
+    fn conveyor_carries_food_into_sight() {
+        let mut board = Board::new(4, 2);
+        tile(&mut board, TileType::Conveyor(MoveDirection::Down), 2, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 2, 1);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(matches!(events.first(), Some(BoardEvent::Carried { to: Location { x: 2, y: 0, .. }, .. })));
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Ate { at: Location { x: 2, y: 0, .. }, .. })));
+        assert_eq!(board.get_entity_type(2, 0), Some(EntityType::Pig));
+    }
+
+    #[test]
+    fn conveyor_food_stops_at_a_fence() {
+        let mut board = Board::new(3, 1);
+        tile(&mut board, TileType::Conveyor(MoveDirection::Right), 0, 0);
+        tile(&mut board, TileType::Fence, 1, 0);
+        board.set_entity(EntityType::PigFood, 0, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.is_empty());
+        assert!(board.cells[0][0].food.is_some());
+    }
+
+    #[test]
+    fn ice_cracks_then_gives_way() {
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::Ice, 1, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Cracked { at: Location { x: 1, .. }, tile_type: TileType::CrackedIce })));
+        assert_eq!(board.get_tile_type(1, 0), Some(TileType::CrackedIce));
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Pig));
+
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::CrackedIce, 1, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 3, 0);
+        let (events, _) = play(&mut board);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Cracked { at: Location { x: 1, .. }, tile_type: TileType::Water })));
+        assert_eq!(board.get_tile_type(1, 0), Some(TileType::Water));
+    }
+
+    #[test]
+    fn one_way_gate_only_lets_them_through_its_way() {
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::OneWay(MoveDirection::Right), 1, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 3, 0);
+        let (_, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Pig));
+
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::OneWay(MoveDirection::Left), 1, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.is_empty());
+        assert_eq!(board.get_entity_type(0, 0), Some(EntityType::Pig));
+    }
+
+    #[test]
+    fn flock_moves_as_one() {
+        let mut board = Board::new(4, 2);
+        board.set_entity(EntityType::Sheep, 0, 0);
+        board.set_entity(EntityType::Sheep, 0, 1);
+        board.set_entity(EntityType::AllFood, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert_eq!(events.iter().filter(|event| matches!(event, BoardEvent::Moved { .. })).count(), 6);
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Sheep));
+        assert_eq!(board.get_entity_type(3, 1), Some(EntityType::Sheep));
+    }
+
+    #[test]
+    fn flock_splits_at_a_fence() {
+        let mut board = Board::new(4, 2);
+        tile(&mut board, TileType::Fence, 1, 1);
+        board.set_entity(EntityType::Sheep, 0, 0);
+        board.set_entity(EntityType::Sheep, 0, 1);
+        board.set_entity(EntityType::AllFood, 3, 0);
+        let (_, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Sheep));
+        assert_eq!(board.get_entity_type(0, 1), Some(EntityType::Sheep));
+    }
+
+    #[test]
+    fn sheepdog_herds_the_animal_it_sees() {
+        let mut board = Board::new(6, 1);
+        board.set_entity(EntityType::Sheepdog, 0, 0);
+        board.set_entity(EntityType::Pig, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Herded { from: Location { x: 3, .. }, to: Location { x: 4, .. }, .. })));
+        assert_eq!(board.get_entity_type(5, 0), Some(EntityType::Pig));
+        assert_eq!(board.get_entity_type(4, 0), Some(EntityType::Sheepdog));
+    }
+
+    #[test]
+    fn duck_swims_across_water() {
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::Water, 1, 0);
+        board.set_entity(EntityType::Duck, 0, 0);
+        board.set_entity(EntityType::DuckFood, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Ate { at: Location { x: 3, .. }, .. })));
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Duck));
+    }
+
+    #[test]
+    fn duck_takes_a_turn_to_get_out_of_mud() {
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::Mud, 1, 0);
+        board.set_entity(EntityType::Duck, 0, 0);
+        board.set_entity(EntityType::DuckFood, 3, 0);
+        let mut simulation_step = EntityType::None;
+        while board.get_entity_type(1, 0) != Some(EntityType::Duck) {
+            assert_eq!(board.step(&mut simulation_step).outcome, StepOutcome::Running);
+        }
+        assert_eq!(board.cells[1][0].animal.map(|duck| duck.state), Some(EntityState::Special));
+        //Its next turn only gets it unstuck
+        while board.cells[1][0].animal.map(|duck| duck.state) == Some(EntityState::Special) {
+            assert_eq!(board.step(&mut simulation_step).outcome, StepOutcome::Running);
+        }
+        assert_eq!(board.cells[1][0].animal.map(|duck| duck.state), Some(EntityState::Walking));
+        let (_, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Duck));
+    }
+
+    #[test]
+    fn numbered_pen_only_takes_its_number() {
+        for (animal_pen, outcome) in [(Some(2), StepOutcome::Settled), (Some(1), StepOutcome::Won)] {
+            let mut board = Board::new(4, 1);
+            board.set_tile(Tile { tile_type: TileType::PigPen, location: Location { x: 3, y: 0, z: 0 }, pen: Some(1) });
+            board.set_entity(EntityType::Pig, 0, 0);
+            board.set_animal_pen(animal_pen, 0, 0);
+            board.set_entity(EntityType::PigFood, 3, 0);
+            assert_eq!(play(&mut board).1, outcome);
+            assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Pig));
+        }
+    }
+
+    #[test]
+    fn button_opens_the_gates_on_its_channel() {
+        let mut board = Board::new(5, 1);
+        tile(&mut board, TileType::Gate(0), 3, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::Button(0), 1, 0);
+        board.set_entity(EntityType::PigFood, 1, 0);
+        board.set_entity(EntityType::PigFood, 4, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Toggled { at: Location { x: 3, .. }, tile_type: TileType::OpenGate(0) })));
+        //Stepping off the button leaves the gate as it is
+        assert_eq!(board.cells[3][0].tile.tile_type, TileType::OpenGate(0));
+        assert_eq!(board.get_entity_type(4, 0), Some(EntityType::Pig));
+    }
+
+    #[test]
+    fn gate_stays_open_while_someone_is_in_it() {
+        for (held, tile_type) in [(true, TileType::OpenGate(0)), (false, TileType::Gate(0))] {
+            let mut board = Board::new(5, 1);
+            tile(&mut board, TileType::OpenGate(0), 4, 0);
+            if held {
+                board.set_entity(EntityType::Pig, 4, 0);
+            }
+            board.set_entity(EntityType::Horse, 0, 0);
+            board.set_entity(EntityType::Button(0), 2, 0);
+            board.set_entity(EntityType::HorseFood, 2, 0);
+            assert_eq!(play(&mut board).1, StepOutcome::Settled);
+            assert_eq!(board.get_entity_type(2, 0), Some(EntityType::Horse));
+            assert_eq!(board.cells[4][0].tile.tile_type, tile_type);
+        }
+    }
+
+    #[test]
+    fn food_only_goes_on_gates_while_they_are_open() {
+        let mut board = Board::new(3, 1);
+        tile(&mut board, TileType::Gate(0), 0, 0);
+        tile(&mut board, TileType::OpenGate(0), 1, 0);
+        tile(&mut board, TileType::Ditch, 2, 0);
+        assert!(!board.can_place_food(0, 0));
+        assert!(board.can_place_food(1, 0));
+        assert!(!board.can_place_food(2, 0));
+        board.set_entity(EntityType::PigFood, 1, 0);
+        assert!(!board.can_place_food(1, 0));
+    }
+
+    #[test]
+    fn rain_spreads_mud_onto_the_grass_next_to_it() {
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::Mud, 1, 0);
+        board.rules.mud_every = Some(2);
+        assert!(board.start_round(1).events.is_empty());
+        let result = board.start_round(2);
+        assert_eq!(result.outcome, StepOutcome::Running);
+        assert_eq!(result.events.len(), 2);
+        assert_eq!(board.get_tile_type(0, 0), Some(TileType::Mud));
+        assert_eq!(board.get_tile_type(2, 0), Some(TileType::Mud));
+        assert_eq!(board.get_tile_type(3, 0), Some(TileType::Grass));
+    }
+
+    #[test]
+    fn animals_cant_see_past_their_sight() {
+        let mut board = Board::new(5, 1);
+        board.rules.sight = Some(2);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 3, 0);
+        let (events, outcome) = play(&mut board);
+        assert_eq!(outcome, StepOutcome::Settled);
+        assert!(events.is_empty());
+
+        //Once it's eaten the closer one, the other is close enough
+        board.set_entity(EntityType::PigFood, 2, 0);
+        let (events, _) = play(&mut board);
+        assert!(events.iter().any(|event| matches!(event, BoardEvent::Ate { at: Location { x: 2, .. }, .. })));
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Pig));
+    }
+
+    #[test]
+    fn thunder_sends_them_back_the_way_they_came() {
+        let mut board = Board::new(4, 1);
+        board.rules.spook_every = Some(2);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 2, 0);
+        board.set_entity(EntityType::Horse, 3, 0);
+        board.start_round(1);
+        play(&mut board);
+        assert_eq!(board.get_entity_type(2, 0), Some(EntityType::Pig));
+        let result = board.start_round(2);
+        assert_eq!(result.outcome, StepOutcome::Running);
+        //The Horse never moved, so it has nowhere to bolt back to
+        assert_eq!(result.events.iter().filter(|event| matches!(event, BoardEvent::Spooked { .. })).count(), 1);
+        assert_eq!(board.get_entity_type(1, 0), Some(EntityType::Pig));
+        assert_eq!(board.get_entity_type(3, 0), Some(EntityType::Horse));
+    }
+}
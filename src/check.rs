@@ -0,0 +1,370 @@
+//Looks for broken levels before they ship.
+//Usage: sokobarn-check <level.skb or folder>...
+
+use sokobarn::board::*;
+use sokobarn::level::*;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("Usage: sokobarn-check <level.skb or folder>...");
+        process::exit(2);
+    }
+
+    let mut paths = vec![];
+    for arg in args {
+        let path = PathBuf::from(arg);
+        if path.is_dir() {
+            match fs::read_dir(&path) {
+                Ok(entries) => {
+                    let mut levels: Vec<PathBuf> = entries
+                        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .filter(|entry| entry.extension().map_or(false, |extension| extension == "skb"))
+                        .collect();
+                    levels.sort();
+                    paths.append(&mut levels);
+                }
+                Err(error) => {
+                    eprintln!("Could not read {}: {}", path.display(), error);
+                    process::exit(1);
+                }
+            }
+        } else {
+            paths.push(path);
+        }
+    }
+
+    let mut broken = 0;
+    for path in &paths {
+        let problems = check_file(path);
+        if problems.is_empty() {
+            println!("{}: ok", path.display());
+        } else {
+            broken += 1;
+            for problem in problems {
+                println!("{}: {}", path.display(), problem);
+            }
+        }
+    }
+    println!("{} levels checked, {} with problems", paths.len(), broken);
+    if broken > 0 {
+        process::exit(1);
+    }
+}
+
+fn check_file(path: &Path) -> Vec<String> {
+    let save_string = match fs::read_to_string(path) {
+        Ok(save_string) => save_string,
+        Err(error) => return vec![format!("could not read file: {}", error)],
+    };
+    let value = match serde_json::from_str::<serde_json::Value>(&save_string) {
+        Ok(value) => value,
+        Err(error) => return vec![format!("level loading failed: {}", error)],
+    };
+    //Older versions that still load are fine, they get upgraded on the way in
+    return match SaveFile::try_from(value) {
+        Ok(save) => check_save(&save),
+        Err(error) => vec![format!("level loading failed: {}", error)],
+    };
+}
+
+fn check_save(save: &SaveFile) -> Vec<String> {
+    let mut problems = vec![];
+
+    if let Some(meta) = &save.meta {
+        if let (Some(par), Some(author_par)) = (meta.par, meta.author_par) {
+            if author_par > par {
+                problems.push(format!("author par {} is worse than par {}", author_par, par));
+            }
+        }
+        if let Some(rules) = meta.rules {
+            let weather = meta.weather.unwrap_or_default();
+            for (rule, every) in [("mud_every", rules.mud_every), ("spook_every", rules.spook_every)] {
+                if every == Some(0) {
+                    problems.push(format!("{} is 0, so it never happens", rule));
+                }
+            }
+            if rules.mud_every.is_some() && !matches!(weather, WeatherType::Raining | WeatherType::RainyNight | WeatherType::Thunder) {
+                problems.push(format!("mud spreads like rain but the weather is {:?}", weather));
+            }
+            if rules.sight.is_some() && !matches!(weather, WeatherType::Night | WeatherType::RainyNight) {
+                problems.push(format!("sight is limited like at night but the weather is {:?}", weather));
+            }
+            if rules.spook_every.is_some() && weather != WeatherType::Thunder {
+                problems.push(format!("animals get spooked by thunder but the weather is {:?}", weather));
+            }
+        }
+        for food in meta.food.iter().flat_map(|food| food.keys()) {
+            if !TRAY_FOODS.contains(food) {
+                problems.push(format!("food lists {:?}, which the tray never hands out", food));
+            }
+        }
+    }
+    if save.tiles.len() != save.width * save.height {
+        problems.push(format!("{}x{} level has {} tiles, expected {}", save.width, save.height, save.tiles.len(), save.width * save.height));
+    }
+
+    let mut tiles = HashMap::new();
+    let mut numbers = HashMap::new();
+    for (tile, _, _, _, _) in &save.tiles {
+        let Some(tile) = tile else {
+            continue;
+        };
+        let location = (tile.location.x, tile.location.y);
+        if tile.location.x >= save.width || tile.location.y >= save.height {
+            problems.push(format!("{:?} tile at ({}, {}) is outside the level", tile.tile_type, location.0, location.1));
+        } else if tiles.insert(location, tile.tile_type).is_some() {
+            problems.push(format!("more than one tile at ({}, {})", location.0, location.1));
+        }
+        if let Some(pen) = tile.pen {
+            if !tile.tile_type.is_pen() {
+                problems.push(format!("{:?} at ({}, {}) is numbered {} but isn't a pen", tile.tile_type, location.0, location.1, pen));
+            }
+            numbers.insert(location, pen);
+        }
+    }
+    for y in 0..save.height {
+        for x in 0..save.width {
+            if !tiles.contains_key(&(x, y)) {
+                problems.push(format!("no tile at ({}, {})", x, y));
+            }
+        }
+    }
+
+    //Placing an entity clears everything else on its tile, so only one can survive loading. Buttons sit underneath.
+    let mut entities: HashMap<(usize, usize), Vec<EntityType>> = HashMap::new();
+    let mut buttons: HashMap<(usize, usize), EntityType> = HashMap::new();
+    let mut animals_at: HashMap<(usize, usize), EntityType> = HashMap::new();
+    for (_, button, food, animal, _) in &save.tiles {
+        for entity in [food, animal].into_iter().flatten() {
+            entities.entry((entity.location.x, entity.location.y)).or_default().push(entity.entity_type);
+        }
+        if let Some(animal) = animal {
+            animals_at.insert((animal.location.x, animal.location.y), animal.entity_type);
+        }
+        if let Some(button) = button {
+            buttons.insert((button.location.x, button.location.y), button.entity_type);
+        }
+    }
+    let mut locations: Vec<&(usize, usize)> = entities.keys().collect();
+    locations.sort();
+    for location in locations {
+        let on_tile = &entities[location];
+        if on_tile.len() > 1 {
+            problems.push(format!("{:?} all on ({}, {})", on_tile, location.0, location.1));
+        }
+        match tiles.get(location).map(|tile_type| tile_type.acts_as()) {
+            Some(tile_type @ (TileType::Fence | TileType::Ditch)) => {
+                //Ducks can float in a ditch
+                for entity_type in on_tile.iter().filter(|entity_type| !(tile_type == TileType::Ditch && **entity_type == EntityType::Duck)) {
+                    problems.push(format!("{:?} on {:?} at ({}, {})", entity_type, tile_type, location.0, location.1));
+                }
+            }
+            //Only Ducks can swim, anyone else would be stuck out there for good
+            Some(TileType::Water) => {
+                if let Some(animal) = animals_at.get(location).filter(|entity_type| **entity_type != EntityType::Duck) {
+                    problems.push(format!("{:?} on Water at ({}, {})", animal, location.0, location.1));
+                }
+            }
+            Some(_) => {}
+            None => {
+                for entity_type in on_tile {
+                    problems.push(format!("{:?} at ({}, {}) has no tile under it", entity_type, location.0, location.1));
+                }
+            }
+        }
+    }
+
+    //Buttons and gates only do anything in pairs
+    for (location, button) in &buttons {
+        let EntityType::Button(channel) = button else {
+            problems.push(format!("{:?} in the button layer at ({}, {})", button, location.0, location.1));
+            continue;
+        };
+        if *channel >= CHANNELS {
+            problems.push(format!("button at ({}, {}) is on channel {}, there are only {}", location.0, location.1, channel, CHANNELS));
+        }
+        if !tiles.values().any(|tile_type| matches!(tile_type, TileType::Gate(gate) | TileType::OpenGate(gate) if gate == channel)) {
+            problems.push(format!("button at ({}, {}) has no gates on channel {}", location.0, location.1, channel));
+        }
+        if let Some(tile_type @ (TileType::Fence | TileType::Ditch)) = tiles.get(location).map(|tile_type| tile_type.acts_as()) {
+            problems.push(format!("button on {:?} at ({}, {}) can never be pressed", tile_type, location.0, location.1));
+        }
+    }
+    for (location, tile_type) in &tiles {
+        if let TileType::Gate(channel) | TileType::OpenGate(channel) = tile_type {
+            if !buttons.values().any(|button| *button == EntityType::Button(*channel)) {
+                problems.push(format!("{:?} at ({}, {}) has no button to flip it", tile_type, location.0, location.1));
+            }
+        }
+    }
+
+    //Flags are waypoints, so each one needs somewhere to stand and someone to reach it
+    let mut flags = HashMap::new();
+    for (_, _, _, _, flag) in &save.tiles {
+        let Some(flag) = flag else {
+            continue;
+        };
+        let location = (flag.location.x, flag.location.y);
+        if flag.index >= FLAGS.len() {
+            problems.push(format!("unknown flag {} at ({}, {})", flag.index, location.0, location.1));
+            continue;
+        }
+        if let Some(other) = flags.insert(flag.index, location) {
+            problems.push(format!("{:?} at both ({}, {}) and ({}, {})", flag.entity_type(), other.0, other.1, location.0, location.1));
+        }
+        if let Some(tile_type @ (TileType::Fence | TileType::Ditch)) = tiles.get(&location) {
+            problems.push(format!("{:?} on {:?} at ({}, {})", flag.entity_type(), tile_type, location.0, location.1));
+        }
+        if let Some(species) = flag.species() {
+            if !entities.values().flatten().any(|entity_type| *entity_type == species) {
+                problems.push(format!("{:?} at ({}, {}) but there's no {:?} to reach it", flag.entity_type(), location.0, location.1, species));
+            }
+        }
+    }
+
+    //Every pen has to be filled to win, so each one needs an animal with the same number to fill it
+    let animals: Vec<GameEntity> = save.tiles.iter().filter_map(|(_, _, _, animal, _)| *animal).collect();
+    for pen in [TileType::ChickenPen, TileType::PigPen, TileType::GoatPen, TileType::HorsePen, TileType::Corral, TileType::DuckPen, TileType::SheepPen] {
+        let mut pen_numbers: Vec<Option<usize>> = tiles.iter().filter(|(_, tile_type)| **tile_type == pen).map(|(location, _)| numbers.get(location).copied()).collect();
+        pen_numbers.sort();
+        pen_numbers.dedup();
+        for number in pen_numbers {
+            let count = tiles.iter().filter(|(location, tile_type)| **tile_type == pen && numbers.get(*location).copied() == number).count();
+            let fillers = animals.iter().filter(|animal| pen.is_pen_for(animal.entity_type) && animal.pen == number).count();
+            if fillers < count {
+                match number {
+                    Some(number) => problems.push(format!("{} {:?} tiles numbered {} but only {} animals to fill them", count, pen, number, fillers)),
+                    None => problems.push(format!("{} {:?} tiles but only {} animals to fill them", count, pen, fillers)),
+                }
+            }
+        }
+    }
+    for animal in &animals {
+        let Some(number) = animal.pen else {
+            continue;
+        };
+        if !tiles.iter().any(|(location, tile_type)| tile_type.is_pen_for(animal.entity_type) && numbers.get(location) == Some(&number)) {
+            problems.push(format!("{:?} at ({}, {}) is numbered {} but no pen of theirs has that number", animal.entity_type, animal.location.x, animal.location.y, number));
+        }
+    }
+
+    return problems;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //A pig and its pen, which is a fine level on its own
+    fn board() -> Board {
+        let mut board = Board::new(4, 2);
+        board.set_tile(Tile { tile_type: TileType::PigPen, location: Location { x: 3, y: 0, z: 0 }, pen: None });
+        board.set_entity(EntityType::Pig, 0, 0);
+        return board;
+    }
+
+    fn problems(board: &Board) -> Vec<String> {
+        return check_save(&board.to_save());
+    }
+
+    fn mentions(problems: &Vec<String>, text: &str) -> bool {
+        return problems.iter().any(|problem| problem.contains(text));
+    }
+
+    #[test]
+    fn finds_nothing_wrong_with_a_good_level() {
+        assert_eq!(problems(&board()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn old_versions_that_still_load_are_fine() {
+        //A version 1 and a version 3 level, neither needs resaving
+        assert_eq!(check_file(Path::new("tests/fixtures/blank-v1.skb")), Vec::<String>::new());
+        assert_eq!(check_file(Path::new("assets/Levels/pig-tutorial-1.skb")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn missing_tiles() {
+        let mut save = board().to_save();
+        save.tiles.pop();
+        let problems = check_save(&save);
+        assert!(mentions(&problems, "4x2 level has 7 tiles, expected 8"));
+        assert!(mentions(&problems, "no tile at (3, 1)"));
+    }
+
+    #[test]
+    fn animals_stuck_where_they_stand() {
+        let mut board = board();
+        for (tile_type, x) in [(TileType::Fence, 1), (TileType::Ditch, 2), (TileType::Water, 0)] {
+            board.set_tile(Tile { tile_type, location: Location { x, y: 1, z: 0 }, pen: None });
+        }
+        board.set_entity(EntityType::Horse, 1, 1);
+        board.set_entity(EntityType::Duck, 2, 1);
+        board.set_entity(EntityType::Goat, 0, 1);
+        let problems = problems(&board);
+        assert!(mentions(&problems, "Horse on Fence at (1, 1)"));
+        assert!(mentions(&problems, "Goat on Water at (0, 1)"));
+        //Ducks float
+        assert!(!mentions(&problems, "Duck on"));
+    }
+
+    #[test]
+    fn pens_need_enough_animals() {
+        let mut board = board();
+        board.set_tile(Tile { tile_type: TileType::PigPen, location: Location { x: 3, y: 1, z: 0 }, pen: None });
+        board.set_tile(Tile { tile_type: TileType::Corral, location: Location { x: 2, y: 1, z: 0 }, pen: Some(1) });
+        let problems = problems(&board);
+        assert!(mentions(&problems, "2 PigPen tiles but only 1 animals to fill them"));
+        assert!(mentions(&problems, "1 Corral tiles numbered 1 but only 0 animals to fill them"));
+    }
+
+    #[test]
+    fn numbered_animals_need_a_pen_with_their_number() {
+        let mut board = board();
+        board.set_animal_pen(Some(2), 0, 0);
+        let problems = problems(&board);
+        assert!(mentions(&problems, "Pig at (0, 0) is numbered 2 but no pen of theirs has that number"));
+    }
+
+    #[test]
+    fn buttons_and_gates_come_in_pairs() {
+        let mut board = board();
+        board.set_entity(EntityType::Button(0), 1, 0);
+        board.set_tile(Tile { tile_type: TileType::Gate(1), location: Location { x: 1, y: 1, z: 0 }, pen: None });
+        let problems = problems(&board);
+        assert!(mentions(&problems, "button at (1, 0) has no gates on channel 0"));
+        assert!(mentions(&problems, "Gate(1) at (1, 1) has no button to flip it"));
+    }
+
+    #[test]
+    fn flags_need_someone_to_reach_them() {
+        let mut board = board();
+        board.set_flag(EntityType::FlagPig1.flag_index().unwrap(), 1, 0);
+        board.set_flag(EntityType::FlagSheep1.flag_index().unwrap(), 2, 0);
+        let problems = problems(&board);
+        assert!(!mentions(&problems, "FlagPig1"));
+        assert!(mentions(&problems, "FlagSheep1 at (2, 0) but there's no Sheep to reach it"));
+    }
+
+    #[test]
+    fn level_settings_that_make_no_sense() {
+        let mut save = board().to_save();
+        save.meta = Some(LevelMeta {
+            par: Some(5),
+            author_par: Some(6),
+            rules: Some(WeatherRules { mud_every: Some(0), sight: None, spook_every: None }),
+            ..Default::default()
+        });
+        let problems = check_save(&save);
+        assert!(mentions(&problems, "author par 6 is worse than par 5"));
+        assert!(mentions(&problems, "mud_every is 0, so it never happens"));
+        assert!(mentions(&problems, "mud spreads like rain but the weather is"));
+    }
+}
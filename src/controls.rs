@@ -0,0 +1,426 @@
+
+use bevy::prelude::*;
+use bevy::input::mouse::MouseMotion;
+use bevy::utils::HashMap;
+use bevy::window::PrimaryWindow;
+use bevy_pkv::PkvStore;
+use serde::{Deserialize, Serialize};
+use crate::*;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Serialize, Deserialize)]
+pub enum InputAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    PlaceFood,
+    Drop,
+    NextFood,
+    PrevFood,
+    Start,
+    Undo,
+    Redo,
+    Reload,
+    Pause,
+    Rotate,
+}
+
+impl InputAction {
+    pub fn all() -> Vec<InputAction> {
+        return vec![
+            InputAction::Up,
+            InputAction::Down,
+            InputAction::Left,
+            InputAction::Right,
+            InputAction::PlaceFood,
+            InputAction::Drop,
+            InputAction::NextFood,
+            InputAction::PrevFood,
+            InputAction::Start,
+            InputAction::Undo,
+            InputAction::Redo,
+            InputAction::Reload,
+            InputAction::Pause,
+            InputAction::Rotate,
+        ];
+    }
+
+    pub fn label(&self) -> &str {
+        return match self {
+            InputAction::Up => "Up",
+            InputAction::Down => "Down",
+            InputAction::Left => "Left",
+            InputAction::Right => "Right",
+            InputAction::PlaceFood => "Place/Pick Up",
+            InputAction::Drop => "Drop",
+            InputAction::NextFood => "Next Food",
+            InputAction::PrevFood => "Prev Food",
+            InputAction::Start => "Start",
+            InputAction::Undo => "Undo",
+            InputAction::Redo => "Redo",
+            InputAction::Reload => "Restart",
+            InputAction::Pause => "Pause",
+            InputAction::Rotate => "Rotate Tile",
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Ctrl(KeyCode),
+    CtrlShift(KeyCode),
+    Mouse(MouseButton),
+    Pad(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn label(&self) -> String {
+        return match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Ctrl(key) => format!("Ctrl+{:?}", key),
+            Binding::CtrlShift(key) => format!("Ctrl+Shift+{:?}", key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::Pad(button) => format!("Pad {:?}", button),
+        };
+    }
+}
+
+//Saved in the PkvStore under "controls", next to the medals
+#[derive(Resource)]
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone)]
+pub struct InputMap {
+    pub bindings: HashMap<InputAction, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = HashMap::from([
+            (InputAction::Up, vec![Binding::Key(KeyCode::Up), Binding::Key(KeyCode::W), Binding::Pad(GamepadButtonType::DPadUp)]),
+            (InputAction::Down, vec![Binding::Key(KeyCode::Down), Binding::Key(KeyCode::S), Binding::Pad(GamepadButtonType::DPadDown)]),
+            (InputAction::Left, vec![Binding::Key(KeyCode::Left), Binding::Key(KeyCode::A), Binding::Pad(GamepadButtonType::DPadLeft)]),
+            (InputAction::Right, vec![Binding::Key(KeyCode::Right), Binding::Key(KeyCode::D), Binding::Pad(GamepadButtonType::DPadRight)]),
+            (InputAction::PlaceFood, vec![Binding::Key(KeyCode::Space), Binding::Mouse(MouseButton::Left), Binding::Pad(GamepadButtonType::South)]),
+            (InputAction::Drop, vec![Binding::Key(KeyCode::X), Binding::Mouse(MouseButton::Right), Binding::Pad(GamepadButtonType::East)]),
+            (InputAction::NextFood, vec![Binding::Key(KeyCode::E), Binding::Pad(GamepadButtonType::RightTrigger)]),
+            (InputAction::PrevFood, vec![Binding::Key(KeyCode::Q), Binding::Pad(GamepadButtonType::LeftTrigger)]),
+            (InputAction::Start, vec![Binding::Key(KeyCode::Return), Binding::Pad(GamepadButtonType::North)]),
+            (InputAction::Undo, vec![Binding::Key(KeyCode::Z), Binding::Ctrl(KeyCode::Z), Binding::Pad(GamepadButtonType::West)]),
+            (InputAction::Redo, vec![Binding::Key(KeyCode::Y), Binding::Ctrl(KeyCode::Y), Binding::CtrlShift(KeyCode::Z), Binding::Pad(GamepadButtonType::RightTrigger2)]),
+            (InputAction::Reload, vec![Binding::Key(KeyCode::R), Binding::Pad(GamepadButtonType::Select)]),
+            (InputAction::Pause, vec![Binding::Key(KeyCode::Escape), Binding::Key(KeyCode::P), Binding::Pad(GamepadButtonType::Start)]),
+            (InputAction::Rotate, vec![Binding::Key(KeyCode::T), Binding::Pad(GamepadButtonType::RightThumb)]),
+        ]);
+        return InputMap { bindings };
+    }
+}
+
+impl InputMap {
+    //Actions missing from an older save keep their defaults
+    pub fn load(pkv: &PkvStore) -> Self {
+        let mut input_map = InputMap::default();
+        if let Ok(saved) = pkv.get::<InputMap>("controls") {
+            input_map.bindings.extend(saved.bindings);
+        }
+        return input_map;
+    }
+
+    //Mouse bindings are left out for the grid cursor, the mouse already points at its own tile
+    //A plain key doesn't fire while Ctrl is held, so Ctrl+Shift+Z doesn't also count as Z
+    pub fn just_pressed(&self, action: InputAction, keys: &Input<KeyCode>, mouse: Option<&Input<MouseButton>>, pad_buttons: &Input<GamepadButton>, gamepads: &Gamepads) -> bool {
+        let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        for binding in self.bindings.get(&action).into_iter().flatten() {
+            let pressed = match *binding {
+                Binding::Key(key) => keys.just_pressed(key) && (!ctrl || is_ctrl(key)),
+                Binding::Ctrl(key) => keys.just_pressed(key) && ctrl && !shift,
+                Binding::CtrlShift(key) => keys.just_pressed(key) && ctrl && shift,
+                Binding::Mouse(button) => mouse.map_or(false, |mouse| mouse.just_pressed(button)),
+                Binding::Pad(button_type) => gamepads.iter().any(|gamepad| pad_buttons.just_pressed(GamepadButton::new(gamepad, button_type))),
+            };
+            if pressed {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    //The mouse button mouse_controls uses for an action, falling back when none is bound
+    pub fn mouse_button(&self, action: InputAction, fallback: MouseButton) -> MouseButton {
+        for binding in self.bindings.get(&action).into_iter().flatten() {
+            if let Binding::Mouse(button) = binding {
+                return *button;
+            }
+        }
+        return fallback;
+    }
+
+    pub fn describe(&self, action: InputAction) -> String {
+        let bound: Vec<String> = self.bindings.get(&action).into_iter().flatten().map(|binding| binding.label()).collect();
+        return if bound.is_empty() {"Unbound".to_owned()} else {bound.join(", ")};
+    }
+
+    //A new binding replaces the old one from the same kind of device, so a key rebind keeps the gamepad button.
+    //Ctrl chords count as their own kind, so binding a plain key leaves Ctrl+Z and the like alone
+    pub fn rebind(&mut self, action: InputAction, binding: Binding) {
+        let bound = self.bindings.entry(action).or_default();
+        bound.retain(|old| std::mem::discriminant(old) != std::mem::discriminant(&binding));
+        bound.push(binding);
+    }
+}
+
+fn is_ctrl(key: KeyCode) -> bool {
+    return key == KeyCode::ControlLeft || key == KeyCode::ControlRight;
+}
+
+fn is_shift(key: KeyCode) -> bool {
+    return key == KeyCode::ShiftLeft || key == KeyCode::ShiftRight;
+}
+
+//Waits for the next key, mouse or gamepad press and binds it, Escape gives up
+pub fn rebind_system(
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    pad_buttons: Res<Input<GamepadButton>>,
+    mut settings_menu: ResMut<SettingsMenu>,
+    mut input_map: ResMut<InputMap>,
+    mut pkv: ResMut<PkvStore>,
+    mut reload_settings_menu: ResMut<ReloadSettingsMenu>,){
+    let Some(action) = settings_menu.listening else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        settings_menu.listening = None;
+        *reload_settings_menu = ReloadSettingsMenu(true);
+        return;
+    }
+    //Holding Ctrl (and Shift) while pressing a key binds the chord instead of the modifier itself
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let chord_key = keys.get_just_pressed().find(|key| !is_ctrl(**key) && !(ctrl && is_shift(**key)));
+    let binding = chord_key.map(|key| if !ctrl {Binding::Key(*key)} else if shift {Binding::CtrlShift(*key)} else {Binding::Ctrl(*key)})
+        .or(mouse.get_just_pressed().next().map(|button| Binding::Mouse(*button)))
+        .or(pad_buttons.get_just_pressed().next().map(|button| Binding::Pad(button.button_type)));
+    if let Some(binding) = binding {
+        input_map.rebind(action, binding);
+        pkv.set("controls", &input_map.to_owned()).expect("failed to store controls");
+        settings_menu.listening = None;
+        *reload_settings_menu = ReloadSettingsMenu(true);
+    }
+}
+
+//The tile picked with the keyboard or a gamepad, takes over from the mouse until the mouse moves again
+#[derive(Resource)]
+#[derive(Default)]
+pub struct GridCursor {
+    pub active: bool,
+    pub x: usize,
+    pub y: usize,
+    pub food: usize,
+}
+
+impl GridCursor {
+    //Window position of the tile, the same space window.cursor_position() is in
+    pub fn window_position(&self, field: &Field, window: &Window, scale: f32) -> Vec2 {
+        let snapped = field.view().to_play_area(self.x as f32, self.y as f32);
+        return Vec2 {
+            x: (snapped.x - TILE_OFFSET_X) * TILE_SIZE * scale + window.width()/2.0,
+            y: window.height()/2.0 - (snapped.y - TILE_OFFSET_Y) * TILE_SIZE * scale,
+        };
+    }
+}
+
+pub fn grid_cursor_controls(
+    mut commands: Commands,
+    sprites: Res<Sprites>,
+    (keys, pad_buttons, gamepads, input_map): (Res<Input<KeyCode>>, Res<Input<GamepadButton>>, Res<Gamepads>, Res<InputMap>),
+    (mut mouse_motion, mouse_buttons): (EventReader<MouseMotion>, Res<Input<MouseButton>>),
+    mut grid: ResMut<GridCursor>,
+    mut field: ResMut<Field>,
+    mut q_cursor: Query<&mut Cursor>,
+    mut q_transform: Query<&mut Transform>,
+    simulation: Res<SimulateRes>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut saving: ResMut<SaveRes>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,){
+    if mouse_motion.iter().count() > 0 || mouse_buttons.get_just_pressed().next().is_some() {
+        grid.active = false;
+    }
+    let pressed = |action| input_map.just_pressed(action, &keys, None, &pad_buttons, &gamepads);
+    let width = field.board.width;
+    let height = field.board.height;
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let mut moved = false;
+    for (action, dx, dy) in [(InputAction::Up, 0, 1), (InputAction::Down, 0, -1), (InputAction::Left, -1, 0), (InputAction::Right, 1, 0)] {
+        if pressed(action) {
+            //The first press just brings the cursor back where it was
+            if grid.active {
+                grid.x = (grid.x as i32 + dx).clamp(0, width as i32 - 1) as usize;
+                grid.y = (grid.y as i32 + dy).clamp(0, height as i32 - 1) as usize;
+            }
+            moved = true;
+        }
+    }
+    grid.x = grid.x.min(width - 1);
+    grid.y = grid.y.min(height - 1);
+
+    let Ok(mut cursor) = q_cursor.get_single_mut() else {
+        return;
+    };
+    if pressed(InputAction::NextFood) || pressed(InputAction::PrevFood) {
+        moved = true;
+        let next = pressed(InputAction::NextFood);
+        let buttons = get_buttons(&field);
+        //Empty handed, the first press picks the first food
+        let from = if cursor.holding == GameObjectType::None && next {buttons.len().saturating_sub(1)} else {grid.food};
+        for step in 1..=buttons.len() {
+            let index = if next {(from + step) % buttons.len()} else {(from + buttons.len() * step - step) % buttons.len()};
+            let (holding, painting) = match buttons[index] {
+                ButtonEffect::PickUp(GameObjectType::Entity(entity_type), limited) => {
+                    //Same rule as the food buttons, the tray has to have one left
+                    if limited && !field.board.refill_tray().contains(&entity_type) {
+                        continue;
+                    }
+                    (GameObjectType::Entity(entity_type), false)
+                }
+                ButtonEffect::Paint(paint_object) => (paint_object, true),
+                _ => {continue;}
+            };
+            grid.food = index;
+            cursor.holding = holding;
+            cursor.painting = painting;
+            //Placing rather than Holding, so the mouse won't drop it when it comes back
+            cursor.drag_drop = CursorState::Placing;
+            break;
+        }
+    }
+    //Editor only: turns the one-way gate or conveyor in hand, or else the one under the cursor
+    if pressed(InputAction::Rotate) && field.editor_mode && !simulation.simulating {
+        if let GameObjectType::Tile(tile_type) = cursor.holding {
+            cursor.holding = GameObjectType::Tile(tile_type.rotated());
+        } else if grid.active {
+            field.rotate_tile(&mut commands, &sprites, grid.x, grid.y);
+        }
+    }
+    if pressed(InputAction::Drop) {
+        moved = true;
+        cursor.holding = GameObjectType::None;
+        cursor.drag_drop = CursorState::Idle;
+    }
+    if pressed(InputAction::PlaceFood) && !simulation.simulating {
+        moved = true;
+        let (x, y) = (grid.x, grid.y);
+        match cursor.holding {
+            GameObjectType::Entity(entity) => {
+                if field.board.can_place_food(x, y) {
+                    field.set_entity(&mut commands, &sprites, entity, x, y);
+                    recorder.record(ReplayAction::Place { round: simulation.rounds, entity_type: entity, x, y });
+                    saving.redos = vec![];
+                    if !cursor.painting {cursor.holding = GameObjectType::None;}
+                }
+            }
+            GameObjectType::Tile(tile) => {
+                field.set_tile(&mut commands, &sprites, tile, x, y);
+                if !cursor.painting {cursor.holding = GameObjectType::None;}
+            }
+            GameObjectType::Pen(pen) => {
+                field.set_pen(&mut commands, &sprites, pen, x, y);
+                if !cursor.painting {cursor.holding = GameObjectType::None;}
+            }
+            GameObjectType::None => {
+                match field.get_entity_type(x, y) {
+                    Some(food @ (EntityType::ChickenFood | EntityType::HorseFood | EntityType::PigFood | EntityType::AllFood | EntityType::WagonFood | EntityType::DuckFood)) => {
+                        cursor.holding = GameObjectType::Entity(food);
+                        cursor.painting = false;
+                        cursor.drag_drop = CursorState::Placing;
+                        field.take_food(&mut commands, x, y);
+                        recorder.record(ReplayAction::PickUp { round: simulation.rounds, x, y });
+                        saving.redos = vec![];
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    if moved {
+        grid.active = true;
+    }
+
+    if grid.active {
+        let view = field.view();
+        if let Ok(mut transform) = q_transform.get_mut(field.cursor) {
+            let snapped = view.to_play_area(grid.x as f32, grid.y as f32);
+            let scale = q_windows.get_single().map_or(1.0, world_scale);
+            transform.scale = Vec3::splat(scale * view.zoom);
+            transform.translation = Vec3{ x: (snapped.x - TILE_OFFSET_X) * TILE_SIZE * scale, y: (snapped.y - TILE_OFFSET_Y) * TILE_SIZE * scale, z: 100.0 };
+        }
+    }
+}
+
+//Start, Undo, Redo, Reload and Pause, mirroring the buttons for them
+pub fn action_controls(
+    (keys, mouse, pad_buttons, gamepads, input_map): (Res<Input<KeyCode>>, Res<Input<MouseButton>>, Res<Input<GamepadButton>>, Res<Gamepads>, Res<InputMap>),
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut pause_menu_data: ResMut<PauseMenuData>,
+    mut simulating: ResMut<SimulateRes>,
+    mut saving: ResMut<SaveRes>,
+    mut recorder: ResMut<ReplayRecorder>,
+    replay_player: Res<ReplayPlayer>,
+    settings_menu: Res<SettingsMenu>,
+    mut round_counter_q: Query<&mut Text, With<RoundCounter>>,){
+    let pressed = |action| input_map.just_pressed(action, &keys, Some(&mouse), &pad_buttons, &gamepads);
+    let paused = *state.get() == GameState::Pause;
+    if settings_menu.page != SettingsPage::Closed {
+        return;
+    }
+    if paused {
+        //Typing a level name needs every key
+        if pause_menu_data.mode == PauseMenuMode::SaveAs {
+            return;
+        }
+        if pressed(InputAction::Pause) && (pause_menu_data.mode == PauseMenuMode::Pause || pause_menu_data.mode == PauseMenuMode::Editor) {
+            next_state.set(GameState::Gameplay);
+            return;
+        }
+    } else if pressed(InputAction::Pause) {
+        pause_menu_data.mode = PauseMenuMode::Pause;
+        next_state.set(GameState::Pause);
+        return;
+    }
+    if saving.saving != SaveStage::Idle {
+        return;
+    }
+    let ended = pause_menu_data.mode == PauseMenuMode::Win || pause_menu_data.mode == PauseMenuMode::Lose;
+    if paused && !ended {
+        return;
+    }
+    if pressed(InputAction::Start) && !paused && !simulating.simulating && !simulating.loss && !simulating.win && replay_player.replay.is_none() {
+        simulating.simulating = true;
+        simulating.rounds = simulating.rounds + 1;
+        recorder.record(ReplayAction::Start { round: simulating.rounds });
+        if let Ok(mut round_counter) = round_counter_q.get_single_mut() {
+            round_counter.sections[0].value = format!("Round {}", simulating.rounds);
+        }
+    } else if pressed(InputAction::Undo) && replay_player.replay.is_none() {
+        next_state.set(GameState::Gameplay);
+        simulating.loss = false;
+        simulating.win = false;
+        saving.saving = SaveStage::Undo;
+        recorder.record(ReplayAction::Undo { round: simulating.rounds });
+    } else if pressed(InputAction::Redo) && !paused && !simulating.simulating && !saving.redos.is_empty() && replay_player.replay.is_none() {
+        saving.saving = SaveStage::Redo;
+        recorder.record(ReplayAction::Redo { round: simulating.rounds });
+    } else if pressed(InputAction::Reload) {
+        next_state.set(GameState::Gameplay);
+        simulating.loss = false;
+        simulating.win = false;
+        saving.saving = SaveStage::Loading;
+    }
+}
@@ -1,29 +1,23 @@
 use crate::*;
 
 use bevy::ecs::component::TableStorage;
-use bevy::reflect::TypeUuid;
-use bevy::{prelude::*, reflect::TypePath};
-use bevy::audio::{Volume, VolumeLevel};
+use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 
+use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 
+use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub static ANIMATION_SPEED: f32 = 0.15;
 pub static TICK_SPEED: f32 = 0.2;
-
-#[derive(Serialize, Deserialize, Debug)]
-#[derive(TypePath)]
-#[derive(TypeUuid)]
-#[uuid = "71402ca5-adec-436a-ba16-6980791e7c7d"]
-pub struct SaveFile {
-    version: usize,
-    width: usize,
-    height: usize,
-    //Tile, Buttons, Food, Animals, Flags
-    tiles: Vec<(Option<Tile>, Option<GameEntity>, Option<GameEntity>, Option<GameEntity>, Option<Flag>)>,
-}
+pub static REPLAY_STEP: f32 = 0.4;
+pub static MIN_FIELD_SIZE: usize = 3;
+pub static MAX_FIELD_WIDTH: usize = 40;
+pub static MAX_FIELD_HEIGHT: usize = 24;
 
 #[derive(Resource)]
 #[derive(Default)]
@@ -52,6 +46,13 @@ struct FoodBundle {
     sprite: SpriteSheetBundle,
 }
 
+#[derive(Bundle)]
+struct PressurePlateBundle {
+    entity: GameEntity,
+    plate: PressurePlate,
+    sprite: SpriteSheetBundle,
+}
+
 #[derive(Bundle)]
 struct WagonBundle {
     entity: GameEntity,
@@ -73,20 +74,6 @@ struct FlagBundle {
     sprite: SpriteSheetBundle,
 }
 
-#[derive(Component)]
-#[derive(Serialize, Deserialize, Debug)]
-#[derive(Clone, Copy)]
-#[derive(Default)]
-pub struct GameEntity {
-    pub entity_type: EntityType,
-    pub location: Location,
-    pub target_location: Location,
-    pub offset: Vec2,
-    pub state: EntityState,
-    pub prev_state: Option<EntityState>,
-    pub last_direction: MoveDirection
-}
-
 #[derive(Component)]
 pub struct Animal;
 
@@ -94,158 +81,165 @@ pub struct Animal;
 pub struct Food;
 
 #[derive(Component)]
-pub struct Wagon;
+pub struct PressurePlate;
 
 #[derive(Component)]
-#[derive(Serialize, Deserialize, Debug)]
-#[derive(Clone, Copy)]
-pub struct Tile {
-    pub tile_type: TileType,
-    pub location: Location,
+pub struct Wagon;
+
+#[derive(Component, Deref, DerefMut)]
+pub struct PlayModeTick(Timer);
+
+//Collects the moves for the level being played, so it can be watched or shared afterwards
+#[derive(Resource)]
+#[derive(Default)]
+pub struct ReplayRecorder {
+    pub replay: Option<Replay>,
 }
 
-#[derive(Component)]
-#[derive(Serialize, Deserialize, Debug)]
-#[derive(Clone, Copy)]
-pub struct Flag {
-    pub location: Location,
-    pub index: usize
+impl ReplayRecorder {
+    pub fn record(&mut self, action: ReplayAction) {
+        if let Some(replay) = &mut self.replay {
+            replay.actions.push(action);
+        }
+    }
 }
 
-#[derive(Component, Deref, DerefMut)]
-pub struct PlayModeTick(Timer);
+#[derive(Resource)]
+pub struct ReplayPlayer {
+    pub replay: Option<Replay>,
+    pub next: usize,
+    pub speed: f32,
+    pub timer: Timer,
+}
+
+impl Default for ReplayPlayer {
+    fn default() -> Self {
+        return ReplayPlayer { replay: None, next: 0, speed: 1.0, timer: Timer::from_seconds(REPLAY_STEP, TimerMode::Repeating) };
+    }
+}
+
+impl ReplayPlayer {
+    pub fn play(&mut self, replay: Replay, saving: &mut SaveRes) {
+        saving.save = replay.level.to_owned();
+        saving.load_from = Some(replay.start.to_owned());
+        saving.saving = SaveStage::Loading;
+        self.replay = Some(replay);
+        self.next = 0;
+        self.timer.reset();
+    }
+}
 
 #[derive(Component, Deref, DerefMut)]
 pub struct AnimationTimer(pub Timer);
 
 #[derive(Component, Deref, DerefMut)]
-pub struct Effect(Timer);
+pub struct Effect(pub Timer);
+
+//Buttons and gates on the same channel share a colour
+pub fn channel_colour(channel: usize) -> Color {
+    return match channel {
+        0 => Color::rgb(0.95, 0.35, 0.3),
+        1 => Color::rgb(0.35, 0.55, 0.95),
+        2 => Color::rgb(0.95, 0.85, 0.3),
+        _ => Color::rgb(0.7, 0.4, 0.9),
+    };
+}
 
-#[derive(PartialEq)]
-#[derive(Clone, Copy)]
-#[derive(Serialize, Deserialize, Debug)]
-#[derive(Default)]
-pub enum EntityState {
-    #[default] Idle,
-    Walking,
-    Sliding,
-    Eating,
-    Celebrating,
-    Special,
-    Failure,
+pub fn channel_name(channel: usize) -> &'static str {
+    return match channel {
+        0 => "Red",
+        1 => "Blue",
+        2 => "Yellow",
+        _ => "Purple",
+    };
 }
 
-#[derive(PartialEq, Eq)]
-#[derive(Clone, Copy)]
-#[derive(PartialOrd, Ord)]
-#[derive(Default)]
-#[derive(Serialize, Deserialize, Debug)]
-pub enum EntityType {
-    Chicken,
-    Pig,
-    Horse,
-    Goat,
-    Wagon,
-    ChickenFood,
-    HorseFood,
-    PigFood,
-    AllFood,
-    WagonFood,
-    #[default] None,
-    FlagChicken1,
-    FlagChicken2,
-    FlagChicken3,
-    FlagChicken4,
-    FlagHorse1,
-    FlagHorse2,
-    FlagHorse3,
-    FlagHorse4,
-    FlagPig1,
-    FlagPig2,
-    FlagPig3,
-    FlagPig4,
-    FlagGoat1,
-    FlagGoat2,
-    FlagGoat3,
-    FlagGoat4,
-    FlagWagon1,
-    FlagWagon2,
-    FlagWagon3,
-    FlagWagon4,
-    Flag1,
-    Flag2,
-    Flag3,
-    Flag4,
+//Numbered animals only take on some of their pen's colour, so they still look like themselves
+pub fn pen_tint(pen: usize) -> Color {
+    let colour = channel_colour(pen);
+    return Color::rgb((1.0 + colour.r()) / 2.0, (1.0 + colour.g()) / 2.0, (1.0 + colour.b()) / 2.0);
+}
+
+//How the pieces and tiles from the library are drawn, which only the game needs to know
+pub trait EntitySprites {
+    fn texture_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas>;
+    fn texture_index(&self) -> usize;
+    fn icon_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas>;
+    fn icon_index(&self) -> usize;
+    fn tint(&self) -> Color;
 }
 
-impl EntityType {
-    pub fn texture_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas>{
+impl EntitySprites for EntityType {
+    fn texture_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas>{
         match self {
             EntityType::Chicken => sprites.sprites["Chicken"].clone(),
             EntityType::Pig => sprites.sprites["Pig"].clone(),
             EntityType::Horse => sprites.sprites["Horse"].clone(),
             EntityType::Goat => sprites.sprites["Goat"].clone(),
             EntityType::Wagon => sprites.sprites["Wagon"].clone(),
+            EntityType::Duck => sprites.sprites["Duck"].clone(),
+            EntityType::Sheepdog => sprites.sprites["Sheepdog"].clone(),
+            EntityType::Sheep => sprites.sprites["Sheep"].clone(),
             EntityType::ChickenFood => sprites.sprites["Food"].clone(),
             EntityType::PigFood => sprites.sprites["Food"].clone(),
             EntityType::HorseFood => sprites.sprites["Food"].clone(),
             EntityType::AllFood => sprites.sprites["Food"].clone(),
             EntityType::WagonFood => sprites.sprites["Food"].clone(),
+            EntityType::DuckFood => sprites.sprites["DuckFood"].clone(),
+            EntityType::Button(_) => sprites.sprites["TileIcons"].clone(),
+            _ if self.flag_index().is_some() => sprites.sprites["Flags"].clone(),
             _ => sprites.sprites["Chicken"].clone(),
         }
     }
-    pub fn texture_index(&self) -> usize{
+    fn texture_index(&self) -> usize{
         match self {
             EntityType::ChickenFood => 0,
             EntityType::HorseFood => 1,
             EntityType::PigFood => 2,
             EntityType::AllFood => 3,
             EntityType::WagonFood => 4,
-            _ => 0,
+            EntityType::Button(_) => 7,
+            _ => self.flag_index().map_or(0, |index| index * 4),
         }
     }
-    pub fn icon_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas>{
+    fn icon_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas>{
         match self {
             EntityType::Chicken => sprites.sprites["Chicken"].clone(),
             EntityType::Pig => sprites.sprites["Pig"].clone(),
             EntityType::Horse => sprites.sprites["Horse"].clone(),
             EntityType::Goat => sprites.sprites["Goat"].clone(),
             EntityType::Wagon => sprites.sprites["Wagon"].clone(),
+            EntityType::Duck => sprites.sprites["Duck"].clone(),
+            EntityType::Sheepdog => sprites.sprites["Sheepdog"].clone(),
+            EntityType::Sheep => sprites.sprites["Sheep"].clone(),
             EntityType::ChickenFood => sprites.sprites["Food"].clone(),
             EntityType::PigFood => sprites.sprites["Food"].clone(),
             EntityType::HorseFood => sprites.sprites["Food"].clone(),
             EntityType::AllFood => sprites.sprites["Food"].clone(),
             EntityType::WagonFood => sprites.sprites["Food"].clone(),
+            EntityType::DuckFood => sprites.sprites["DuckFood"].clone(),
+            EntityType::Button(_) => sprites.sprites["TileIcons"].clone(),
+            _ if self.flag_index().is_some() => sprites.sprites["Flags"].clone(),
             _ => sprites.sprites["Chicken"].clone(),
         }
     }
-    pub fn icon_index(&self) -> usize{
+    fn icon_index(&self) -> usize{
         match self {
             EntityType::ChickenFood => 0,
             EntityType::HorseFood => 1,
             EntityType::PigFood => 2,
             EntityType::AllFood => 3,
             EntityType::WagonFood => 4,
-            _ => 0,
+            EntityType::Button(_) => 7,
+            _ => self.flag_index().map_or(0, |index| index * 4),
+        }
+    }
+    fn tint(&self) -> Color {
+        match self {
+            EntityType::Button(channel) => channel_colour(*channel),
+            _ => Color::WHITE,
         }
     }
-}
-
-#[derive(Eq, PartialEq)]
-#[derive(Clone, Copy)]
-#[derive(Serialize, Deserialize, Debug)]
-pub enum TileType {
-    Grass,
-    Fence,
-    Rocks,
-    Mud,
-    MuddyRocks,
-    Ditch,
-    ChickenPen,
-    PigPen,
-    GoatPen,
-    HorsePen,
-    Corral,
 }
 
 pub struct TileData {
@@ -255,8 +249,31 @@ pub struct TileData {
     depth: f32,
 }
 
-impl TileType {
-    pub fn tile_data(&self, sprites: &Res<Sprites>, x: usize, y: usize) -> (TileData, Vec<TileData>) {
+pub trait DirectionSprites {
+    fn sprite_index(&self) -> usize;
+}
+
+impl DirectionSprites for MoveDirection {
+    //Directional sprite sheets go Right, Up, Left, Down
+    fn sprite_index(&self) -> usize {
+        return match self {
+            MoveDirection::Right | MoveDirection::None => 0,
+            MoveDirection::Up => 1,
+            MoveDirection::Left => 2,
+            MoveDirection::Down => 3,
+        };
+    }
+}
+
+pub trait TileSprites {
+    fn tile_data(&self, sprites: &Res<Sprites>, x: usize, y: usize) -> (TileData, Vec<TileData>);
+    fn icon_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas>;
+    fn icon_index(&self) -> usize;
+    fn tint(&self) -> Color;
+}
+
+impl TileSprites for TileType {
+    fn tile_data(&self, sprites: &Res<Sprites>, x: usize, y: usize) -> (TileData, Vec<TileData>) {
         let mut children = vec![];
         let texture_atlas;
         match self {
@@ -271,6 +288,13 @@ impl TileType {
             TileType::GoatPen => texture_atlas = sprites.sprites["Grass"].clone(),
             TileType::HorsePen => texture_atlas = sprites.sprites["Grass"].clone(),
             TileType::Corral => texture_atlas = sprites.sprites["Grass"].clone(),
+            TileType::DuckPen => texture_atlas = sprites.sprites["Grass"].clone(),
+            TileType::SheepPen => texture_atlas = sprites.sprites["Grass"].clone(),
+            TileType::Water => texture_atlas = sprites.sprites["Water"].clone(),
+            TileType::Ice => texture_atlas = sprites.sprites["Ice"].clone(),
+            TileType::CrackedIce => texture_atlas = sprites.sprites["CrackedIce"].clone(),
+            TileType::OneWay(_) | TileType::Conveyor(_) => texture_atlas = sprites.sprites["Grass"].clone(),
+            TileType::Gate(_) | TileType::OpenGate(_) => texture_atlas = sprites.sprites["Grass"].clone(),
         }
 
         let z = match self {
@@ -285,6 +309,13 @@ impl TileType {
             TileType::GoatPen => 0,
             TileType::HorsePen => 0,
             TileType::Corral => 0,
+            TileType::DuckPen => 0,
+            TileType::SheepPen => 0,
+            TileType::Water => 0,
+            TileType::Ice => 0,
+            TileType::CrackedIce => 0,
+            TileType::OneWay(_) | TileType::Conveyor(_) => 0,
+            TileType::Gate(_) | TileType::OpenGate(_) => 0,
         };
 
         match self {
@@ -328,6 +359,25 @@ impl TileType {
                     depth: 31.0,
                 });
             },
+            //A closed gate is a painted stretch of fence, an open one just leaves its post behind
+            TileType::Gate(channel) => {
+                for (index, depth) in [(4, 30.0), (1, 31.0), (0, 31.0), (3, 31.0), (2, 31.0)] {
+                    children.push(TileData {
+                        texture_atlas: sprites.sprites["Fence"].clone(),
+                        sprite: TextureAtlasSprite { color: channel_colour(*channel), ..TextureAtlasSprite::new(index) },
+                        z: 0,
+                        depth: depth,
+                    });
+                }
+            },
+            TileType::OpenGate(channel) => {
+                children.push(TileData {
+                    texture_atlas: sprites.sprites["Fence"].clone(),
+                    sprite: TextureAtlasSprite { color: channel_colour(*channel).with_a(0.6), ..TextureAtlasSprite::new(4) },
+                    z: 0,
+                    depth: 30.0,
+                });
+            },
             TileType::ChickenPen | TileType::PigPen | TileType::GoatPen | TileType::HorsePen | TileType::Corral => {
                 let offset = match self {
                     TileType::ChickenPen => 0,
@@ -356,6 +406,35 @@ impl TileType {
                     depth: 44.0,
                 });
             },
+            //Same three layers as the other pens, on their own sheets
+            TileType::DuckPen | TileType::SheepPen => {
+                let sheet = if *self == TileType::DuckPen {"DuckPen"} else {"SheepPen"};
+                for (index, depth) in [(0, 5.0), (1, 36.0), (2, 44.0)] {
+                    children.push(TileData {
+                        texture_atlas: sprites.sprites[sheet].clone(),
+                        sprite: TextureAtlasSprite::new(index),
+                        z: 0,
+                        depth: depth,
+                    });
+                }
+            },
+            TileType::OneWay(direction) => {
+                children.push(TileData {
+                    texture_atlas: sprites.sprites["OneWay"].clone(),
+                    sprite: TextureAtlasSprite::new(direction.sprite_index()),
+                    z: 0,
+                    depth: 31.0,
+                });
+            },
+            //Flat on the ground, under whoever is riding it
+            TileType::Conveyor(direction) => {
+                children.push(TileData {
+                    texture_atlas: sprites.sprites["Conveyor"].clone(),
+                    sprite: TextureAtlasSprite::new(direction.sprite_index()),
+                    z: 0,
+                    depth: 5.0,
+                });
+            },
             _ => {}
         }
 
@@ -368,7 +447,7 @@ impl TileType {
 
         return ret_val;
     }
-    pub fn icon_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas> {
+    fn icon_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas> {
         match self {
             TileType::Grass => sprites.sprites["TileIcons"].clone(),
             TileType::Fence => sprites.sprites["TileIcons"].clone(),
@@ -381,10 +460,15 @@ impl TileType {
             TileType::PigPen => sprites.sprites["TileIcons"].clone(),
             TileType::GoatPen => sprites.sprites["TileIcons"].clone(),
             TileType::Corral => sprites.sprites["TileIcons"].clone(),
+            TileType::Water | TileType::DuckPen => sprites.sprites["DuckIcons"].clone(),
+            TileType::SheepPen => sprites.sprites["SheepIcons"].clone(),
+            TileType::Ice | TileType::CrackedIce => sprites.sprites["IceIcons"].clone(),
+            TileType::OneWay(_) | TileType::Conveyor(_) => sprites.sprites["DirectionIcons"].clone(),
+            TileType::Gate(_) | TileType::OpenGate(_) => sprites.sprites["TileIcons"].clone(),
             _ => sprites.sprites["Chicken"].clone(),
         }
     }
-    pub fn icon_index(&self) -> usize{
+    fn icon_index(&self) -> usize{
         match self {
             TileType::Grass => 0,
             TileType::Mud => 1,
@@ -397,21 +481,23 @@ impl TileType {
             TileType::PigPen => 11,
             TileType::GoatPen => 12,
             TileType::Corral => 13,
+            TileType::Water => 0,
+            TileType::DuckPen => 1,
+            TileType::SheepPen => 0,
+            TileType::Ice => 0,
+            TileType::CrackedIce => 1,
+            TileType::OneWay(direction) => direction.sprite_index(),
+            TileType::Conveyor(direction) => 4 + direction.sprite_index(),
+            TileType::Gate(_) | TileType::OpenGate(_) => 5,
             _ => 0,
         }
     }
-}
-
-#[derive(PartialEq)]
-#[derive(Clone, Copy)]
-#[derive(Serialize, Deserialize, Debug)]
-#[derive(Default)]
-pub enum MoveDirection {
-    #[default] None,
-    Left,
-    Right,
-    Up,
-    Down
+    fn tint(&self) -> Color {
+        match self {
+            TileType::Gate(channel) | TileType::OpenGate(channel) => channel_colour(*channel),
+            _ => Color::WHITE,
+        }
+    }
 }
 
 #[derive(Component)]
@@ -420,16 +506,6 @@ pub struct Fence;
 #[derive(Component)]
 pub struct Ditch;
 
-#[derive(Component)]
-#[derive(Serialize, Deserialize, Debug)]
-#[derive(Clone, Copy)]
-#[derive(Default)]
-pub struct Location {
-    pub x: usize,
-    pub y: usize,
-    pub z: usize,
-}
-
 #[derive(Component)]
 #[derive(Serialize, Deserialize, Debug)]
 #[derive(Clone, Copy)]
@@ -440,19 +516,76 @@ pub struct Depth {
 
 #[derive(Resource)]
 pub struct Field {
-    //Tile, Buttons, Food, Animals, Flags
-    pub tiles: Vec<Vec<(Entity, Option<Entity>, Option<Entity>, Option<Entity>, Option<Entity>, Option<Entity>)>>,
+    //The rules live on the board, the sprites below just mirror it
+    pub board: Board,
+    //Tile, Flag
+    pub tiles: Vec<Vec<(Entity, Option<Entity>)>>,
+    //Board piece id -> sprite
+    pub pieces: HashMap<usize, Entity>,
     pub cursor: Entity,
     pub simulate_timer: PlayModeTick,
     pub editor_mode: bool,
     pub level_id: String,
     pub par: usize,
     pub author_par: usize,
+    pub meta: Option<LevelMeta>,
+}
+
+//Where the board sits inside the 14x8 play area, shrinking it when it would not fit
+#[derive(Clone, Copy)]
+pub struct BoardView {
+    pub zoom: f32,
+    pub shift: Vec2,
+}
+
+impl BoardView {
+    pub fn new(width: usize, height: usize) -> Self {
+        let zoom = (PLAY_AREA_W / width.max(1) as f32).min(PLAY_AREA_H / height.max(1) as f32).min(1.0);
+        return BoardView {
+            zoom,
+            shift: Vec2 {
+                x: ((PLAY_AREA_W - 1.0) - (width as f32 - 1.0) * zoom) / 2.0,
+                y: ((PLAY_AREA_H - 1.0) - (height as f32 - 1.0) * zoom) / 2.0,
+            },
+        };
+    }
+
+    //Board tile to the play area grid that TILE_OFFSET_X/Y are measured in
+    pub fn to_play_area(&self, x: f32, y: f32) -> Vec2 {
+        return Vec2 { x: x * self.zoom + self.shift.x, y: y * self.zoom + self.shift.y };
+    }
+
+    pub fn to_board(&self, position: Vec2) -> Vec2 {
+        return (position - self.shift) / self.zoom;
+    }
+}
+
+impl Default for BoardView {
+    fn default() -> Self {
+        return BoardView::new(PLAY_AREA_W as usize, PLAY_AREA_H as usize);
+    }
 }
 
 impl Field {
     pub fn new(commands: &mut Commands, sprites: &Res<Sprites>, width: usize, height: usize) -> Self
     {
+        let tiles = Field::spawn_grid(commands, sprites, width, height);
+
+        let cursor = commands.spawn(
+            SpriteSheetBundle {
+                texture_atlas: sprites.sprites["Cursor"].clone(),
+                sprite: TextureAtlasSprite::new(4),
+                ..default()
+            }).id();
+        let field = Field { board: Board::new(width, height), tiles, pieces: HashMap::new(), cursor, simulate_timer: PlayModeTick(Timer::from_seconds(TICK_SPEED, TimerMode::Repeating)), editor_mode: false, level_id: "".to_owned(), par: 0, author_par: 0, meta: None };
+        return field;
+    }
+
+    pub fn view(&self) -> BoardView {
+        return BoardView::new(self.board.width, self.board.height);
+    }
+
+    fn spawn_grid(commands: &mut Commands, sprites: &Res<Sprites>, width: usize, height: usize) -> Vec<Vec<(Entity, Option<Entity>)>> {
         let mut tiles = vec![];
         let mut x = 0;
         while x < width {
@@ -467,6 +600,7 @@ impl Field {
                                 y: y,
                                 z: 0,
                             },
+                            pen: None,
                         },
                         sprite: SpriteSheetBundle {
                             texture_atlas: sprites.sprites["Grass"].clone(),
@@ -474,258 +608,272 @@ impl Field {
                             ..default()
                         }
                     }
-                ).id(), None, None, None, None, None));
+                ).id(), None));
                 y += 1;
             }
             x += 1;
         }
+        return tiles;
+    }
 
-        let cursor = commands.spawn(
-            SpriteSheetBundle {
-                texture_atlas: sprites.sprites["Cursor"].clone(),
-                sprite: TextureAtlasSprite::new(4),
-                ..default()
-            }).id();
-        let field = Field { tiles, cursor, simulate_timer: PlayModeTick(Timer::from_seconds(TICK_SPEED, TimerMode::Repeating)), editor_mode: false, level_id: "".to_owned(), par: 0, author_par: 0 };
-        return field;
+    //Throws away every tile and piece and starts over with an empty board of the new size
+    fn rebuild(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, width: usize, height: usize){
+        for column in &self.tiles {
+            for (tile, flag) in column {
+                commands.entity(*tile).despawn_recursive();
+                if let Some(entity) = flag {
+                    commands.entity(*entity).despawn_recursive();
+                }
+            }
+        }
+        for (_, entity) in self.pieces.drain() {
+            commands.entity(entity).despawn_recursive();
+        }
+        self.tiles = Field::spawn_grid(commands, sprites, width, height);
+        let rules = self.board.rules;
+        let tray = self.board.tray.clone();
+        self.board = Board::new(width, height);
+        self.board.rules = rules;
+        self.board.tray = tray;
+    }
+
+    //Anything that falls off the new edges is lost
+    pub fn resize(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, width: usize, height: usize){
+        let width = width.clamp(MIN_FIELD_SIZE, MAX_FIELD_WIDTH);
+        let height = height.clamp(MIN_FIELD_SIZE, MAX_FIELD_HEIGHT);
+        let mut save = self.board.to_save();
+        save.width = width;
+        save.height = height;
+        self.load(commands, sprites, &save);
     }
 
     pub fn despawn_all(&self, commands: &mut Commands){
         commands.entity(self.cursor).despawn_recursive();
         for column in &self.tiles {
-            for (tile, layer0, layer1, layer2, layer3, layer4) in column {
+            for (tile, flag) in column {
                 commands.entity(*tile).despawn_recursive();
-                if let Some(entity) = layer0 {
-                    commands.entity(*entity).despawn_recursive();
-                }
-                if let Some(entity) = layer1 {
-                    commands.entity(*entity).despawn_recursive();
-                }
-                if let Some(entity) = layer2 {
-                    commands.entity(*entity).despawn_recursive();
-                }
-                if let Some(entity) = layer3 {
-                    commands.entity(*entity).despawn_recursive();
-                }
-                if let Some(entity) = layer4 {
+                if let Some(entity) = flag {
                     commands.entity(*entity).despawn_recursive();
                 }
             }
         }
+        for entity in self.pieces.values() {
+            commands.entity(*entity).despawn_recursive();
+        }
     }
 
     pub fn can_get_tile(&self, x: usize, y: usize) -> bool {
-        if x < self.tiles.len() && y < self.tiles[x].len() {
-            return true;
-        }
-        return false;
+        return self.board.can_get_tile(x, y);
+    }
+
+    pub fn get_tile_type(&self, x: usize, y: usize) -> Option<TileType> {
+        return self.board.get_tile_type(x, y);
+    }
+
+    pub fn get_entity_type(&self, x: usize, y: usize) -> Option<EntityType> {
+        return self.board.get_entity_type(x, y);
     }
 
-    pub fn get_tile_type(&self, x: usize, y: usize, q_tile: &Query<&Tile>) -> Option<TileType> {
+    pub fn set_tile(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, tile_type: TileType, x: usize, y: usize){
         if self.can_get_tile(x, y) {
-            if let Ok(tile) = q_tile.get(self.tiles[x][y].0) {
-                return Some(tile.tile_type);
-            }
+            let tile = self.spawn_tile(commands, sprites, tile_type, None, x, y);
+            self.board.set_tile(tile);
         }
-        return None;
     }
 
-    pub fn get_entity_type(&self, x: usize, y: usize, q_entity: &Query<&GameEntity>) -> Option<EntityType> {
-        if self.can_get_tile(x, y) {
-            if let Some(entity_object) = self.tiles[x][y].3 {
-                if let Ok(entity) = q_entity.get(entity_object) {
-                    return Some(entity.entity_type);
-                }
-            }else if let Some(entity_object) = self.tiles[x][y].2 {
-                if let Ok(entity) = q_entity.get(entity_object) {
-                    return Some(entity.entity_type);
+    fn spawn_tile(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, tile_type: TileType, pen: Option<usize>, x: usize, y: usize) -> Tile {
+        commands.entity(self.tiles[x][y].0).despawn_recursive();
+        let (tile_data, children) = tile_type.tile_data(sprites, x, y);
+        let tile_info = Tile { tile_type: tile_type,
+            location: Location { 
+                x: x,
+                y: y,
+                z: tile_data.z,
+            },
+            pen: pen,
+        };
+        let mut tile = commands.spawn((
+            TileBundle {
+                tile: tile_info,
+                sprite: SpriteSheetBundle {
+                    texture_atlas: tile_data.texture_atlas,
+                    sprite: tile_data.sprite,
+                    transform: Transform::from_xyz(-10000.0, -10000.0, -10000.0),
+                    ..default()
                 }
+            }, 
+        ));
+        if let TileType::Fence | TileType::Gate(_) = tile_type {tile.insert(Fence);}
+        for mut child_tile in children {
+            //A numbered pen's fence is painted in its colour
+            if let Some(pen) = pen {
+                child_tile.sprite.color = channel_colour(pen);
             }
+            tile.with_children(|parent| {
+                let mut child = parent.spawn((
+                    SpriteSheetBundle {
+                        texture_atlas: child_tile.texture_atlas,
+                        sprite: child_tile.sprite,
+                        ..default()
+                    },
+                    Depth { depth: child_tile.depth },
+                ));
+                if tile_type == TileType::Ditch {child.insert(Ditch);}
+            });
         }
-        return None;
+        self.tiles[x][y].0 = tile.id();
+        return tile_info;
     }
 
-    pub fn set_tile(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, tile_type: TileType, x: usize, y: usize){
+    pub fn set_entity(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, entity_type: EntityType, x: usize, y: usize){
+        if entity_type.flag_index().is_some() {
+            self.set_flag(commands, sprites, entity_type, x, y);
+            return;
+        }
         if self.can_get_tile(x, y) {
-            commands.entity(self.tiles[x][y].0).despawn_recursive();
-            let (tile_data, children) = tile_type.tile_data(sprites, x, y);
-            let mut tile = commands.spawn((
-                TileBundle {
-                    tile: Tile { tile_type: tile_type,
-                        location: Location { 
-                            x: x,
-                            y: y,
-                            z: tile_data.z,
-                        },
-                    },
-                    sprite: SpriteSheetBundle {
-                        texture_atlas: tile_data.texture_atlas,
-                        sprite: tile_data.sprite,
-                        transform: Transform::from_xyz(-10000.0, -10000.0, -10000.0),
-                        ..default()
-                    }
-                }, 
-            ));
-            if tile_type == TileType::Fence {tile.insert(Fence);}
-            for child_tile in children {
-                tile.with_children(|parent| {
-                    let mut child = parent.spawn((
-                        SpriteSheetBundle {
-                            texture_atlas: child_tile.texture_atlas,
-                            sprite: child_tile.sprite,
-                            ..default()
-                        },
-                        Depth { depth: child_tile.depth },
-                    ));
-                    if tile_type == TileType::Ditch {child.insert(Ditch);}
-                });
+            for id in self.board.set_entity(entity_type, x, y) {
+                self.despawn_piece(commands, id);
+            }
+            self.spawn_flag(commands, sprites, x, y);
+            let cell = self.board.cells[x][y];
+            let placed = if let EntityType::Button(_) = entity_type {cell.button} else {cell.animal.or(cell.food)};
+            if let Some(piece) = placed {
+                self.spawn_piece(commands, sprites, piece);
             }
-            self.tiles[x][y].0 = tile.id();
         }
     }
 
-    pub fn set_entity(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, entity_type: EntityType, x: usize, y: usize){
-        if self.can_get_tile(x, y) {
-            if let Some(old_entity) = self.tiles[x][y].2 {
-                commands.entity(old_entity).despawn_recursive();
-                self.tiles[x][y].2 = None;
+    //Painting a flag that's already down somewhere else puts down the next free number for that animal instead
+    pub fn set_flag(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, entity_type: EntityType, x: usize, y: usize){
+        let Some(index) = entity_type.flag_index() else {
+            return;
+        };
+        if !self.can_get_tile(x, y) {
+            return;
+        }
+        let taken: Vec<usize> = self.board.flags().iter().filter(|flag| flag.location.x != x || flag.location.y != y).map(|flag| flag.index).collect();
+        let first = index - index % 4;
+        let Some(index) = [index].into_iter().chain(first..first + 4).find(|index| !taken.contains(index)) else {
+            println!("All four {:?} flags are already down", FLAGS[first]);
+            return;
+        };
+        self.board.set_flag(index, x, y);
+        self.spawn_flag(commands, sprites, x, y);
+    }
+
+    //Turns a one-way gate or conveyor a quarter turn clockwise
+    pub fn rotate_tile(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, x: usize, y: usize){
+        if let Some(tile_type) = self.get_tile_type(x, y) {
+            if tile_type.rotated() != tile_type {
+                self.set_tile(commands, sprites, tile_type.rotated(), x, y);
+            }
+        }
+    }
+
+    //Numbers the animal on the tile, or if there isn't one the whole pen the tile is part of
+    pub fn set_pen(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, pen: usize, x: usize, y: usize){
+        if !self.can_get_tile(x, y) {
+            return;
+        }
+        let cell = self.board.cells[x][y];
+        if let Some(animal) = cell.animal {
+            if animal.pen != Some(pen) {
+                if let Some(piece) = self.board.set_animal_pen(Some(pen), x, y) {
+                    self.despawn_piece(commands, piece.id);
+                    self.spawn_piece(commands, sprites, piece);
+                }
             }
-            if let Some(old_entity) = self.tiles[x][y].3 {
-                commands.entity(old_entity).despawn_recursive();
-                self.tiles[x][y].3 = None;
+            return;
+        }
+        if cell.tile.pen != Some(pen) {
+            for tile in self.board.set_pen(Some(pen), x, y) {
+                self.spawn_tile(commands, sprites, tile.tile_type, tile.pen, tile.location.x, tile.location.y);
             }
-            if let Some(old_entity) = self.tiles[x][y].4 {
-                commands.entity(old_entity).despawn_recursive();
-                self.tiles[x][y].4 = None;
+        }
+    }
+
+    pub fn take_button(&mut self, commands: &mut Commands, x: usize, y: usize) -> Option<EntityType> {
+        let piece = self.board.take_button(x, y)?;
+        self.despawn_piece(commands, piece.id);
+        return Some(piece.entity_type);
+    }
+
+    pub fn take_flag(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, x: usize, y: usize) -> Option<EntityType> {
+        let flag = self.board.take_flag(x, y)?;
+        self.spawn_flag(commands, sprites, x, y);
+        return Some(flag.entity_type());
+    }
+
+    fn spawn_piece(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, piece: Piece){
+        let sprite = SpriteSheetBundle {
+            texture_atlas: piece.entity_type.texture_atlas(sprites),
+            sprite: TextureAtlasSprite { color: piece.pen.map_or(piece.entity_type.tint(), pen_tint), ..TextureAtlasSprite::new(piece.entity_type.texture_index()) },
+            transform: Transform::from_xyz(-10000.0, -10000.0, -10000.0),
+            ..default()
+        };
+        let entity = match piece.entity_type {
+            EntityType::Chicken | EntityType::Horse | EntityType::Pig | EntityType::Goat | EntityType::Duck | EntityType::Sheepdog | EntityType::Sheep => {
+                commands.spawn(
+                    AnimalBundle {
+                        entity: piece.to_entity(),
+                        animation_timer: AnimationTimer(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating)),
+                        animal: Animal,
+                        sprite,
+                    }
+                ).id()
             }
-            if let Some(old_entity) = self.tiles[x][y].5 {
-                commands.entity(old_entity).despawn_recursive();
-                self.tiles[x][y].5 = None;
+            EntityType::Wagon => {
+                commands.spawn(
+                    WagonBundle {
+                        entity: piece.to_entity(),
+                        animation_timer: AnimationTimer(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating)),
+                        wagon: Wagon,
+                        sprite,
+                    }
+                ).id()
             }
-            match entity_type {
-                EntityType::Chicken | EntityType::Horse | EntityType::Pig | EntityType::Goat => {
-                    self.tiles[x][y].3 = Some(commands.spawn(
-                        AnimalBundle {
-                            entity: GameEntity { 
-                                entity_type: entity_type,
-                                location: Location { 
-                                    x: x,
-                                    y: y,
-                                    z: 38 + if entity_type == EntityType::Chicken {1} else {0},
-                                },
-                                target_location: Location {x,y,z:0},
-                                offset: Vec2::splat(0.0),
-                                state: EntityState::Idle,
-                                last_direction: MoveDirection::None,
-                                ..default()
-                            },
-                            animation_timer: AnimationTimer(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating)),
-                            animal: Animal,
-                            sprite: SpriteSheetBundle {
-                                texture_atlas: sprites.sprites[
-                                    match entity_type {
-                                        EntityType::Chicken => {"Chicken"}
-                                        EntityType::Horse => {"Horse"}
-                                        EntityType::Pig => {"Pig"}
-                                        EntityType::Goat => {"Goat"}
-                                        _ => {"Chicken"}
-                                    }
-                                ].clone(),
-                                sprite: TextureAtlasSprite::new(0),
-                                transform: Transform::from_xyz(-10000.0, -10000.0, -10000.0),
-                                ..default()
-                            }
-                        }
-                    ).id());
-                }
-                EntityType::ChickenFood | EntityType::HorseFood | EntityType::PigFood | EntityType::AllFood | EntityType::WagonFood => {
-                    self.tiles[x][y].2 = Some(commands.spawn(
-                        FoodBundle {
-                            entity: GameEntity { 
-                                entity_type: entity_type,
-                                location: Location { 
-                                    x: x,
-                                    y: y,
-                                    z: 37,
-                                },
-                                target_location: Location {x,y,z:0},
-                                offset: Vec2::splat(0.0),
-                                state: EntityState::Idle,
-                                last_direction: MoveDirection::None,
-                                ..default()
-                            },
-                            food: Food,
-                            sprite: SpriteSheetBundle {
-                                texture_atlas: sprites.sprites["Food"].clone(),
-                                sprite: TextureAtlasSprite::new(
-                                    match entity_type {
-                                        EntityType::ChickenFood => {0}
-                                        EntityType::HorseFood => {1}
-                                        EntityType::PigFood => {2}
-                                        EntityType::AllFood => {3}
-                                        EntityType::WagonFood => {4}
-                                        _ => {3}
-                                    }
-                                ),
-                                transform: Transform::from_xyz(-10000.0, -10000.0, -10000.0),
-                                ..default()
-                            }
-                        }
-                    ).id());
-                }
-                EntityType::Wagon => {
-                    self.tiles[x][y].3 = Some(commands.spawn(
-                        WagonBundle {
-                            entity: GameEntity { 
-                                entity_type: entity_type,
-                                location: Location { 
-                                    x: x,
-                                    y: y,
-                                    z: 38,
-                                },
-                                target_location: Location {x,y,z:0},
-                                offset: Vec2::splat(0.0),
-                                state: EntityState::Idle,
-                                last_direction: MoveDirection::None,
-                                ..default()
-                            },
-                            animation_timer: AnimationTimer(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating)),
-                            wagon: Wagon,
-                            sprite: SpriteSheetBundle {
-                                texture_atlas: sprites.sprites["Wagon"].clone(),
-                                sprite: TextureAtlasSprite::new(0),
-                                transform: Transform::from_xyz(-10000.0, -10000.0, -10000.0),
-                                ..default()
-                            }
-                        }
-                    ).id());
-                }
-                _ => {
-                }
+            EntityType::Button(_) => {
+                commands.spawn(
+                    PressurePlateBundle {
+                        entity: piece.to_entity(),
+                        plate: PressurePlate,
+                        sprite,
+                    }
+                ).id()
             }
+            _ => {
+                commands.spawn(
+                    FoodBundle {
+                        entity: piece.to_entity(),
+                        food: Food,
+                        sprite,
+                    }
+                ).id()
+            }
+        };
+        self.pieces.insert(piece.id, entity);
+    }
+
+    pub fn despawn_piece(&mut self, commands: &mut Commands, id: usize){
+        if let Some(entity) = self.pieces.remove(&id) {
+            commands.entity(entity).despawn_recursive();
         }
     }
-    
-    pub fn set_flag(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, index: usize, x: usize, y: usize){
-        if self.can_get_tile(x, y) {
-            if let Some(old_entity) = self.tiles[x][y].5 {
-                commands.entity(old_entity).despawn_recursive();
-                self.tiles[x][y].5 = None;
-            }
-            self.tiles[x][y].5 = Some(commands.spawn(
+
+    //Brings the flag sprite on a tile in line with the board
+    pub fn spawn_flag(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, x: usize, y: usize){
+        if let Some(old_entity) = self.tiles[x][y].1 {
+            commands.entity(old_entity).despawn_recursive();
+            self.tiles[x][y].1 = None;
+        }
+        if let Some(flag) = self.board.cells[x][y].flag {
+            self.tiles[x][y].1 = Some(commands.spawn(
                 FlagBundle {
-                    flag: Flag { 
-                        location: Location { 
-                            x: x,
-                            y: y,
-                            z: 32,
-                        },
-                        index: index,
-                    },
+                    flag: flag,
                     animation_timer: AnimationTimer(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating)),
                     sprite: SpriteSheetBundle {
-                        texture_atlas: sprites.sprites["Flag"].clone(),
-                        sprite: TextureAtlasSprite::new(index * 4),
+                        texture_atlas: sprites.sprites["Flags"].clone(),
+                        sprite: TextureAtlasSprite::new(flag.index * 4),
                         transform: Transform::from_xyz(-10000.0, -10000.0, -10000.0),
                         ..default()
                     }
@@ -734,1018 +882,50 @@ impl Field {
         }
     }
 
-    pub fn can_see_food(&mut self, animal: GameEntity, entity_q: &Query<&GameEntity>, tile_q: &Query<&Tile>,) -> Location {
-        let animalx = animal.location.x;
-        let animaly = animal.location.y;
-        let mut x = animalx;
-        let mut y = animaly;
-        let mut best = (Location{x,y,z:0}, 999);
-        let canfly = animal.entity_type == EntityType::Chicken;
-        let mut fly = canfly;
-        x = animalx + 1;
-        y = animaly;
-        while self.can_get_tile(x, y){
-            let mut has_flown = false;
-            if let Ok(tile) = tile_q.get(self.tiles[x][y].0) {
-                if tile.tile_type == TileType::Fence {
-                    break;
-                }
-                if tile.tile_type == TileType::Ditch {
-                    if fly {
-                        has_flown = true;
-                    }else{
-                        break;
-                    }
-                }
-            }
-            if self.tiles[x][y].3.is_some() {
-                if fly {
-                    has_flown = true;
-                }else{
-                    break;
+    pub fn take_food(&mut self, commands: &mut Commands, x: usize, y: usize) -> Option<EntityType> {
+        let piece = self.board.take_food(x, y)?;
+        self.despawn_piece(commands, piece.id);
+        return Some(piece.entity_type);
+    }
+
+    pub fn take_animal(&mut self, commands: &mut Commands, x: usize, y: usize) -> Option<EntityType> {
+        let piece = self.board.take_animal(x, y)?;
+        self.despawn_piece(commands, piece.id);
+        return Some(piece.entity_type);
+    }
+
+    //Loads the save onto the board, then rebuilds every sprite from it
+    pub fn load(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, save: &SaveFile){
+        if save.width != self.board.width || save.height != self.board.height {
+            self.rebuild(commands, sprites, save.width, save.height);
+        }
+        self.board.load(save);
+        self.respawn(commands, sprites);
+    }
+
+    //Puts back a board kept for Undo or Redo, then rebuilds every sprite from it
+    pub fn restore(&mut self, commands: &mut Commands, sprites: &Res<Sprites>, board: &Board){
+        if board.width != self.board.width || board.height != self.board.height {
+            self.rebuild(commands, sprites, board.width, board.height);
+        }
+        self.board = board.clone();
+        self.respawn(commands, sprites);
+    }
+
+    fn respawn(&mut self, commands: &mut Commands, sprites: &Res<Sprites>){
+        for (_, entity) in self.pieces.drain() {
+            commands.entity(entity).despawn_recursive();
+        }
+        for x in 0..self.board.width {
+            for y in 0..self.board.height {
+                let cell = self.board.cells[x][y];
+                self.spawn_tile(commands, sprites, cell.tile.tile_type, cell.tile.pen, x, y);
+                self.spawn_flag(commands, sprites, x, y);
+                for piece in [cell.button, cell.food, cell.animal, cell.flying].into_iter().flatten() {
+                    self.spawn_piece(commands, sprites, piece);
                 }
             }
-            if self.likes_food_on_tile(animal, &entity_q, x, y) {
-                if let Some(entity_id) = self.tiles[x][y].2 {
-                    if let Ok(entity) = entity_q.get(entity_id) {
-                        if best.1 > x - animalx {best = (entity.location, x - animalx);}
-                        if best.1 == x - animalx {
-                            match self.get_entity_type(x, y, &entity_q) {
-                                Some(EntityType::AllFood) => {
-                                    if animal.entity_type == EntityType::Goat {
-                                        best = (entity.location, x - animalx);
-                                    }
-                                }
-                                _ => {
-                                    if animal.entity_type != EntityType::Goat {
-                                        best = (entity.location, x - animalx);
-                                    }
-                                }
-                            }
-                        }
-                        break;
-                    }
-                }
-            }
-            x = x + 1;
-            if has_flown {
-                fly = false;
-            } else {
-                fly = canfly;
-            }
-        }
-        x = animalx;
-        y = animaly + 1;
-        fly = canfly;
-        while self.can_get_tile(x, y){
-            let mut has_flown = false;
-            if let Ok(tile) = tile_q.get(self.tiles[x][y].0) {
-                if tile.tile_type == TileType::Fence {
-                    break;
-                }
-                if tile.tile_type == TileType::Ditch {
-                    if fly {
-                        has_flown = true;
-                    }else{
-                        break;
-                    }
-                }
-            }
-            if self.tiles[x][y].3.is_some() {
-                if fly {
-                    has_flown = true;
-                }else{
-                    break;
-                }
-            }
-            if self.likes_food_on_tile(animal, &entity_q, x, y) {
-                if let Some(entity_id) = self.tiles[x][y].2 {
-                    if let Ok(entity) = entity_q.get(entity_id) {
-                        if best.1 > y - animaly {best = (entity.location, y - animaly);}
-                        if best.1 == y - animaly {
-                            match self.get_entity_type(x, y, &entity_q) {
-                                Some(EntityType::AllFood) => {
-                                    if animal.entity_type == EntityType::Goat {
-                                        best = (entity.location, x - animalx);
-                                    }
-                                }
-                                _ => {
-                                    if animal.entity_type != EntityType::Goat {
-                                        best = (entity.location, x - animalx);
-                                    }
-                                }
-                            }
-                        }
-                        break;
-                    }
-                }
-            }
-            y = y + 1;
-            if has_flown {
-                fly = false;
-            } else {
-                fly = canfly;
-            }
-        }
-        if animalx > 0 {x = animalx - 1;}
-        y = animaly;
-        fly = canfly;
-        while self.can_get_tile(x, y){
-            let mut has_flown = false;
-            if let Ok(tile) = tile_q.get(self.tiles[x][y].0) {
-                if tile.tile_type == TileType::Fence {
-                    break;
-                }
-                if tile.tile_type == TileType::Ditch {
-                    if fly {
-                        has_flown = true;
-                    }else{
-                        break;
-                    }
-                }
-            }
-            if self.tiles[x][y].3.is_some() {
-                if fly {
-                    has_flown = true;
-                }else{
-                    break;
-                }
-            }
-            if self.likes_food_on_tile(animal, &entity_q, x, y) {
-                if let Some(entity_id) = self.tiles[x][y].2 {
-                    if let Ok(entity) = entity_q.get(entity_id) {
-                        if best.1 > animalx - x {best = (entity.location, animalx - x);}
-                        if best.1 == animalx - x {
-                            match self.get_entity_type(x, y, &entity_q) {
-                                Some(EntityType::AllFood) => {
-                                    if animal.entity_type == EntityType::Goat {
-                                        best = (entity.location, animalx - x);
-                                    }
-                                }
-                                _ => {
-                                    if animal.entity_type != EntityType::Goat {
-                                        best = (entity.location, animalx - x);
-                                    }
-                                }
-                            }
-                        }
-                        break;
-                    }
-                }
-            }
-            if x == 0 {break;}
-            x = x - 1;
-            if has_flown {
-                fly = false;
-            } else {
-                fly = canfly;
-            }
-        }
-        x = animalx;
-        if animaly > 0 {y = animaly - 1;}
-        fly = canfly;
-        while self.can_get_tile(x, y){
-            let mut has_flown = false;
-            if let Ok(tile) = tile_q.get(self.tiles[x][y].0) {
-                if tile.tile_type == TileType::Fence {
-                    break;
-                }
-                if tile.tile_type == TileType::Ditch {
-                    if fly {
-                        has_flown = true;
-                    }else{
-                        break;
-                    }
-                }
-            }
-            if self.tiles[x][y].3.is_some() {
-                if fly {
-                    has_flown = true;
-                }else{
-                    break;
-                }
-            }
-            if self.likes_food_on_tile(animal, &entity_q, x, y) {
-                if let Some(entity_id) = self.tiles[x][y].2 {
-                    if let Ok(entity) = entity_q.get(entity_id) {
-                        if best.1 > animaly - y {best = (entity.location, animaly - y);}
-                        if best.1 == animaly - y {
-                            match self.get_entity_type(x, y, &entity_q) {
-                                Some(EntityType::AllFood) => {
-                                    if animal.entity_type == EntityType::Goat {
-                                        best = (entity.location, x - animalx);
-                                    }
-                                }
-                                _ => {
-                                    if animal.entity_type != EntityType::Goat {
-                                        best = (entity.location, x - animalx);
-                                    }
-                                }
-                            }
-                        }
-                        break;
-                    }
-                }
-            }
-            if y == 0 {break;}
-            y = y - 1;
-            if has_flown {
-                fly = false;
-            } else {
-                fly = canfly;
-            }
-        }
-        return best.0;
-    }
-
-    pub fn likes_food_on_tile(&mut self, animal: GameEntity, entity_q: &Query<&GameEntity>, x: usize, y: usize) -> bool {
-        if self.can_get_tile(x, y) {
-            if let Some(entity_id) = self.tiles[x][y].2 {
-                if let Ok(entity) = entity_q.get(entity_id) {
-                    if (animal.entity_type != EntityType::Wagon && entity.entity_type == EntityType::AllFood) || entity.entity_type == match animal.entity_type {
-                        EntityType::Chicken => { EntityType::ChickenFood }
-                        EntityType::Pig => { EntityType::PigFood }
-                        EntityType::Horse => { EntityType::HorseFood }
-                        EntityType::Goat => { entity.entity_type }
-                        EntityType::Wagon => { EntityType::WagonFood }
-                        _ => { EntityType::AllFood }
-                    } {
-                        return true;
-                    }
-                }
-            }
-        }
-        return false;
-    }
-
-    pub fn get_entities(&mut self,
-        entity_q: &Query<&GameEntity>, 
-    ) -> Vec<GameEntity>{
-        let mut ret_val = vec![];
-        for column in &self.tiles {
-            for tile in column {
-                if let Some(entity_id) = tile.3 {
-                    if let Ok(entity) = entity_q.get(entity_id) {
-                        ret_val.push(entity.to_owned());
-                    }
-                }
-                if let Some(entity_id) = tile.4 {
-                    if let Ok(entity) = entity_q.get(entity_id) {
-                        ret_val.push(entity.to_owned());
-                    }
-                }
-            }
-        }
-        ret_val.sort_by_key(|e| e.entity_type);
-        return ret_val;
-    }
-
-    pub fn check_win(&mut self,
-        entity_q: &mut Query<&mut GameEntity>, 
-        tile_q: &Query<&Tile>, 
-    ) -> bool {
-        let mut ret_val = false;
-        for column in &self.tiles {
-            for tile in column {
-                if let Ok(tile_type) = tile_q.get(tile.0) {
-                    match tile_type.tile_type {
-                        TileType::ChickenPen => {
-                            if let Some(entity_id) = tile.3 {
-                                if let Ok(mut entity) = entity_q.get_mut(entity_id) {
-                                    if entity.entity_type == EntityType::Chicken {
-                                        ret_val = true;
-                                        entity.state = EntityState::Celebrating;
-                                        continue;
-                                    }
-                                }
-                            }
-                            return false;
-                        }
-                        TileType::HorsePen => {
-                            if let Some(entity_id) = tile.3 {
-                                if let Ok(mut entity) = entity_q.get_mut(entity_id) {
-                                    if entity.entity_type == EntityType::Horse {
-                                        ret_val = true;
-                                        entity.state = EntityState::Celebrating;
-                                        continue;
-                                    }
-                                }
-                            }
-                            return false;
-                        }
-                        TileType::PigPen => {
-                            if let Some(entity_id) = tile.3 {
-                                if let Ok(mut entity) = entity_q.get_mut(entity_id) {
-                                    if entity.entity_type == EntityType::Pig {
-                                        ret_val = true;
-                                        entity.state = EntityState::Celebrating;
-                                        continue;
-                                    }
-                                }
-                            }
-                            return false;
-                        }
-                        TileType::GoatPen => {
-                            if let Some(entity_id) = tile.3 {
-                                if let Ok(mut entity) = entity_q.get_mut(entity_id) {
-                                    if entity.entity_type == EntityType::Goat {
-                                        ret_val = true;
-                                        entity.state = EntityState::Celebrating;
-                                        continue;
-                                    }
-                                }
-                            }
-                            return false;
-                        }
-                        TileType::Corral => {
-                            if let Some(entity_id) = tile.3 {
-                                if let Ok(mut entity) = entity_q.get_mut(entity_id) {
-                                    if entity.entity_type == EntityType::Wagon {
-                                        ret_val = true;
-                                        entity.state = EntityState::Celebrating;
-                                        continue;
-                                    }
-                                }
-                            }
-                            return false;
-                        }
-                        _ => {}
-                    }
-                }
-            }
-        }
-        return ret_val;
-    }
-
-    pub fn slide_entity(&mut self,
-        commands: &mut Commands, 
-        entity_q: &mut Query<&mut GameEntity>, 
-        tile_q: &Query<&Tile>,
-        sounds: &Res<Sounds>,
-        sprites: &Res<Sprites>,
-        rng: &mut ResMut<GlobalEntropy<ChaCha8Rng>>,
-        entity: GameEntity,
-        slide_direction: MoveDirection) -> bool{
-
-        let startx = entity.location.x;
-        let starty = entity.location.y;
-
-        let (xoffset, yoffset) = match slide_direction {
-            MoveDirection::Left => {(-1, 0)}
-            MoveDirection::Right => {(1, 0)}
-            MoveDirection::Down => {(0, -1)}
-            MoveDirection::Up => {(0, 1)}
-            _ => {(0, 0)}
-        };
-
-        if (startx as isize) < -xoffset || (starty as isize) < -yoffset || !self.can_get_tile(((startx as isize) + xoffset) as usize, ((starty as isize) + yoffset) as usize) {
-            //SLID OUT OF BOUNDS
-            if entity.entity_type == EntityType::Chicken && entity.state == EntityState::Special {
-                return false;
-            }
-            if let Some(entity_id) = self.tiles[startx][starty].3 {
-                if let Ok(mut sliding_entity) = entity_q.get_mut(entity_id) {
-                    sliding_entity.state = EntityState::Idle;
-                }
-            }
-            return true;
-        }
-        let x: usize = ((startx as isize) + xoffset) as usize;
-        let y: usize = ((starty as isize) + yoffset) as usize;
-        return self.move_entity(commands, entity_q, tile_q, sounds, sprites, rng, entity, Location{x, y, z:0})
-    }
-
-    pub fn move_entity(&mut self,
-        commands: &mut Commands, 
-        entity_q: &mut Query<&mut GameEntity>, 
-        tile_q: &Query<&Tile>,
-        sounds: &Res<Sounds>,
-        sprites: &Res<Sprites>,
-        rng: &mut ResMut<GlobalEntropy<ChaCha8Rng>>,
-        entity: GameEntity, 
-        target_location: Location) -> bool{
-
-        let startx = entity.location.x;
-        let starty = entity.location.y;
-
-        let mut target = (target_location.x as isize, target_location.y as isize);
-        if (target.0 - (startx as isize)).abs() > (target.1 - (starty as isize)).abs() {target.1 = starty as isize;} else {target.0 = startx as isize;}
-
-        let (xoffset, yoffset): (isize, isize) = ((target.0 - (startx as isize)).signum(), (target.1 - (starty as isize)).signum());
-
-        let move_direction = 
-            if xoffset < 0 {
-                MoveDirection::Left
-            }else if xoffset > 0 {
-                MoveDirection::Right
-            }else if yoffset < 0 {
-                MoveDirection::Down
-            }else if yoffset > 0 {
-                MoveDirection::Up
-            }else{
-                MoveDirection::None
-            };
-        
-        println!("{:?}", move_direction);
-
-        if move_direction == MoveDirection::None || 
-            (
-                entity.state != EntityState::Sliding && 
-                !(entity.state == EntityState::Special && entity.entity_type == EntityType::Chicken) &&
-                !self.likes_food_on_tile(entity, &entity_q.to_readonly(), target_location.x, target_location.y)
-            ) {
-            if let Some(entity_id) = self.tiles[startx][starty].3 {
-                if let Ok(mut moving_entity) = entity_q.get_mut(entity_id) {
-                    moving_entity.state = EntityState::Idle;
-                    moving_entity.target_location = moving_entity.location;
-                }
-            }
-            return true;
-        }
-
-        if (startx as isize) < -xoffset || (starty as isize) < -yoffset {
-            if let Some(entity_id) = self.tiles[startx][starty].3 {
-                if let Ok(mut moving_entity) = entity_q.get_mut(entity_id) {
-                    moving_entity.state = EntityState::Idle;
-                }
-            }
-            return true;
-        }
-        let x: usize = ((startx as isize) + xoffset) as usize;
-        let y: usize = ((starty as isize) + yoffset) as usize;
-
-        let tile_in_front = (x as isize) >= -xoffset && (y as isize) >= -yoffset;
-        let frontx: usize = if tile_in_front {((x as isize) + xoffset) as usize} else {0};
-        let fronty: usize = if tile_in_front {((y as isize) + yoffset) as usize} else {0};
-
-        let tile_in_back = (startx as isize) >= xoffset && (starty as isize) >= yoffset;
-        let backx: usize = if tile_in_back {((startx as isize) - xoffset) as usize} else {0};
-        let backy: usize = if tile_in_back {((starty as isize) - yoffset) as usize} else {0};
-
-        //TODO: check entity id to make sure it matches up with entity
-        if self.can_get_tile(x, y) && self.can_get_tile(startx, starty) {
-            if entity.entity_type == EntityType::Chicken && entity.state == EntityState::Special {
-                if let Some(entity_id) = self.tiles[startx][starty].4 {
-                    if let Ok(mut moving_entity) = entity_q.get_mut(entity_id) {
-                        if let Ok(tile) = tile_q.get(self.tiles[x][y].0) {
-                            match tile.tile_type {
-                                TileType::Fence => {
-                                    return false;
-                                }
-                                TileType::Mud => {
-                                    moving_entity.state = EntityState::Sliding;
-                                }
-                                TileType::MuddyRocks => {
-                                    moving_entity.state = EntityState::Sliding;
-                                }
-                                TileType::Ditch => {
-                                    return false;
-                                }
-                                TileType::ChickenPen => {
-                                    moving_entity.state = EntityState::Celebrating;
-                                }
-                                _ => {
-                                    moving_entity.state = EntityState::Walking;
-                                }
-                            }
-                        }
-                        if entity.state != EntityState::Sliding {
-                            moving_entity.target_location = target_location;
-                            if moving_entity.target_location.x == moving_entity.location.x && moving_entity.target_location.y == moving_entity.location.y {
-                                moving_entity.state = EntityState::Idle;
-                            }
-                        }
-                        commands.spawn(AudioBundle {
-                            source: sounds.sounds[&format!("Chicken{}", (rng.next_u32() % 4) + 1)].to_owned(),
-                            settings: PlaybackSettings{
-                                mode: PlaybackMode::Despawn,
-                                ..default()
-                            },
-                            ..default()
-                        });
-    
-                        moving_entity.last_direction = move_direction;
-                        moving_entity.location.x = x;
-                        moving_entity.location.y = y;
-                        if !(startx == x && starty == y) {
-                            if self.tiles[x][y].3.is_some() {
-                                return false;
-                            }
-                            self.tiles[x][y].3 = self.tiles[startx][starty].4.to_owned();
-                            self.tiles[startx][starty].4 = None;
-                        } else {
-                            return false;
-                        }
-                    }
-                    if let Some(food_entity_id) = self.tiles[x][y].2 {
-                        let mut eating = false;
-                        if let Ok(food_entity) = entity_q.get(food_entity_id) {
-                            if food_entity.entity_type == EntityType::AllFood || food_entity.entity_type == EntityType::ChickenFood {
-                                commands.entity(food_entity_id).despawn_recursive();
-                                self.tiles[x][y].2 = None;
-                                eating = true;
-                            }
-                        }
-                        if let Ok(tile) = tile_q.get(self.tiles[x][y].0) {
-                            if eating && tile.tile_type != TileType::Mud && tile.tile_type != TileType::MuddyRocks {
-                                if let Ok(mut moving_entity) = entity_q.get_mut(entity_id) {
-                                    moving_entity.state = EntityState::Eating;
-                                }
-                            }
-                        }
-                    }
-                }
-            }else if let Some(entity_id) = self.tiles[startx][starty].3 {
-                if let Some(target_entity_id) = self.tiles[x][y].3 {
-                    if let Ok([mut entity, mut target_entity]) = entity_q.get_many_mut([entity_id, target_entity_id]) {
-                        //check for other animals in the way
-                        match target_entity.entity_type {
-                            EntityType::None => {
-
-                            }
-                            _ => {
-                                if entity.entity_type == EntityType::Chicken && entity.state != EntityState::Special {
-                                    entity.state = EntityState::Special;
-                                } else {
-                                    entity.state = EntityState::Idle;
-                                    if entity.entity_type == EntityType::Wagon {
-                                        if let Ok(tile) = tile_q.get(self.tiles[startx][starty].0) {
-                                            if tile.tile_type == TileType::MuddyRocks || tile.tile_type == TileType::Rocks {
-                                                return false;
-                                            }
-                                        }
-                                    }
-                                    if entity.entity_type == EntityType::Goat && target_entity.state != EntityState::Celebrating {
-                                        let tile_slam_target = (frontx as isize) > -xoffset || (fronty as isize) > -yoffset;
-                                        if tile_slam_target && 
-                                            self.can_get_tile(((frontx as isize) + xoffset) as usize, ((fronty as isize) + yoffset) as usize) {
-                                            let tile_slam_target_x = ((frontx as isize) + xoffset) as usize;
-                                            let tile_slam_target_y = ((fronty as isize) + yoffset) as usize;
-                                            if let Some(TileType::Fence) = self.get_tile_type(frontx, fronty, tile_q) {
-                                                return false;
-                                            }
-                                            if self.tiles[tile_slam_target_x][tile_slam_target_y].3.is_some() {
-                                                return false;
-                                            }else{
-                                                //SLAM
-                                                match self.get_tile_type(frontx, fronty, tile_q) {
-                                                    Some(TileType::Fence) | Some(TileType::Ditch) => {
-                                                        return false;
-                                                    }
-                                                    _ => {}
-                                                }
-                                                println!("SLAM");
-                                                commands.spawn(AudioBundle {
-                                                    source: sounds.sounds["GoatCrash"].to_owned(),
-                                                    settings: PlaybackSettings{
-                                                        mode: PlaybackMode::Despawn,
-                                                        ..default()
-                                                    },
-                                                    ..default()
-                                                });
-                                                //if entity.state != EntityState::Eating {
-                                                    entity.state = EntityState::Special;
-                                                //}
-                                                target_entity.last_direction = move_direction;
-                                                target_entity.location.x = tile_slam_target_x;
-                                                target_entity.location.y = tile_slam_target_y;
-                                                target_entity.target_location = target_entity.location;
-                                                target_entity.state = EntityState::Idle;
-                                                if let Ok(tile) = tile_q.get(self.tiles[tile_slam_target_x][tile_slam_target_y].0) {
-                                                    match tile.tile_type {
-                                                        TileType::Ditch | TileType::Fence => {
-                                                            return false;
-                                                        }
-                                                        TileType::Mud | TileType::MuddyRocks => {
-                                                            if target_entity.entity_type == EntityType::Pig {
-                                                                target_entity.state = EntityState::Idle;
-                                                            }else{
-                                                                target_entity.state = EntityState::Sliding;
-                                                            }
-                                                        }
-                                                        TileType::Rocks => {
-                                                            if target_entity.entity_type == EntityType::Wagon {
-                                                                return false;
-                                                            }
-                                                        }
-                                                        TileType::ChickenPen => {
-                                                            if target_entity.entity_type == EntityType::Chicken && target_entity.state != EntityState::Special {
-                                                                target_entity.state = EntityState::Celebrating;
-                                                            }
-                                                        }
-                                                        TileType::PigPen => {
-                                                            if target_entity.entity_type == EntityType::Pig {
-                                                                target_entity.state = EntityState::Celebrating;
-                                                            }
-                                                        }
-                                                        TileType::HorsePen => {
-                                                            if target_entity.entity_type == EntityType::Horse {
-                                                                target_entity.state = EntityState::Celebrating;
-                                                            }
-                                                        }
-                                                        TileType::GoatPen => {
-                                                            if target_entity.entity_type == EntityType::Goat {
-                                                                target_entity.state = EntityState::Celebrating;
-                                                            }
-                                                        }
-                                                        TileType::Corral => {
-                                                            if target_entity.entity_type == EntityType::Wagon {
-                                                                target_entity.state = EntityState::Celebrating;
-                                                            }
-                                                        }
-                                                        _ => {}
-                                                    }
-                                                }
-                                                self.tiles[tile_slam_target_x][tile_slam_target_y].3 = self.tiles[x][y].3.to_owned();
-                                                self.tiles[x][y].3 = None;
-                                            }
-                                        }
-                                    }
-                                    return true;
-                                }
-                            }
-                        }
-                    }
-                }
-                let mut eating = false;
-                if let Some(food_entity_id) = self.tiles[x][y].2 {
-                    if let Ok(food_entity) = entity_q.get(food_entity_id) {
-                        if entity.entity_type != EntityType::Wagon && food_entity.entity_type == EntityType::AllFood {
-                            commands.entity(food_entity_id).despawn_recursive();
-                            self.tiles[x][y].2 = None;
-                            eating = true;
-                        } else {
-                            match entity.entity_type {
-                                EntityType::Chicken => {
-                                    if food_entity.entity_type == EntityType::ChickenFood {
-                                        commands.entity(food_entity_id).despawn_recursive();
-                                        self.tiles[x][y].2 = None;
-                                        eating = true;
-                                    }
-                                }
-                                EntityType::Pig => {
-                                    if food_entity.entity_type == EntityType::PigFood {
-                                        commands.entity(food_entity_id).despawn_recursive();
-                                        self.tiles[x][y].2 = None;
-                                        eating = true;
-                                    }
-                                }
-                                EntityType::Horse => {
-                                    if food_entity.entity_type == EntityType::HorseFood {
-                                        commands.entity(food_entity_id).despawn_recursive();
-                                        self.tiles[x][y].2 = None;
-                                        eating = true;
-                                    }
-                                }
-                                EntityType::Goat => {
-                                    commands.entity(food_entity_id).despawn_recursive();
-                                    self.tiles[x][y].2 = None;
-                                    eating = true;
-                                }
-                                EntityType::Wagon => {
-                                    if food_entity.entity_type == EntityType::WagonFood {
-                                        commands.entity(food_entity_id).despawn_recursive();
-                                        self.tiles[x][y].2 = None;
-                                        eating = true;
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-                if let Ok(mut moving_entity) = entity_q.get_mut(entity_id) {
-                    if !(moving_entity.entity_type == EntityType::Chicken && moving_entity.state == EntityState::Special){
-                        if let Ok(tile) = tile_q.get(self.tiles[x][y].0) {
-                            match tile.tile_type {
-                                TileType::Fence => {
-                                    moving_entity.state = EntityState::Idle;
-                                    if entity.entity_type == EntityType::Wagon {
-                                        if let Ok(tile) = tile_q.get(self.tiles[startx][starty].0) {
-                                            if tile.tile_type == TileType::MuddyRocks || tile.tile_type == TileType::Rocks {
-                                                return false;
-                                            }
-                                        }
-                                    }
-                                    return true;
-                                }
-                                TileType::Mud => {
-                                    //set state as muddy
-                                    if moving_entity.entity_type == EntityType::Chicken && moving_entity.state == EntityState::Special {
-                                    }else if moving_entity.entity_type == EntityType::Pig {
-                                        moving_entity.state = EntityState::Walking;
-                                    }else{
-                                        moving_entity.state = EntityState::Sliding;
-                                    }
-                                }
-                                TileType::MuddyRocks => {
-                                    if moving_entity.entity_type == EntityType::Wagon {
-                                        return false;
-                                    }
-                                    //set state as muddy
-                                    if moving_entity.entity_type == EntityType::Chicken && moving_entity.state == EntityState::Special {
-                                    }else if moving_entity.entity_type == EntityType::Pig {
-                                        moving_entity.state = EntityState::Walking;
-                                    }else{
-                                        moving_entity.state = EntityState::Sliding;
-                                    }
-                                }
-                                TileType::Rocks => {
-                                    if moving_entity.entity_type == EntityType::Wagon {
-                                        return false;
-                                    }
-                                    moving_entity.state = EntityState::Walking;
-                                }
-                                TileType::Ditch => {
-                                    if moving_entity.entity_type == EntityType::Chicken && moving_entity.state != EntityState::Special {
-                                        moving_entity.state = EntityState::Special;
-                                    } else {
-                                        return false;
-                                    }
-                                }
-                                TileType::ChickenPen => {
-                                    if moving_entity.entity_type == EntityType::Chicken && moving_entity.state != EntityState::Special {
-                                        moving_entity.state = EntityState::Celebrating;
-                                    }else{
-                                        moving_entity.state = EntityState::Walking;
-                                    }
-                                }
-                                TileType::PigPen => {
-                                    if moving_entity.entity_type == EntityType::Pig {
-                                        moving_entity.state = EntityState::Celebrating;
-                                    }else{
-                                        moving_entity.state = EntityState::Walking;
-                                    }
-                                }
-                                TileType::HorsePen => {
-                                    if moving_entity.entity_type == EntityType::Horse {
-                                        moving_entity.state = EntityState::Celebrating;
-                                    }else{
-                                        moving_entity.state = EntityState::Walking;
-                                    }
-                                }
-                                TileType::GoatPen => {
-                                    if moving_entity.entity_type == EntityType::Goat {
-                                        moving_entity.state = EntityState::Celebrating;
-                                    }else{
-                                        moving_entity.state = EntityState::Walking;
-                                    }
-                                }
-                                TileType::Corral => {
-                                    if moving_entity.entity_type == EntityType::Wagon {
-                                        moving_entity.state = EntityState::Celebrating;
-                                    }else{
-                                        moving_entity.state = EntityState::Walking;
-                                    }
-                                }
-                                _ => {
-                                    moving_entity.state = EntityState::Walking;
-                                }
-                            }
-                            //self.set_entity(commands, &sprites, entity.entity_type, x, y);
-                            //self.set_entity(commands, &sprites, EntityType::None, entityx, entityy);
-                        }
-                        if entity.state != EntityState::Sliding {
-                            moving_entity.target_location = target_location;
-                            if moving_entity.target_location.x == moving_entity.location.x && moving_entity.target_location.y == moving_entity.location.y {
-                                moving_entity.state = EntityState::Idle;
-                            }
-                            if eating {
-                                moving_entity.state = EntityState::Eating;
-                            }
-                        }
-                    }
-
-                    if moving_entity.state == EntityState::Sliding{
-                        commands.spawn(AudioBundle {
-                            source: sounds.sounds[&format!("Mud{}", (rng.next_u32() % 4) + 1)].to_owned(),
-                            settings: PlaybackSettings{
-                                mode: PlaybackMode::Despawn,
-                                ..default()
-                            },
-                            ..default()
-                        });
-                        let mud_effect = commands.spawn((SpriteSheetBundle {
-                                texture_atlas: sprites.sprites["MuddySplash"].clone(),
-                                sprite: TextureAtlasSprite::new(0),
-                                transform: Transform::from_xyz(0.0, 0.0, 0.1),
-                                ..default()
-                            }, Effect(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating)),
-                            /*Scaling { position: Vec2{ x: 5.0, y: 5.0 } }*/)).id();
-                        commands.entity(entity_id).push_children(&[mud_effect]);
-                        
-                    }
-
-                    match entity.entity_type {
-                        EntityType::Chicken => {
-                            if moving_entity.state == EntityState::Special {
-                                commands.spawn(AudioBundle {
-                                    source: sounds.sounds[&format!("ChickenFly{}", (rng.next_u32() % 4) + 1)].to_owned(),
-                                    settings: PlaybackSettings{
-                                        mode: PlaybackMode::Despawn,
-                                        ..default()
-                                    },
-                                    ..default()
-                                });
-                            } else {
-                                commands.spawn(AudioBundle {
-                                    source: sounds.sounds[&format!("Chicken{}", (rng.next_u32() % 4) + 1)].to_owned(),
-                                    settings: PlaybackSettings{
-                                        mode: PlaybackMode::Despawn,
-                                        ..default()
-                                    },
-                                    ..default()
-                                });
-                            }
-                        }
-                        EntityType::Horse => {
-                            commands.spawn(AudioBundle {
-                                source: sounds.sounds[&format!("Horse{}", (rng.next_u32() % 4) + 1)].to_owned(),
-                                settings: PlaybackSettings{
-                                    mode: PlaybackMode::Despawn,
-                                    ..default()
-                                },
-                                ..default()
-                            });
-                        }
-                        EntityType::Pig => {
-                            commands.spawn(AudioBundle {
-                                source: sounds.sounds[&format!("Pig{}", (rng.next_u32() % 4) + 1)].to_owned(),
-                                settings: PlaybackSettings{
-                                    mode: PlaybackMode::Despawn,
-                                    ..default()
-                                },
-                                ..default()
-                            });
-                        }
-                        EntityType::Goat => {
-                            commands.spawn(AudioBundle {
-                                source: sounds.sounds[&format!("Goat{}", (rng.next_u32() % 4) + 1)].to_owned(),
-                                settings: PlaybackSettings{
-                                    mode: PlaybackMode::Despawn,
-                                    ..default()
-                                },
-                                ..default()
-                            });
-                        }
-                        EntityType::Wagon => {
-                            commands.spawn(AudioBundle {
-                                source: sounds.sounds[&format!("Cart{}", (rng.next_u32() % 3) + 1)].to_owned(),
-                                settings: PlaybackSettings{
-                                    mode: PlaybackMode::Despawn,
-                                    ..default()
-                                },
-                                ..default()
-                            });
-                        }
-                        _ => {}
-                    }
-
-                    moving_entity.last_direction = move_direction;
-                    moving_entity.location.x = x;
-                    moving_entity.location.y = y;
-                    if !(startx == x && starty == y) {
-                        if moving_entity.entity_type == EntityType::Chicken && moving_entity.state == EntityState::Special {
-                            if self.tiles[x][y].4.is_some() {
-                                return false;
-                            }
-                            self.tiles[x][y].4 = self.tiles[startx][starty].3.to_owned();
-                            self.tiles[startx][starty].3 = None;
-                        }else{
-                            self.tiles[x][y].3 = self.tiles[startx][starty].3.to_owned();
-                            self.tiles[startx][starty].3 = None;
-                        }
-                    } else {
-                        moving_entity.state = EntityState::Idle;
-                    }
-                }
-                if tile_in_front && self.can_get_tile(frontx, fronty) {
-                    if let Some(slam_entity_id) = self.tiles[frontx][fronty].3 {
-                        println!("CHECKING FOR SLAM");
-                        if let Ok([mut entity, mut slam_entity]) = entity_q.get_many_mut([entity_id, slam_entity_id]) {
-                            if entity.entity_type == EntityType::Goat && slam_entity.state != EntityState::Celebrating {
-                                println!("TRYING TO SLAM");
-                                let tile_slam_target = (frontx as isize) > -xoffset*2 || (fronty as isize) > -yoffset*2;
-                                if tile_slam_target && 
-                                self.can_get_tile(((frontx as isize) + xoffset*2) as usize, ((fronty as isize) + yoffset*2) as usize) {
-                                    let tile_slam_target_x = ((frontx as isize) + xoffset*2) as usize;
-                                    let tile_slam_target_y = ((fronty as isize) + yoffset*2) as usize;
-                                    if let Some(TileType::Fence) = self.get_tile_type(((frontx as isize) + xoffset) as usize, ((fronty as isize) + yoffset) as usize, tile_q) {
-                                        return false;
-                                    }
-                                    if self.tiles[tile_slam_target_x][tile_slam_target_y].3.is_some() {
-                                        return false;
-                                    }else{
-                                        //SLAM
-                                        match self.get_tile_type(frontx, fronty, tile_q) {
-                                            Some(TileType::Fence) | Some(TileType::Ditch) => {
-                                                return false;
-                                            }
-                                            _ => {}
-                                        }
-                                        println!("SLAM");
-                                        commands.spawn(AudioBundle {
-                                            source: sounds.sounds["GoatCrash"].to_owned(),
-                                            settings: PlaybackSettings{
-                                                mode: PlaybackMode::Despawn,
-                                                ..default()
-                                            },
-                                            ..default()
-                                        });
-                                        entity.state = EntityState::Special;
-                                        slam_entity.last_direction = move_direction;
-                                        slam_entity.location.x = tile_slam_target_x;
-                                        slam_entity.location.y = tile_slam_target_y;
-                                        slam_entity.target_location = slam_entity.location;
-                                        slam_entity.state = EntityState::Idle;
-                                        if let Ok(tile) = tile_q.get(self.tiles[tile_slam_target_x][tile_slam_target_y].0) {
-                                            match tile.tile_type {
-                                                TileType::Ditch | TileType::Fence => {
-                                                    return false;
-                                                }
-                                                TileType::Mud | TileType::MuddyRocks => {
-                                                    if slam_entity.entity_type == EntityType::Pig {
-                                                        slam_entity.state = EntityState::Idle;
-                                                    }else{
-                                                        slam_entity.state = EntityState::Sliding;
-                                                    }
-                                                }
-                                                TileType::Rocks => {
-                                                    if slam_entity.entity_type == EntityType::Wagon {
-                                                        return false;
-                                                    }
-                                                }
-                                                TileType::ChickenPen => {
-                                                    if slam_entity.entity_type == EntityType::Chicken && slam_entity.state != EntityState::Special {
-                                                        slam_entity.state = EntityState::Celebrating;
-                                                    }
-                                                }
-                                                TileType::PigPen => {
-                                                    if slam_entity.entity_type == EntityType::Pig {
-                                                        slam_entity.state = EntityState::Celebrating;
-                                                    }
-                                                }
-                                                TileType::HorsePen => {
-                                                    if slam_entity.entity_type == EntityType::Horse {
-                                                        slam_entity.state = EntityState::Celebrating;
-                                                    }
-                                                }
-                                                TileType::GoatPen => {
-                                                    if slam_entity.entity_type == EntityType::Goat {
-                                                        slam_entity.state = EntityState::Celebrating;
-                                                    }
-                                                }
-                                                TileType::Corral => {
-                                                    if slam_entity.entity_type == EntityType::Wagon {
-                                                        slam_entity.state = EntityState::Celebrating;
-                                                    }
-                                                }
-                                                _ => {}
-                                            }
-                                        }
-                                        self.tiles[tile_slam_target_x][tile_slam_target_y].3 = self.tiles[frontx][fronty].3.to_owned();
-                                        self.tiles[frontx][fronty].3 = None;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                if tile_in_back && self.can_get_tile(backx, backy) {
-                    if let Some(pull_entity_id) = self.tiles[backx][backy].3 {
-                        if let Ok([entity, mut pull_entity]) = entity_q.get_many_mut([entity_id, pull_entity_id]) {
-                            if entity.entity_type == EntityType::Horse && pull_entity.entity_type == EntityType::Wagon && pull_entity.state != EntityState::Celebrating {
-                                println!("TRYING TO PULL");
-                                if self.tiles[startx][starty].3.is_none() {
-                                    pull_entity.last_direction = move_direction;
-                                    pull_entity.location.x = startx;
-                                    pull_entity.location.y = starty;
-                                    self.tiles[startx][starty].3 = self.tiles[backx][backy].3.to_owned();
-                                    self.tiles[backx][backy].3 = None;
-                                    if let Ok(tile) = tile_q.get(self.tiles[startx][starty].0) {
-                                        if pull_entity.entity_type == EntityType::Wagon && tile.tile_type == TileType::Rocks {
-                                            return false;
-                                        }
-                                        if pull_entity.entity_type == EntityType::Wagon && tile.tile_type == TileType::Corral {
-                                            pull_entity.state = EntityState::Celebrating;
-                                        }
-                                        if tile.tile_type == TileType::Mud || tile.tile_type == TileType::MuddyRocks {
-                                            pull_entity.state = EntityState::Sliding;
-                                        }
-                                    }
-                                } else {
-                                    println!("PULL FAILED");
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        else if entity.entity_type == EntityType::Chicken && entity.state == EntityState::Special {
-            return false;
         }
-        return true;
     }
 }
 
@@ -1758,7 +938,7 @@ pub fn setup_level(mut commands: Commands, sprites: Res<Sprites>){
 pub fn fence_system(field: ResMut<Field>, fences: Query<(&Children, &Tile), With<Fence>>, mut fenceparts: Query<(&TextureAtlasSprite, &mut Visibility), Without<Fence>>){
     for (children, tile) in &fences {
         match tile.tile_type {
-            TileType::Fence => {
+            TileType::Fence | TileType::Gate(_) => {
                 for child in children {
                     if let Ok((sprite, mut visibility)) = fenceparts.get_mut(*child) {
                         let locx = tile.location.x;
@@ -1811,19 +991,19 @@ pub fn ditch_system(field: ResMut<Field>, mut ditches: Query<(&Parent, &mut Text
                     let locy = tile.location.y;
                     sprite.index = 0;
                     if locy > 0 {
-                        if let Some(TileType::Ditch) = field.get_tile_type(locx, locy - 1, &tile_q) {
+                        if let Some(TileType::Ditch) = field.get_tile_type(locx, locy - 1) {
                             sprite.index += 1;
                         }
                     }
-                    if let Some(TileType::Ditch) = field.get_tile_type(locx, locy + 1, &tile_q) {
+                    if let Some(TileType::Ditch) = field.get_tile_type(locx, locy + 1) {
                         sprite.index += 2;
                     }
                     if locx > 0 {
-                        if let Some(TileType::Ditch) = field.get_tile_type(locx - 1, locy, &tile_q) {
+                        if let Some(TileType::Ditch) = field.get_tile_type(locx - 1, locy) {
                             sprite.index += 4;
                         }
                     }
-                    if let Some(TileType::Ditch) = field.get_tile_type(locx + 1, locy, &tile_q) {
+                    if let Some(TileType::Ditch) = field.get_tile_type(locx + 1, locy) {
                         sprite.index += 8;
                     }
                 }
@@ -1838,46 +1018,53 @@ pub fn mouse_controls(
     sprites: Res<Sprites>,
     mut field: ResMut<Field>, 
     q_windows: Query<&Window, With<PrimaryWindow>>,
-    q_tile: Query<&Tile>,
-    q_entity: Query<&GameEntity>,
     mut q_cursor: Query<&mut Cursor>, 
     mut q_transform: Query<&mut Transform>,
     mut q_desc: Query<(&mut Text, &Description)>,
     simulation: Res<SimulateRes>,
-    buttons: Res<Input<MouseButton>>,
-    ui_scale: Res<UiScale>,){
+    mut recorder: ResMut<ReplayRecorder>,
+    mut saving: ResMut<SaveRes>,
+    grid: Res<GridCursor>,
+    input_map: Res<InputMap>,
+    buttons: Res<Input<MouseButton>>,){
+    if grid.active {
+        return;
+    }
+    let grab = input_map.mouse_button(InputAction::PlaceFood, MouseButton::Left);
+    let drop = input_map.mouse_button(InputAction::Drop, MouseButton::Right);
     if let Ok(window) = q_windows.get_single() {
         if let Some(position) = window.cursor_position() {
-            let tile = Vec2{ x: (position.x - window.width()/2.0) / TILE_SIZE / ui_scale.scale as f32, y: (window.height()/2.0 - position.y) / TILE_SIZE / ui_scale.scale as f32};
-            let tile_pos_x = (tile.x + TILE_OFFSET_X).round() as usize;
-            let tile_pos_y = (tile.y + TILE_OFFSET_Y).round() as usize;
+            let view = field.view();
+            let scale = world_scale(window);
+            let tile = Vec2{ x: (position.x - window.width()/2.0) / TILE_SIZE / scale, y: (window.height()/2.0 - position.y) / TILE_SIZE / scale};
+            let board_pos = view.to_board(Vec2{ x: tile.x + TILE_OFFSET_X, y: tile.y + TILE_OFFSET_Y }).round();
+            let tile_pos_x = board_pos.x as usize;
+            let tile_pos_y = board_pos.y as usize;
+            //Off the edge of the board, or down in the bottom bar
             let illegal_y_pos = 
-            if (tile.y + TILE_OFFSET_Y).round() < 0.0 {
+            if (tile.y + TILE_OFFSET_Y).round() < 0.0 || board_pos.x < 0.0 || board_pos.y < 0.0 {
                 true
             }else{false};
             if let Ok(mut cursor) = q_cursor.get_single_mut() {
                 if !simulation.simulating && !illegal_y_pos {
-                    if !(buttons.pressed(MouseButton::Left) || buttons.pressed(MouseButton::Right)) && (cursor.drag_drop == CursorState::Holding)
+                    if !(buttons.pressed(grab) || buttons.pressed(drop)) && (cursor.drag_drop == CursorState::Holding)
                     && (Vec2::distance(cursor.pos, cursor.starting_pos) < CURSOR_MIN_MOVE_DIST) {
                         cursor.drag_drop = CursorState::Placing
                     }
-                    if buttons.just_pressed(MouseButton::Right) {
+                    if buttons.just_pressed(drop) {
                         cursor.holding = GameObjectType::None;
                         cursor.drag_drop = CursorState::Idle;
                     }
-                    if ((!(buttons.pressed(MouseButton::Left) || buttons.pressed(MouseButton::Right)) && (cursor.drag_drop == CursorState::Holding)) || 
-                    (buttons.pressed(MouseButton::Left) && cursor.drag_drop == CursorState::Placing))
+                    if ((!(buttons.pressed(grab) || buttons.pressed(drop)) && (cursor.drag_drop == CursorState::Holding)) || 
+                    (buttons.pressed(grab) && cursor.drag_drop == CursorState::Placing))
                     && (Vec2::distance(cursor.pos, cursor.starting_pos) > CURSOR_MIN_MOVE_DIST) {
                         if field.can_get_tile(tile_pos_x, tile_pos_y) {
                             if let GameObjectType::Entity(entity) = cursor.holding {
-                                if field.get_entity_type(tile_pos_x, tile_pos_y, &q_entity) == None {
-                                    match field.get_tile_type(tile_pos_x, tile_pos_y, &q_tile) {
-                                        Some(TileType::Fence) | Some(TileType::Ditch) => {}
-                                        _ => {
-                                            field.set_entity(&mut commands, &sprites, entity, tile_pos_x, tile_pos_y);
-                                            if !cursor.painting {cursor.holding = GameObjectType::None;}
-                                        }
-                                    }
+                                if field.board.can_place_food(tile_pos_x, tile_pos_y) {
+                                    field.set_entity(&mut commands, &sprites, entity, tile_pos_x, tile_pos_y);
+                                    recorder.record(ReplayAction::Place { round: simulation.rounds, entity_type: entity, x: tile_pos_x, y: tile_pos_y });
+                                    saving.redos = vec![];
+                                    if !cursor.painting {cursor.holding = GameObjectType::None;}
                                 }
                             }
                             if field.can_get_tile(tile_pos_x, tile_pos_y) {
@@ -1885,33 +1072,42 @@ pub fn mouse_controls(
                                     field.set_tile(&mut commands, &sprites, tile, tile_pos_x, tile_pos_y);
                                     if !cursor.painting {cursor.holding = GameObjectType::None;}
                                 }
+                                if let GameObjectType::Pen(pen) = cursor.holding {
+                                    field.set_pen(&mut commands, &sprites, pen, tile_pos_x, tile_pos_y);
+                                    if !cursor.painting {cursor.holding = GameObjectType::None;}
+                                }
                             }
                         } else {
                             cursor.holding = GameObjectType::None;
                         }
                     }else if cursor.holding == GameObjectType::None {
-                        if buttons.just_pressed(MouseButton::Left) {
-                            let food = field.get_entity_type(tile_pos_x, tile_pos_y, &q_entity);
+                        if buttons.just_pressed(grab) {
+                            let food = field.get_entity_type(tile_pos_x, tile_pos_y);
                             match food {
-                                Some(EntityType::ChickenFood) | Some(EntityType::HorseFood) | Some(EntityType::PigFood) | Some(EntityType::AllFood) | Some(EntityType::WagonFood) => {
+                                Some(EntityType::ChickenFood) | Some(EntityType::HorseFood) | Some(EntityType::PigFood) | Some(EntityType::AllFood) | Some(EntityType::WagonFood) | Some(EntityType::DuckFood) => {
                                     cursor.holding = GameObjectType::Entity(food.unwrap());
-                                    if let Some(old_entity) = field.tiles[tile_pos_x][tile_pos_y].2 {
-                                        commands.entity(old_entity).despawn_recursive();
-                                        field.tiles[tile_pos_x][tile_pos_y].2 = None;
-                                    }
+                                    field.take_food(&mut commands, tile_pos_x, tile_pos_y);
+                                    recorder.record(ReplayAction::PickUp { round: simulation.rounds, x: tile_pos_x, y: tile_pos_y });
+                                    saving.redos = vec![];
                                     cursor.starting_pos = cursor.pos;
                                     cursor.drag_drop = CursorState::Holding;
                                 }
-                                None => {}
+                                None => {
+                                    if field.editor_mode {
+                                        let picked = field.take_flag(&mut commands, &sprites, tile_pos_x, tile_pos_y).or_else(|| field.take_button(&mut commands, tile_pos_x, tile_pos_y));
+                                        if let Some(picked) = picked {
+                                            cursor.holding = GameObjectType::Entity(picked);
+                                            cursor.starting_pos = cursor.pos;
+                                            cursor.drag_drop = CursorState::Holding;
+                                            cursor.painting = false;
+                                        }
+                                    }
+                                }
                                 _ => {
                                     if field.editor_mode {
                                         cursor.holding = GameObjectType::Entity(food.unwrap());
-                                        if let Some(old_entity) = field.tiles[tile_pos_x][tile_pos_y].3 {
-                                            commands.entity(old_entity).despawn_recursive();
-                                            field.tiles[tile_pos_x][tile_pos_y].3 = None;
-                                        }else if let Some(old_entity) = field.tiles[tile_pos_x][tile_pos_y].2 {
-                                            commands.entity(old_entity).despawn_recursive();
-                                            field.tiles[tile_pos_x][tile_pos_y].2 = None;
+                                        if field.take_animal(&mut commands, tile_pos_x, tile_pos_y).is_none() {
+                                            field.take_food(&mut commands, tile_pos_x, tile_pos_y);
                                         }
                                         cursor.starting_pos = cursor.pos;
                                         cursor.drag_drop = CursorState::Holding;
@@ -1929,7 +1125,7 @@ pub fn mouse_controls(
                 for (mut desc, part) in &mut q_desc {
                     match part.part {
                         0 => {
-                            if let Some(entity) = field.get_entity_type(tile_pos_x, tile_pos_y, &q_entity){
+                            if let Some(entity) = field.get_entity_type(tile_pos_x, tile_pos_y){
                                 desc.sections[0].value = 
                                 match entity {
                                     EntityType::Chicken => {"Chicken: Can fly over obstacles!"}
@@ -1937,28 +1133,55 @@ pub fn mouse_controls(
                                     EntityType::Horse => {"Horse: Can Pull carts by walking away from them!"}
                                     EntityType::Goat => {"Goat: Can SLAM animals and carts over all sorts of things!"}
                                     EntityType::Wagon => {"Cart: Help the cart get to its goal!"}
+                                    EntityType::Duck => {"Duck: Swims across Water and Ditches, but waddles slowly through Mud."}
+                                    EntityType::Sheepdog => {"Sheepdog: Doesn't eat. Runs up to the nearest animal it can see and nudges it one tile away!"}
+                                    EntityType::Sheep => {"Sheep: Sticks with its flock. Every Sheep it touches moves with it towards Mixed Food any of them can see."}
                                     EntityType::ChickenFood => {"Seeds: Chickens prefer to eat these, and Goats will eat it."}
                                     EntityType::HorseFood => {"Apples: Horses prefer to eat these, and Goats will eat it"}
                                     EntityType::PigFood => {"Carrots: Pigs prefer to eat these, and Goats will eat it"}
                                     EntityType::AllFood => {"Mixed Food: Goats prefer to eat this, but any Animal will eat it."}
                                     EntityType::WagonFood => {"Cart Chow: Carts will... eat it?????"}
+                                    EntityType::DuckFood => {"Bread: Ducks prefer to eat this, and Goats will eat it."}
                                     _ => {""}
                                 }.to_owned();
+                                if let Some(pen) = field.board.cells[tile_pos_x][tile_pos_y].animal.and_then(|animal| animal.pen) {
+                                    desc.sections[0].value += &format!(" This one belongs in the {} Pen.", channel_name(pen));
+                                }
+                            }else if let Some(EntityType::Button(_)) = field.board.cells[tile_pos_x][tile_pos_y].button.map(|button| button.entity_type) {
+                                desc.sections[0].value = "Button: Stepping on it flips every gate of the same colour!".to_owned();
+                            }else if let Some(flag) = field.board.cells[tile_pos_x][tile_pos_y].flag {
+                                desc.sections[0].value = match flag.species() {
+                                    Some(EntityType::Wagon) => format!("Flag {} (Cart): Waypoint. The Cart has to pass its flags in order before the Corral counts!", flag.order() + 1),
+                                    Some(species) => format!("Flag {} ({:?}): Waypoint. The {:?} has to pass its flags in order before its Pen counts!", flag.order() + 1, species, species),
+                                    None => format!("Flag {}: Waypoint. Any animal can take these, but they have to go in order!", flag.order() + 1),
+                                };
                             }else{
                                 desc.sections[0].value = 
-                                match field.get_tile_type(tile_pos_x, tile_pos_y, &q_tile) {
+                                match Some(field.board.cells[tile_pos_x][tile_pos_y].tile.tile_type) {
                                     Some(TileType::Fence) => {"Fence: Impassible. Keeps everything in, no matter what!"}
+                                    Some(TileType::Gate(_)) => {"Gate (Closed): Like a Fence, until a Button of its colour opens it."}
+                                    Some(TileType::OpenGate(_)) => {"Gate (Open): Anyone can walk through, until a Button of its colour shuts it."}
                                     Some(TileType::Mud) => {"Mud: Slippery. Things can't stop here!"}
                                     Some(TileType::Rocks) => {"Rocks: Dangerous. Carts break on the rocks!"}
                                     Some(TileType::MuddyRocks) => {"Muddy Rocks: Slippery AND Dangerous! Uh oh!"}
-                                    Some(TileType::Ditch) => {"Ditches: Dangerous. It's too deep for Animals and Carts!"}
+                                    Some(TileType::Ditch) => {"Ditches: Dangerous. It's too deep for Animals and Carts, but Ducks can swim across!"}
                                     Some(TileType::ChickenPen) => {"Pen (Chicken): Goal. A comfortable coop for the Chicken!"}
                                     Some(TileType::HorsePen) => {"Pen (Horse): Goal. A nice stable for the Horse."}
                                     Some(TileType::PigPen) => {"Pen (Pig): Goal. The Pig loves the Mud here."}
                                     Some(TileType::GoatPen) => {"Pen (Goat): Goal. The Fences are extra sturdy for the Goat."}
                                     Some(TileType::Corral) => {"Pen (Cart): Goal. A place for Cart maintenance and upkeep."}
+                                    Some(TileType::DuckPen) => {"Pen (Duck): Goal. The Duck's very own pond."}
+                                    Some(TileType::SheepPen) => {"Pen (Sheep): Goal. Room enough for the whole flock."}
+                                    Some(TileType::Water) => {"Water: Only Ducks can swim. Everyone else stops at the edge!"}
+                                    Some(TileType::Ice) => {"Ice: Everyone slides, even Pigs! It cracks when an animal steps off."}
+                                    Some(TileType::CrackedIce) => {"Cracked Ice: The next animal to step off it leaves only Water behind."}
+                                    Some(TileType::OneWay(_)) => {"One-Way Gate: Animals can only go through the way the arrow points."}
+                                    Some(TileType::Conveyor(_)) => {"Conveyor: Carries anyone standing on it, and any food on it, a tile the way the arrow points, every turn."}
                                     _ => {""}
                                 }.to_owned();
+                                if let Some(pen) = field.board.cells[tile_pos_x][tile_pos_y].tile.pen {
+                                    desc.sections[0].value += &format!(" Only the {} one counts here.", channel_name(pen));
+                                }
                             }
                         }
                         _ => {}
@@ -1966,8 +1189,9 @@ pub fn mouse_controls(
                 }
                     
                 if let Ok(mut cursor) = q_transform.get_mut(field.cursor) {
-                    cursor.scale = Vec3::splat(ui_scale.scale as f32);
-                    cursor.translation = Vec3{ x: (tile.x.floor() + 0.5) * TILE_SIZE * cursor.scale.x, y: tile.y.round() * TILE_SIZE * cursor.scale.y, z: 100.0 };
+                    let snapped = view.to_play_area(board_pos.x, board_pos.y);
+                    cursor.scale = Vec3::splat(scale * view.zoom);
+                    cursor.translation = Vec3{ x: (snapped.x - TILE_OFFSET_X) * TILE_SIZE * scale, y: (snapped.y - TILE_OFFSET_Y) * TILE_SIZE * scale, z: 100.0 };
                 }
             }
         }
@@ -1977,19 +1201,20 @@ pub fn mouse_controls(
 pub fn saving_system(
     mut commands: Commands, 
     sprites: Res<Sprites>,
-    music: Res<GameMusic>,
+    mut audio: ResMut<AudioManager>,
     levels: Res<Levels>,
     savefiles: Res<Assets<SaveFile>>,
     mut field: ResMut<Field>, 
-    q_tile: Query<&Tile>,
-    q_entity: Query<&GameEntity>,
-    q_flag: Query<&Flag>, 
     mut q_cursor: Query<&mut Cursor>, 
-    music_player: Query<Entity, With<MusicPlayer>>,    
     mut simulation: ResMut<SimulateRes>,
     mut saving: ResMut<SaveRes>,
     mut round_counter_q: Query<&mut Text, With<RoundCounter>>,
-    mut weather: ResMut<Weather>){
+    mut reload_pause_menu: ResMut<ReloadPauseMenu>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut replay_player: ResMut<ReplayPlayer>,
+    settings: Res<Settings>,
+    mut weather: ResMut<Weather>,
+    mut reload_game_ui: ResMut<ReloadGameUI>){
     if !simulation.simulating {
         match saving.saving {
             SaveStage::Saving => {
@@ -1997,49 +1222,22 @@ pub fn saving_system(
                     println!("You FOOL! There is no level to save!");
                     return;
                 }
-                let mut save = SaveFile { version: 3, width: field.tiles.len(), height: field.tiles[0].len(), tiles: vec![] };
-
-                let mut y = 0;
-                while y < save.height {
-                    let mut x = 0;
-                    while x < save.width {
-                        let mut save_tile: Option<Tile> = None;
-                        let mut save_entity_1: Option<GameEntity> = None;
-                        let mut save_entity_2: Option<GameEntity> = None;
-                        let mut save_entity_3: Option<GameEntity> = None;
-                        let mut save_entity_4: Option<Flag> = None;
-                        if let Ok(tile) = q_tile.get(field.tiles[x][y].0) {
-                            save_tile = Some(tile.clone());
-                        }
-                        if let Some(entity_id) = field.tiles[x][y].1 {
-                            if let Ok(entity) = q_entity.get(entity_id) {
-                                save_entity_1 = Some(entity.clone());
-                            }
-                        }
-                        if let Some(entity_id) = field.tiles[x][y].2 {
-                            if let Ok(entity) = q_entity.get(entity_id) {
-                                save_entity_2 = Some(entity.clone());
-                            }
-                        }
-                        if let Some(entity_id) = field.tiles[x][y].3 {
-                            if let Ok(entity) = q_entity.get(entity_id) {
-                                save_entity_3 = Some(entity.clone());
-                            }
-                        }
-                        if let Some(entity_id) = field.tiles[x][y].4 {
-                            if let Ok(entity) = q_flag.get(entity_id) {
-                                save_entity_4 = Some(entity.clone());
-                            }
-                        }
-                        save.tiles.push((save_tile, save_entity_1, save_entity_2, save_entity_3, save_entity_4));
-                        x += 1;
-                    }
-                    y += 1;
-                }
+                let mut save = field.board.to_save();
+                save.meta = field.meta.to_owned();
 
+                let path = match &saving.slot {
+                    Some(name) => {
+                        let _ = fs::create_dir_all(user_level_dir());
+                        user_level_path(name)
+                    }
+                    None => "level.skb".to_owned(),
+                };
                 if let Ok(save_string) = serde_json::to_string(&save){
-                    let _ = fs::write("level.skb", save_string);
-                    saving.save = "level.skb".to_owned();
+                    match fs::write(&path, save_string) {
+                        Ok(()) => println!("Saved {}", path),
+                        Err(error) => println!("Could not save {}: {}", path, error),
+                    }
+                    saving.save = path;
                 }
 
                 saving.saving = SaveStage::Idle;
@@ -2049,46 +1247,17 @@ pub fn saving_system(
                 if let Ok(mut cursor) = q_cursor.get_single_mut() {
                     cursor.holding = GameObjectType::None;
                 }
-                if let Some(loaded_weather) = saving.weather {
-                    weather.weather = loaded_weather;
-                }
-                if let Some(song) = &saving.song {
-                    for player in &music_player {
-                        commands.entity(player).despawn();
-                    }
-                    commands.spawn((AudioBundle {
-                        settings: PlaybackSettings{
-                            mode: PlaybackMode::Loop,
-                            volume: Volume::Absolute(VolumeLevel::new(0.75)),
-                            ..default()
-                        },
-                        source: music.songs[song].to_owned(),
-                        ..default()
-                    }, MusicPlayer));
-                }
-                field.level_id = saving.save.to_owned();
-                field.par = saving.par;
-                field.author_par = saving.author_par;
-                if let Ok(save_string) = fs::read_to_string(saving.save.to_owned()) {
-                    if let Ok(save) = serde_json::from_str::<SaveFile>(&save_string) {
-                        simulation.rounds = 0;
-                        for savetile in save.tiles {
-                            if let Some(tile) = savetile.0 {
-                                field.set_tile(&mut commands, &sprites, tile.tile_type, tile.location.x, tile.location.y);
-                                field.set_entity(&mut commands, &sprites, EntityType::None, tile.location.x, tile.location.y);
-                            }
-                            if let Some(entity) = savetile.1 {
-                                field.set_entity(&mut commands, &sprites, entity.entity_type, entity.location.x, entity.location.y);
-                            }
-                            if let Some(entity) = savetile.2 {
-                                field.set_entity(&mut commands, &sprites, entity.entity_type, entity.location.x, entity.location.y);
-                            }
-                            if let Some(entity) = savetile.3 {
-                                field.set_entity(&mut commands, &sprites, entity.entity_type, entity.location.x, entity.location.y);
-                            }
+                let replaying = saving.load_from.is_some();
+                let save = if let Some(save) = saving.load_from.take() {
+                    field.editor_mode = false;
+                    Some(save)
+                } else if let Ok(save_string) = fs::read_to_string(saving.save.to_owned()) {
+                    match serde_json::from_str::<SaveFile>(&save_string) {
+                        Ok(save) => Some(save),
+                        Err(error) => {
+                            println!("Level Loading Failed! Error: {}", error);
+                            None
                         }
-                    }else if let Err(error) = serde_json::from_str::<SaveFile>(&save_string){
-                        println!("Level Loading Failed! Error: {:?}", error);
                     }
                 }else {
                     if let Some(editor) = saving.editor_mode {
@@ -2096,29 +1265,57 @@ pub fn saving_system(
                     } else {
                         field.editor_mode = false;
                     }
-                    if let Some(save) = savefiles.get(&levels.levels[&saving.save]) {
-                        simulation.rounds = 0;
-                        for savetile in &save.tiles {
-                            if let Some(tile) = savetile.0 {
-                                field.set_tile(&mut commands, &sprites, tile.tile_type, tile.location.x, tile.location.y);
-                                field.set_entity(&mut commands, &sprites, EntityType::None, tile.location.x, tile.location.y);
-                            }
-                            if let Some(entity) = savetile.1 {
-                                field.set_entity(&mut commands, &sprites, entity.entity_type, entity.location.x, entity.location.y);
-                            }
-                            if let Some(entity) = savetile.2 {
-                                field.set_entity(&mut commands, &sprites, entity.entity_type, entity.location.x, entity.location.y);
-                            }
-                            if let Some(entity) = savetile.3 {
-                                field.set_entity(&mut commands, &sprites, entity.entity_type, entity.location.x, entity.location.y);
-                            }
-                            if let Some(flag) = savetile.4 {
-                                field.set_flag(&mut commands, &sprites, flag.index, flag.location.x, flag.location.y);
-                            }
-                        }
+                    savefiles.get(&levels.levels[&saving.save]).cloned()
+                };
+                //The level's own header wins over whatever the level list said
+                let meta = save.as_ref().and_then(|save| save.meta.to_owned()).unwrap_or_default();
+                if let Some(loaded_weather) = meta.weather.or(saving.weather) {
+                    weather.weather = loaded_weather;
+                }
+                weather.reset();
+                let song = meta.song.to_owned().or(saving.song.to_owned());
+                if let Some(song) = &song {
+                    audio.play_song(song);
+                }
+                field.level_id = saving.save.to_owned();
+                field.par = meta.par.unwrap_or(saving.par);
+                field.author_par = meta.author_par.unwrap_or(saving.author_par);
+                if !replaying {
+                    replay_player.replay = None;
+                    field.simulate_timer.set_duration(settings.tick());
+                }
+                recorder.replay = None;
+                if let Some(save) = save {
+                    simulation.rounds = 0;
+                    field.meta = save.meta.to_owned();
+                    field.load(&mut commands, &sprites, &save);
+                    field.board.rules = meta.rules.unwrap_or_default();
+                    field.board.stock_tray(meta.food.as_ref());
+                    //The food buttons depend on what this level hands out
+                    *reload_game_ui = ReloadGameUI(true);
+                    if !field.editor_mode && !replaying {
+                        //Bake in the weather, song and pars so the replay plays out the same anywhere
+                        let mut start = save;
+                        start.meta = Some(LevelMeta {
+                            par: Some(field.par),
+                            author_par: Some(field.author_par),
+                            weather: Some(weather.weather),
+                            song,
+                            ..meta
+                        });
+                        recorder.replay = Some(Replay { version: REPLAY_VERSION, level: saving.save.to_owned(), start, actions: vec![] });
                     }
                 }
                 saving.quicksaves = vec![];
+                saving.redos = vec![];
+                saving.saving = SaveStage::SaveUndo;
+            }
+            SaveStage::Resize(width, height) => {
+                if let Ok(mut cursor) = q_cursor.get_single_mut() {
+                    cursor.holding = GameObjectType::None;
+                }
+                field.resize(&mut commands, &sprites, width, height);
+                *reload_pause_menu = ReloadPauseMenu(true);
                 saving.saving = SaveStage::SaveUndo;
             }
             SaveStage::SaveUndo => {
@@ -2126,48 +1323,11 @@ pub fn saving_system(
                     println!("You FOOL! There is no level to save!");
                     return;
                 }
-                let mut save = SaveFile { version: 3, width: field.tiles.len(), height: field.tiles[0].len(), tiles: vec![] };
-
-                let mut y = 0;
-                while y < save.height {
-                    let mut x = 0;
-                    while x < save.width {
-                        let mut save_tile: Option<Tile> = None;
-                        let mut save_entity_1: Option<GameEntity> = None;
-                        let mut save_entity_2: Option<GameEntity> = None;
-                        let mut save_entity_3: Option<GameEntity> = None;
-                        let mut save_entity_4: Option<Flag> = None;
-                        if let Ok(tile) = q_tile.get(field.tiles[x][y].0) {
-                            save_tile = Some(tile.clone());
-                        }
-                        if let Some(entity_id) = field.tiles[x][y].1 {
-                            if let Ok(entity) = q_entity.get(entity_id) {
-                                save_entity_1 = Some(entity.clone());
-                            }
-                        }
-                        if let Some(entity_id) = field.tiles[x][y].2 {
-                            if let Ok(entity) = q_entity.get(entity_id) {
-                                save_entity_2 = Some(entity.clone());
-                            }
-                        }
-                        if let Some(entity_id) = field.tiles[x][y].3 {
-                            if let Ok(entity) = q_entity.get(entity_id) {
-                                save_entity_3 = Some(entity.clone());
-                            }
-                        }
-                        if let Some(entity_id) = field.tiles[x][y].4 {
-                            if let Ok(entity) = q_flag.get(entity_id) {
-                                save_entity_4 = Some(entity.clone());
-                            }
-                        }
-                        save.tiles.push((save_tile, save_entity_1, save_entity_2, save_entity_3, save_entity_4));
-                        x += 1;
-                    }
-                    y += 1;
-                }
-
-                if let Ok(save_string) = serde_json::to_string(&save){
-                    saving.quicksaves.push((save_string, simulation.to_owned()));
+                //Showing the lose screen again after a redo shouldn't stack up another copy
+                let repeated = saving.quicksaves.last().map_or(false, |(last, lastsimulation)| *last == field.board && lastsimulation.rounds == simulation.rounds);
+                if !repeated {
+                    saving.quicksaves.push((field.board.clone(), simulation.to_owned()));
+                    saving.redos = vec![];
                 }
 
                 saving.saving = SaveStage::Idle;
@@ -2175,36 +1335,17 @@ pub fn saving_system(
             SaveStage::Undo => {
                 println!("LOADING UNDO");
                 if saving.quicksaves.len() > 1 {
-                    saving.quicksaves.pop();
+                    if let Some(undone) = saving.quicksaves.pop() {
+                        saving.redos.push(undone);
+                    }
                     if let Ok(mut cursor) = q_cursor.get_single_mut() {
                         cursor.holding = GameObjectType::None;
                     }
-                    if let Some((save_string, savedsimulation)) = saving.quicksaves.last() {
-                        if let Ok(save) = serde_json::from_str::<SaveFile>(&save_string) {
-                            simulation.rounds = savedsimulation.rounds;
-                            simulation.loss = savedsimulation.loss;
-                            simulation.win = savedsimulation.win;
-                            for savetile in save.tiles {
-                                if let Some(tile) = savetile.0 {
-                                    field.set_tile(&mut commands, &sprites, tile.tile_type, tile.location.x, tile.location.y);
-                                    field.set_entity(&mut commands, &sprites, EntityType::None, tile.location.x, tile.location.y);
-                                }
-                                if let Some(entity) = savetile.1 {
-                                    field.set_entity(&mut commands, &sprites, entity.entity_type, entity.location.x, entity.location.y);
-                                }
-                                if let Some(entity) = savetile.2 {
-                                    field.set_entity(&mut commands, &sprites, entity.entity_type, entity.location.x, entity.location.y);
-                                }
-                                if let Some(entity) = savetile.3 {
-                                    field.set_entity(&mut commands, &sprites, entity.entity_type, entity.location.x, entity.location.y);
-                                }
-                                if let Some(flag) = savetile.4 {
-                                    field.set_flag(&mut commands, &sprites, flag.index, flag.location.x, flag.location.y);
-                                }
-                            }
-                        }else if let Err(error) = serde_json::from_str::<SaveFile>(&save_string){
-                            println!("Level Loading Failed! Error: {:?}", error);
-                        }
+                    if let Some((board, savedsimulation)) = saving.quicksaves.last() {
+                        simulation.rounds = savedsimulation.rounds;
+                        simulation.loss = savedsimulation.loss;
+                        simulation.win = savedsimulation.win;
+                        field.restore(&mut commands, &sprites, board);
 
                         saving.saving = SaveStage::Idle;
                     }
@@ -2213,6 +1354,19 @@ pub fn saving_system(
                     saving.saving = SaveStage::Idle;
                 }
             }
+            SaveStage::Redo => {
+                if let Some((board, savedsimulation)) = saving.redos.pop() {
+                    if let Ok(mut cursor) = q_cursor.get_single_mut() {
+                        cursor.holding = GameObjectType::None;
+                    }
+                    simulation.rounds = savedsimulation.rounds;
+                    simulation.loss = savedsimulation.loss;
+                    simulation.win = savedsimulation.win;
+                    field.restore(&mut commands, &sprites, &board);
+                    saving.quicksaves.push((board, savedsimulation));
+                }
+                saving.saving = SaveStage::Idle;
+            }
             _ => {}
         }
         if let Ok(mut round_counter) = round_counter_q.get_single_mut() {
@@ -2220,10 +1374,145 @@ pub fn saving_system(
         }
     }
 }
+pub fn user_data_dir() -> PathBuf {
+    return match ProjectDirs::from("", "BarnyardBunch", "SokoBARN") {
+        Some(dirs) => dirs.data_dir().to_path_buf(),
+        None => PathBuf::from("UserData"),
+    };
+}
+
+//Named editor levels live here, one .skb per slot
+pub fn user_level_dir() -> PathBuf {
+    return user_data_dir().join("Levels");
+}
+
+pub fn user_level_path(name: &str) -> String {
+    return user_level_dir().join(format!("{}.skb", name)).to_string_lossy().into_owned();
+}
+
+//The slot name if this path is one of the editor's levels
+pub fn user_level_name(path: &str) -> Option<String> {
+    let path = Path::new(path);
+    if path.parent() != Some(user_level_dir().as_path()) {
+        return None;
+    }
+    return path.file_stem().map(|name| name.to_string_lossy().into_owned());
+}
+
+pub fn user_levels() -> Vec<String> {
+    let mut names: Vec<String> = match fs::read_dir(user_level_dir()) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |extension| extension == "skb"))
+            .filter_map(|path| path.file_stem().map(|name| name.to_string_lossy().into_owned()))
+            .collect(),
+        Err(_) => vec![],
+    };
+    names.sort_by_key(|name| name.to_lowercase());
+    return names;
+}
+
 pub fn par_text_system(
     field: ResMut<Field>,
     mut par_q: Query<&mut Text, With<ParText>>,){
     for mut par_text in &mut par_q {
         par_text.sections[0].value = format!("PAR: {}", field.par);
     }
-}
\ No newline at end of file
+}
+pub fn save_replay(replay: &Replay) {
+    let dir = user_data_dir().join("Replays");
+    let _ = fs::create_dir_all(&dir);
+    let level = Path::new(&replay.level).file_stem().map(|name| name.to_string_lossy().into_owned()).unwrap_or("level".to_owned());
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let path = dir.join(format!("{} {}.replay", level, time));
+    if let Ok(replay_string) = serde_json::to_string(replay) {
+        match fs::write(&path, replay_string) {
+            Ok(()) => println!("Saved replay {}", path.display()),
+            Err(error) => println!("Could not save replay {}: {}", path.display(), error),
+        }
+    }
+}
+
+//sokobarn --replay <file> goes straight to watching it, for bug reports
+pub fn replay_from_args(
+    mut replay_player: ResMut<ReplayPlayer>,
+    mut saving: ResMut<SaveRes>,
+    mut next_state: ResMut<NextState<GameState>>){
+    let Some(path) = env::args().skip_while(|arg| arg != "--replay").nth(1) else {
+        return;
+    };
+    match fs::read_to_string(&path).map(|replay_string| serde_json::from_str::<Replay>(&replay_string)) {
+        Ok(Ok(replay)) => {
+            replay_player.play(replay, &mut saving);
+            next_state.set(GameState::Gameplay);
+        }
+        Ok(Err(error)) => println!("Replay Loading Failed! Error: {}", error),
+        Err(error) => println!("Could not read {}: {}", path, error),
+    }
+}
+
+pub fn not_replaying(replay_player: Res<ReplayPlayer>) -> bool {
+    return replay_player.replay.is_none();
+}
+
+//Feeds the recorded moves back in one at a time, waiting for each round, load and undo to finish
+pub fn replay_system(
+    mut commands: Commands,
+    sprites: Res<Sprites>,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    state: Res<State<GameState>>,
+    pause_menu_data: Res<PauseMenuData>,
+    mut field: ResMut<Field>,
+    mut replay_player: ResMut<ReplayPlayer>,
+    mut simulating: ResMut<SimulateRes>,
+    mut saving: ResMut<SaveRes>,
+    mut next_state: ResMut<NextState<GameState>>){
+    let Some(replay) = &replay_player.replay else {
+        return;
+    };
+    let Some(action) = replay.actions.get(replay_player.next).copied() else {
+        return;
+    };
+    //The replay speed goes on top of the tick speed from the settings, same as when playing
+    let tick = settings.tick().div_f32(replay_player.speed);
+    if field.simulate_timer.duration() != tick {
+        field.simulate_timer.set_duration(tick);
+    }
+    if simulating.simulating || saving.saving != SaveStage::Idle {
+        return;
+    }
+    //A lost round is only left through Undo, anything else in the pause menu means the viewer paused
+    if *state.get() == GameState::Pause && !(pause_menu_data.mode == PauseMenuMode::Lose && matches!(action, ReplayAction::Undo { .. })) {
+        return;
+    }
+    let speed = replay_player.speed;
+    replay_player.timer.tick(time.delta().mul_f32(speed));
+    if !replay_player.timer.just_finished() {
+        return;
+    }
+    replay_player.next += 1;
+    match action {
+        ReplayAction::Place { entity_type, x, y, .. } => {
+            field.set_entity(&mut commands, &sprites, entity_type, x, y);
+        }
+        ReplayAction::PickUp { x, y, .. } => {
+            field.take_food(&mut commands, x, y);
+        }
+        ReplayAction::Start { .. } => {
+            simulating.simulating = true;
+            simulating.rounds = simulating.rounds + 1;
+            simulating.loss = false;
+            simulating.win = false;
+        }
+        ReplayAction::Undo { .. } => {
+            next_state.set(GameState::Gameplay);
+            simulating.loss = false;
+            simulating.win = false;
+            saving.saving = SaveStage::Undo;
+        }
+        ReplayAction::Redo { .. } => {
+            saving.saving = SaveStage::Redo;
+        }
+    }
+}
@@ -0,0 +1,549 @@
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub static SAVE_VERSION: usize = 4;
+
+//Older saves are read as their own version and upgraded, so every loader gets the current layout
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone)]
+#[serde(try_from = "serde_json::Value")]
+#[derive(TypePath)]
+#[derive(TypeUuid)]
+#[uuid = "71402ca5-adec-436a-ba16-6980791e7c7d"]
+pub struct SaveFile {
+    pub version: usize,
+    pub width: usize,
+    pub height: usize,
+    //Tile, Buttons, Food, Animals, Flags
+    pub tiles: Vec<(Option<Tile>, Option<GameEntity>, Option<GameEntity>, Option<GameEntity>, Option<Flag>)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<LevelMeta>,
+}
+
+//Everything here is optional, anything left out falls back to the level list
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+pub struct LevelMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub par: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_par: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weather: Option<WeatherType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub song: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<WeatherRules>,
+    //How many of each food can be out on the field at once, foods left out get one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub food: Option<BTreeMap<EntityType, usize>>,
+}
+
+//Opt-in ways the weather changes how a level plays, none of them happen unless the level asks
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct WeatherRules {
+    //Rain spreads every puddle onto the grass next to it, once every this many rounds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mud_every: Option<usize>,
+    //In the dark animals only notice food this many tiles away
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sight: Option<usize>,
+    //Thunder sends everyone bolting a tile back the way they came, once every this many rounds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spook_every: Option<usize>,
+}
+
+impl WeatherRules {
+    //How many rounds until the weather comes round to doing the same thing again
+    pub fn cycle(&self) -> usize {
+        let mut ret_val = 1;
+        for every in [self.mud_every, self.spook_every].into_iter().flatten().filter(|every| *every > 0) {
+            let (mut a, mut b) = (ret_val, every);
+            while b > 0 {
+                (a, b) = (b, a % b);
+            }
+            ret_val = ret_val / a * every;
+        }
+        return ret_val;
+    }
+}
+
+impl LevelMeta {
+    //Name, author and hint as shown on the pause menu
+    pub fn describe(&self) -> Option<String> {
+        let mut lines = vec![];
+        match (&self.name, &self.author) {
+            (Some(name), Some(author)) => lines.push(format!("{} by {}", name, author)),
+            (Some(name), None) => lines.push(name.to_owned()),
+            (None, Some(author)) => lines.push(format!("by {}", author)),
+            (None, None) => {}
+        }
+        if let Some(hint) = &self.hint {
+            lines.push(format!("Hint: {}", hint));
+        }
+        if lines.is_empty() {
+            return None;
+        }
+        return Some(lines.join("\n"));
+    }
+}
+
+pub static REPLAY_VERSION: usize = 1;
+
+//A level as it was when play began, plus every move the player made on it
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone)]
+pub struct Replay {
+    pub version: usize,
+    pub level: String,
+    pub start: SaveFile,
+    pub actions: Vec<ReplayAction>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum ReplayAction {
+    Place { round: usize, entity_type: EntityType, x: usize, y: usize },
+    PickUp { round: usize, x: usize, y: usize },
+    Start { round: usize },
+    Undo { round: usize },
+    Redo { round: usize },
+}
+
+//Version 1: no flag layer, and each tile's sprite index was saved too, which is just skipped over.
+//No version 2 file has turned up, so there's no telling what it looked like and it isn't read.
+#[derive(Deserialize)]
+pub struct SaveFileV1 {
+    pub width: usize,
+    pub height: usize,
+    //Tile, Buttons, Food, Animals
+    pub tiles: Vec<(Option<Tile>, Option<GameEntity>, Option<GameEntity>, Option<GameEntity>)>,
+}
+
+//Version 3: flags get their own layer, and the sprite index is worked out from the neighbours instead of being saved
+#[derive(Deserialize)]
+pub struct SaveFileV3 {
+    pub width: usize,
+    pub height: usize,
+    //Tile, Buttons, Food, Animals, Flags
+    pub tiles: Vec<(Option<Tile>, Option<GameEntity>, Option<GameEntity>, Option<GameEntity>, Option<Flag>)>,
+    #[serde(default)]
+    pub meta: Option<LevelMeta>,
+}
+
+//Version 4: same layout, but ducks, water, ice, gates, buttons, one-way gates and conveyors are new, so older builds can't read it
+#[derive(Deserialize)]
+pub struct SaveFileV4 {
+    pub width: usize,
+    pub height: usize,
+    //Tile, Buttons, Food, Animals, Flags
+    pub tiles: Vec<(Option<Tile>, Option<GameEntity>, Option<GameEntity>, Option<GameEntity>, Option<Flag>)>,
+    #[serde(default)]
+    pub meta: Option<LevelMeta>,
+}
+
+impl From<SaveFileV1> for SaveFileV3 {
+    fn from(save: SaveFileV1) -> Self {
+        return SaveFileV3 {
+            width: save.width,
+            height: save.height,
+            tiles: save.tiles.into_iter().map(|(tile, button, food, animal)| (tile, button, food, animal, None)).collect(),
+            meta: None,
+        };
+    }
+}
+
+impl From<SaveFileV3> for SaveFileV4 {
+    fn from(save: SaveFileV3) -> Self {
+        return SaveFileV4 {
+            width: save.width,
+            height: save.height,
+            tiles: save.tiles,
+            meta: save.meta,
+        };
+    }
+}
+
+impl From<SaveFileV4> for SaveFile {
+    fn from(save: SaveFileV4) -> Self {
+        return SaveFile {
+            version: SAVE_VERSION,
+            width: save.width,
+            height: save.height,
+            tiles: save.tiles,
+            meta: save.meta,
+        };
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    NoVersion,
+    UnknownVersion(usize),
+    FromTheFuture(usize),
+    Malformed(usize, serde_json::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::NoVersion => write!(f, "save has no version number"),
+            SaveError::UnknownVersion(version) => write!(f, "save is version {}, which this build doesn't know how to read", version),
+            SaveError::FromTheFuture(version) => write!(f, "save is version {}, but this build only reads up to version {}; update the game to load it", version, SAVE_VERSION),
+            SaveError::Malformed(version, error) => write!(f, "save claims to be version {} but does not match that layout: {}", version, error),
+        }
+    }
+}
+
+impl TryFrom<serde_json::Value> for SaveFile {
+    type Error = SaveError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        let Some(version) = save_version(&value) else {
+            return Err(SaveError::NoVersion);
+        };
+        let malformed = |error| SaveError::Malformed(version, error);
+        return match version {
+            1 => {
+                let save = serde_json::from_value::<SaveFileV1>(value).map_err(malformed)?;
+                Ok(SaveFileV4::from(SaveFileV3::from(save)).into())
+            }
+            3 => {
+                let save = serde_json::from_value::<SaveFileV3>(value).map_err(malformed)?;
+                Ok(SaveFileV4::from(save).into())
+            }
+            4 => {
+                let save = serde_json::from_value::<SaveFileV4>(value).map_err(malformed)?;
+                Ok(save.into())
+            }
+            _ if version > SAVE_VERSION => Err(SaveError::FromTheFuture(version)),
+            _ => Err(SaveError::UnknownVersion(version)),
+        };
+    }
+}
+
+//The version a save was written as, before any upgrading
+pub fn save_version(value: &serde_json::Value) -> Option<usize> {
+    return value.get("version")?.as_u64().map(|version| version as usize);
+}
+
+#[derive(Component)]
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy)]
+#[derive(Default)]
+pub struct GameEntity {
+    pub entity_type: EntityType,
+    pub location: Location,
+    pub target_location: Location,
+    pub offset: Vec2,
+    pub state: EntityState,
+    pub prev_state: Option<EntityState>,
+    pub last_direction: MoveDirection,
+    //Only the pen with the same number will take this animal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pen: Option<usize>,
+}
+
+#[derive(Component)]
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Tile {
+    pub tile_type: TileType,
+    pub location: Location,
+    //Pens with a number only take the animal with the same number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pen: Option<usize>,
+}
+
+#[derive(Component)]
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Flag {
+    pub location: Location,
+    pub index: usize
+}
+
+#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Default)]
+pub enum EntityState {
+    #[default] Idle,
+    Walking,
+    Sliding,
+    Eating,
+    Celebrating,
+    Special,
+    Failure,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, Copy)]
+#[derive(PartialOrd, Ord)]
+#[derive(Default)]
+#[derive(Serialize, Deserialize, Debug)]
+pub enum EntityType {
+    Chicken,
+    Pig,
+    Horse,
+    Goat,
+    Wagon,
+    Duck,
+    //Never eats, herds the other animals instead
+    Sheepdog,
+    //Moves with every sheep it touches
+    Sheep,
+    ChickenFood,
+    HorseFood,
+    PigFood,
+    AllFood,
+    WagonFood,
+    DuckFood,
+    #[default] None,
+    FlagChicken1,
+    FlagChicken2,
+    FlagChicken3,
+    FlagChicken4,
+    FlagHorse1,
+    FlagHorse2,
+    FlagHorse3,
+    FlagHorse4,
+    FlagPig1,
+    FlagPig2,
+    FlagPig3,
+    FlagPig4,
+    FlagGoat1,
+    FlagGoat2,
+    FlagGoat3,
+    FlagGoat4,
+    FlagWagon1,
+    FlagWagon2,
+    FlagWagon3,
+    FlagWagon4,
+    Flag1,
+    Flag2,
+    Flag3,
+    Flag4,
+    FlagDuck1,
+    FlagDuck2,
+    FlagDuck3,
+    FlagDuck4,
+    FlagSheep1,
+    FlagSheep2,
+    FlagSheep3,
+    FlagSheep4,
+    FlagSheepdog1,
+    FlagSheepdog2,
+    FlagSheepdog3,
+    FlagSheepdog4,
+    //A pressure plate that flips the gates on its channel
+    Button(usize),
+}
+
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+#[derive(Default)]
+#[derive(Serialize, Deserialize, Debug)]
+#[allow(dead_code)]
+pub enum WeatherType {
+    #[default] Sunny,
+    Cloudy,
+    Raining,
+    Night,
+    RainyNight,
+    Thunder
+}
+
+#[derive(Eq, PartialEq, Hash)]
+#[derive(Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug)]
+pub enum TileType {
+    Grass,
+    Fence,
+    Rocks,
+    Mud,
+    MuddyRocks,
+    Ditch,
+    ChickenPen,
+    PigPen,
+    GoatPen,
+    HorsePen,
+    Corral,
+    DuckPen,
+    SheepPen,
+    //Only Ducks can swim, everyone else stops at the bank
+    Water,
+    //Everyone slides on Ice. It cracks the first time an animal steps off it and turns to Water the second.
+    Ice,
+    CrackedIce,
+    //Can only be walked onto heading the way it points
+    OneWay(MoveDirection),
+    //Carries whoever stands on it a tile its way every turn
+    Conveyor(MoveDirection),
+    //Closed and open gates, flipped by buttons of the same channel
+    Gate(usize),
+    OpenGate(usize),
+}
+
+#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Default)]
+pub enum MoveDirection {
+    #[default] None,
+    Left,
+    Right,
+    Up,
+    Down
+}
+
+impl MoveDirection {
+    pub fn opposite(&self) -> MoveDirection {
+        return match self {
+            MoveDirection::Left => MoveDirection::Right,
+            MoveDirection::Right => MoveDirection::Left,
+            MoveDirection::Up => MoveDirection::Down,
+            MoveDirection::Down => MoveDirection::Up,
+            MoveDirection::None => MoveDirection::None,
+        };
+    }
+
+    //A quarter turn, for rotating tiles in the editor
+    pub fn clockwise(&self) -> MoveDirection {
+        return match self {
+            MoveDirection::Right => MoveDirection::Down,
+            MoveDirection::Down => MoveDirection::Left,
+            MoveDirection::Left => MoveDirection::Up,
+            MoveDirection::Up => MoveDirection::Right,
+            MoveDirection::None => MoveDirection::None,
+        };
+    }
+
+    //Straight lines only, horizontal wins a tie
+    pub fn towards(from: Location, to: Location) -> MoveDirection {
+        return if to.x > from.x {
+            MoveDirection::Right
+        } else if to.x < from.x {
+            MoveDirection::Left
+        } else if to.y > from.y {
+            MoveDirection::Up
+        } else if to.y < from.y {
+            MoveDirection::Down
+        } else {
+            MoveDirection::None
+        };
+    }
+}
+
+#[derive(Component)]
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy)]
+#[derive(Default)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Location {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(json: &str) -> Result<SaveFile, SaveError> {
+        return SaveFile::try_from(serde_json::from_str::<serde_json::Value>(json).unwrap());
+    }
+
+    static PIG: &str = r#"{"entity_type":"Pig","location":{"x":1,"y":0,"z":38},"target_location":{"x":1,"y":0,"z":0},"offset":[0.0,0.0],"state":"Idle","last_direction":"None"}"#;
+
+    #[test]
+    fn upgrades_version_1() {
+        let json = format!(r#"{{"version":1,"width":2,"height":1,"tiles":[
+            [{{"tile_type":"Grass","location":{{"x":0,"y":0,"z":0}},"index":3}},null,null,null],
+            [{{"tile_type":"PigPen","location":{{"x":1,"y":0,"z":0}},"index":7}},null,null,{}]]}}"#, PIG);
+        let save = load(&json).unwrap();
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!((save.width, save.height), (2, 1));
+        assert_eq!(save.tiles[1].0.map(|tile| tile.tile_type), Some(TileType::PigPen));
+        assert_eq!(save.tiles[1].3.map(|animal| animal.entity_type), Some(EntityType::Pig));
+        //Version 1 had no flags
+        assert!(save.tiles.iter().all(|tile| tile.4.is_none()));
+        assert!(save.meta.is_none());
+    }
+
+    #[test]
+    fn upgrades_the_old_blank_level() {
+        //blank.skb as it shipped before the save format got versioned migrations
+        let save = load(include_str!("../tests/fixtures/blank-v1.skb")).unwrap();
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!((save.width, save.height), (14, 8));
+        assert_eq!(save.tiles.len(), 14 * 8);
+        assert!(save.tiles.iter().all(|tile| tile.0.map(|tile| tile.tile_type) == Some(TileType::Grass)));
+        assert!(save.tiles.iter().all(|tile| tile.1.is_none() && tile.2.is_none() && tile.3.is_none() && tile.4.is_none()));
+        assert!(save.meta.is_none());
+    }
+
+    #[test]
+    fn upgrades_version_3() {
+        let json = format!(r#"{{"version":3,"width":2,"height":1,"tiles":[
+            [{{"tile_type":"Grass","location":{{"x":0,"y":0,"z":0}}}},null,null,null,null],
+            [{{"tile_type":"PigPen","location":{{"x":1,"y":0,"z":0}}}},null,null,{},null]],
+            "meta":{{"name":"Trough","par":3}}}}"#, PIG);
+        let save = load(&json).unwrap();
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.tiles[1].3.map(|animal| animal.entity_type), Some(EntityType::Pig));
+        let meta = save.meta.unwrap();
+        assert_eq!(meta.name.as_deref(), Some("Trough"));
+        assert_eq!(meta.par, Some(3));
+    }
+
+    #[test]
+    fn reads_version_4_as_it_is() {
+        let json = r#"{"version":4,"width":1,"height":1,"tiles":[
+            [{"tile_type":{"Gate":2},"location":{"x":0,"y":0,"z":0}},null,null,null,null]],
+            "meta":{"rules":{"mud_every":2}}}"#;
+        let save = load(json).unwrap();
+        assert_eq!(save.version, 4);
+        assert_eq!(save.tiles[0].0.map(|tile| tile.tile_type), Some(TileType::Gate(2)));
+        assert_eq!(save.meta.and_then(|meta| meta.rules).and_then(|rules| rules.mud_every), Some(2));
+    }
+
+    #[test]
+    fn upgraded_saves_write_out_as_the_current_version() {
+        let json = r#"{"version":1,"width":1,"height":1,"tiles":[[{"tile_type":"Grass","location":{"x":0,"y":0,"z":0},"index":3},null,null,null]]}"#;
+        let written = serde_json::to_string(&load(json).unwrap()).unwrap();
+        let value = serde_json::from_str::<serde_json::Value>(&written).unwrap();
+        assert_eq!(save_version(&value), Some(SAVE_VERSION));
+        assert_eq!(load(&written).unwrap().tiles.len(), 1);
+    }
+
+    #[test]
+    fn rejects_bad_versions() {
+        assert!(matches!(load(r#"{"width":1,"height":1,"tiles":[]}"#), Err(SaveError::NoVersion)));
+        assert!(matches!(load(r#"{"version":0,"width":1,"height":1,"tiles":[]}"#), Err(SaveError::UnknownVersion(0))));
+        //No version 2 file is known, so its layout would only be a guess
+        assert!(matches!(load(r#"{"version":2,"width":1,"height":1,"tiles":[]}"#), Err(SaveError::UnknownVersion(2))));
+        assert!(matches!(load(r#"{"version":99,"width":1,"height":1,"tiles":[]}"#), Err(SaveError::FromTheFuture(99))));
+        //A version 3 save needs the flag layer
+        let json = r#"{"version":3,"width":1,"height":1,"tiles":[[{"tile_type":"Grass","location":{"x":0,"y":0,"z":0}},null,null,null]]}"#;
+        assert!(matches!(load(json), Err(SaveError::Malformed(3, _))));
+    }
+}
@@ -0,0 +1,5 @@
+//The rules of the game without the game, shared by the game and the level tools
+
+pub mod board;
+pub mod level;
+pub mod solver;
@@ -1,15 +1,22 @@
 #![windows_subsystem = "windows"]
 
+mod audio;
+mod controls;
 mod game;
 mod menu;
+mod settings;
 mod simulation;
 mod weather;
 
+use crate::audio::*;
+use crate::controls::*;
 use crate::game::*;
 use crate::menu::*;
+use crate::settings::*;
 use crate::simulation::*;
 use crate::weather::*;
-use bevy::audio::PlaybackMode;
+use sokobarn::board::*;
+use sokobarn::level::*;
 use bevy::ecs::schedule::common_conditions;
 use bevy::prelude::*;
 use bevy::utils::HashMap;
@@ -26,6 +33,9 @@ static ASPECT_RATIO_W: f32 = 16.0;
 static ASPECT_RATIO_H: f32 = 9.0;
 static TILE_OFFSET_X: f32 = 7.5;
 static TILE_OFFSET_Y: f32 = 3.0;
+//The part of the 16x9 screen left over beside the side panel and above the bottom bar
+static PLAY_AREA_W: f32 = 14.0;
+static PLAY_AREA_H: f32 = 8.0;
 static ONLINE_BUILD: bool = false;
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
@@ -50,7 +60,9 @@ pub struct Cursor {
 pub enum GameObjectType {
     None,
     Entity(EntityType),
-    Tile(TileType)
+    Tile(TileType),
+    //Paints a number onto an animal or a whole pen, see Tile::pen
+    Pen(usize),
 }
 impl GameObjectType {
     pub fn icon_atlas(&self, sprites: &Res<Sprites>) -> Handle<TextureAtlas>{
@@ -61,6 +73,9 @@ impl GameObjectType {
             GameObjectType::Tile(tile) => {
                 return tile.icon_atlas(sprites);
             }
+            GameObjectType::Pen(_) => {
+                return TileType::Corral.icon_atlas(sprites);
+            }
             _ => sprites.sprites["Chicken"].clone(),
         }
     }
@@ -72,9 +87,20 @@ impl GameObjectType {
             GameObjectType::Tile(tile) => {
                 return tile.icon_index();
             }
+            GameObjectType::Pen(_) => {
+                return TileType::Corral.icon_index();
+            }
             _ => 0,
         }
     }
+    pub fn tint(&self) -> Color {
+        match self {
+            GameObjectType::Entity(entity) => entity.tint(),
+            GameObjectType::Tile(tile) => tile.tint(),
+            GameObjectType::Pen(pen) => channel_colour(*pen),
+            _ => Color::WHITE,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -94,9 +120,6 @@ pub struct CursorObj {
 #[derive(Component)]
 pub struct KeyArt;
 
-#[derive(Component)]
-pub struct MusicPlayer;
-
 #[derive(Component)]
 pub struct Scaling {
     position: Vec2
@@ -116,6 +139,13 @@ pub struct Medals {
     pub medals: HashMap<String, usize>
 }
 
+impl Medals {
+    //Every medal earned so far, which is what unlocks levels
+    pub fn total(&self) -> usize {
+        return self.medals.values().sum();
+    }
+}
+
 #[derive(Resource)]
 #[derive(Default)]
 #[derive(PartialEq, Eq)]
@@ -146,6 +176,16 @@ pub struct Tutorial {
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct ReloadGameUI(bool);
 
+#[derive(Resource)]
+#[derive(Default)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ReloadPauseMenu(bool);
+
+#[derive(Resource)]
+#[derive(Default)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ReloadSettingsMenu(bool);
+
 #[derive(Resource)]
 #[derive(Default)]
 pub struct GameMusic {
@@ -155,7 +195,8 @@ pub struct GameMusic {
 #[derive(Resource)]
 #[derive(Default)]
 pub struct Levels {
-    levels: HashMap<String, Handle<SaveFile>>
+    levels: HashMap<String, Handle<SaveFile>>,
+    campaign: Handle<Campaign>
 }
 
 fn main() {
@@ -176,12 +217,13 @@ fn main() {
                 ..default()
             }).set(ImagePlugin::default_nearest()),
             JsonAssetPlugin::<SaveFile>::new(&["skb"]),
+            JsonAssetPlugin::<Campaign>::new(&["campaign.json"]),
             EntropyPlugin::<ChaCha8Rng>::default()
         ))
         .insert_resource(ClearColor(Color::hex("ACD132").unwrap()))
         .insert_resource(PkvStore::new("BarnyardBunch", "SokoBARN"))
         .insert_resource(Tutorial{seen: false})
-        .add_systems(Startup, setup)
+        .add_systems(Startup, (setup, apply_deferred, replay_from_args).chain())
 
         //Menus
         .add_systems(OnEnter(GameState::Menu), (game_cleanup.run_if(resource_exists::<Field>()), menu_setup).chain())
@@ -194,10 +236,19 @@ fn main() {
         
         .add_systems(OnEnter(GameState::Pause), pause_menu_setup)
         .add_systems(OnExit(GameState::Pause), pause_menu_cleanup)
+        .add_systems(Update, (pause_menu_cleanup, pause_menu_setup).chain().run_if(in_state(GameState::Pause).and_then(resource_equals(ReloadPauseMenu(true)))))
+        .add_systems(Update, save_name_system.run_if(in_state(GameState::Pause)))
 
         //Buttons
         .add_systems(Update, (button_system, button_update_system))
 
+        //Level list
+        .add_systems(Update, campaign_system)
+        .add_systems(Update, rebind_system.before(button_system))
+        .add_systems(Update, apply_settings)
+        .add_systems(Update, audio_system)
+        .add_systems(Update, (settings_menu_cleanup, settings_menu_setup).chain().run_if(resource_equals(ReloadSettingsMenu(true))))
+
         //Move custom cursor
         .add_systems(Update, cursor)
 
@@ -211,10 +262,13 @@ fn main() {
         .add_systems(OnEnter(GameState::Gameplay), (setup_level.run_if(common_conditions::not(resource_exists::<Field>())), apply_deferred, saving_system, game_ui_setup).chain())
         .add_systems(Update, saving_system.run_if(in_state(GameState::Gameplay).or_else(in_state(GameState::Pause))))
         .add_systems(Update, simulate.run_if(in_state(GameState::Gameplay)))
+        .add_systems(Update, replay_system.run_if(in_state(GameState::Gameplay).or_else(in_state(GameState::Pause))))
         .add_systems(Update, weather_system.run_if(in_state(GameState::Gameplay)))
 
         //Cursor Controls
-        .add_systems(Update, (mouse_controls).chain().run_if(in_state(GameState::Gameplay)))
+        .add_systems(Update, (mouse_controls).chain().run_if(in_state(GameState::Gameplay).and_then(not_replaying)))
+        .add_systems(Update, grid_cursor_controls.run_if(in_state(GameState::Gameplay).and_then(not_replaying)))
+        .add_systems(Update, action_controls.run_if(in_state(GameState::Gameplay).or_else(in_state(GameState::Pause))))
 
         //Post Update Visuals
         .add_systems(PostUpdate, ((ditch_system, fence_system).run_if(in_state(GameState::Gameplay).or_else(in_state(GameState::Pause))), animation_system, effect_system, resize_system, apply_deferred).chain())
@@ -225,215 +279,38 @@ fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut pkv: ResMut<PkvStore>
+    pkv: Res<PkvStore>
     ) {
 
     commands.insert_resource(SaveRes { saving: SaveStage::Idle, save: "level.skb".to_owned(), quicksaves: vec![], ..default() });
     commands.insert_resource(SimulateRes { simulating: false, rounds: 0, ..default() });
     commands.insert_resource(MenuData { button_entities: vec![], menu_offset: 0 });
-    commands.insert_resource(PauseMenuData { button_entities: vec![], mode: PauseMenuMode::Pause });
+    commands.insert_resource(PauseMenuData { button_entities: vec![], mode: PauseMenuMode::Pause, save_name: "".to_owned(), page: 0, confirm_delete: None, confirm_overwrite: None });
     commands.insert_resource(Weather { raindrop_count: 800 /*400*/, ..default() });
     commands.insert_resource(ReloadLevelSelect{reloading: true});
     commands.insert_resource(ReloadGameUI(false));
-
-    let mut worlds = vec![];
-
-    worlds.push(LevelWorld{
-        name: "Tutorials".to_owned(),
-        levels: vec![
-            LevelData {
-                name: "Goat 1".to_owned(),
-                id: "Levels/goat-tutorial-1.skb".to_owned(),
-                song: "Song 3".to_owned(),
-                par: 6,
-                author_par: 4,
-                ..default()
-            },
-            LevelData {
-                name: "Goat 2".to_owned(),
-                id: "Levels/goat-tutorial-2.skb".to_owned(),
-                song: "Song 3".to_owned(),
-                par: 8,
-                author_par: 6,
-                ..default()
-            },
-            LevelData {
-                name: "Horse 1".to_owned(),
-                id: "Levels/horse-tutorial-1.skb".to_owned(),
-                song: "Song 3".to_owned(),
-                par: 9,
-                author_par: 7,
-                ..default()
-            },
-            LevelData {
-                name: "Horse 2".to_owned(),
-                id: "Levels/horse-tutorial-2.skb".to_owned(),
-                song: "Song 3".to_owned(),
-                par: 10,
-                author_par: 8,
-                ..default()
-            }
-        ]
-    });
-    worlds.push(LevelWorld{
-        name: "Tutorials 2".to_owned(),
-        levels: vec![
-            LevelData {
-                name: "Pig 1".to_owned(),
-                id: "Levels/pig-tutorial-1.skb".to_owned(),
-                song: "Song 2".to_owned(),
-                par: 11,
-                author_par: 10,
-                ..default()
-            },
-            LevelData {
-                name: "Pig 2".to_owned(),
-                id: "Levels/pig-tutorial-2.skb".to_owned(),
-                song: "Song 2".to_owned(),
-                par: 6,
-                author_par: 5,
-                ..default()
-            },
-            LevelData {
-                name: "Chicken 1".to_owned(),
-                id: "Levels/chicken-tutorial-1.skb".to_owned(),
-                song: "Song 2".to_owned(),
-                par: 13,
-                author_par: 12,
-                ..default()
-            },
-            LevelData {
-                name: "Chicken 2".to_owned(),
-                id: "Levels/chicken-tutorial-2.skb".to_owned(),
-                song: "Song 2".to_owned(),
-                par: 9,
-                author_par: 8,
-                ..default()
-            }
-        ]
-    });
-    worlds.push(LevelWorld{
-        name: "Rain".to_owned(),
-        levels: vec![
-            LevelData {
-                name: "Rain 1".to_owned(),
-                id: "Levels/Rain-1.skb".to_owned(),
-                weather: WeatherType::Raining,
-                song: "Rain 2".to_owned(),
-                par: 11,
-                author_par: 8,
-                ..default()
-            },
-            LevelData {
-                name: "Rain 2".to_owned(),
-                id: "Levels/Rain-2.skb".to_owned(),
-                weather: WeatherType::Raining,
-                song: "Rain 2".to_owned(),
-                par: 12,
-                author_par: 10,
-                ..default()
-            },
-            LevelData {
-                name: "Rain 3".to_owned(),
-                id: "Levels/Rain-3.skb".to_owned(),
-                weather: WeatherType::Raining,
-                song: "Rain 1".to_owned(),
-                par: 15,
-                author_par: 14,
-                ..default()
-            },
-            LevelData {
-                name: "Rain 4".to_owned(),
-                id: "Levels/Rain-4.skb".to_owned(),
-                weather: WeatherType::Raining,
-                song: "Rain 1".to_owned(),
-                par: 17,
-                author_par: 16,
-                ..default()
-            },
-        ]
-    });
-    
-    worlds.push(LevelWorld{
-        name: "Night".to_owned(),
-        levels: vec![
-            LevelData {
-                name: "Night 1".to_owned(),
-                id: "Levels/Night-1.skb".to_owned(),
-                weather: WeatherType::Night,
-                song: "Song 1".to_owned(),
-                par: 50,
-                author_par: 43,
-                ..default()
-            },
-            LevelData {
-                name: "Night 2".to_owned(),
-                id: "Levels/Night-2.skb".to_owned(),
-                weather: WeatherType::Night,
-                song: "Song 1".to_owned(),
-                par: 10,
-                author_par: 7,
-                ..default()
-            },
-            LevelData {
-                name: "Night 3".to_owned(),
-                id: "Levels/Night-3.skb".to_owned(),
-                weather: WeatherType::RainyNight,
-                song: "Saga".to_owned(),
-                par: 18,
-                author_par: 15,
-                ..default()
-            },
-            LevelData {
-                name: "Editor".to_owned(),
-                id: "Levels/blank.skb".to_owned(),
-                song: "Song 1".to_owned(),
-                editor: true,
-                ..default()
-            }
-        ]
-    });
+    commands.insert_resource(ReloadPauseMenu(false));
+    commands.insert_resource(ReplayRecorder::default());
+    commands.insert_resource(ReplayPlayer::default());
+    commands.insert_resource(InputMap::load(&pkv));
+    commands.insert_resource(SettingsMenu::default());
+    commands.insert_resource(Settings::load(&pkv));
+    commands.insert_resource(AudioManager::new());
+    commands.insert_resource(ReloadSettingsMenu(false));
+    commands.insert_resource(GridCursor::default());
 
     if let Ok(medals) = pkv.get::<Medals>("save") {
         commands.insert_resource(medals);
     } else {
-        let mut medals: HashMap<String, usize> = HashMap::new();
-        for world in &worlds {
-            for level in &world.levels {
-                medals.insert(level.id.to_owned(), 0);
-            }
-        }
-        pkv.set("save", &medals).expect("failed to store medals");
-        commands.insert_resource(Medals{medals});
+        commands.insert_resource(Medals::default());
     }
-
-    commands.insert_resource(WorldList { index: 0, worlds });
-
+    //Filled in by campaign_system once the manifest has loaded
+    commands.insert_resource(WorldList { index: 0, worlds: vec![] });
     let camera_bundle = Camera2dBundle::default();
     //camera_bundle.projection.scaling_mode = ScalingMode::Fixed { width: 640.0, height: 360.0 };
     commands.spawn(camera_bundle);
 
-    
-    let mut levels: HashMap<String, Handle<SaveFile>> = HashMap::new();
-    {let level = "Levels/goat-tutorial-1.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/goat-tutorial-2.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/horse-tutorial-1.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/horse-tutorial-2.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/pig-tutorial-1.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/pig-tutorial-2.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/chicken-tutorial-1.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/chicken-tutorial-2.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/Night-1.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/Night-2.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/Night-3.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/Night-4.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/Rain-1.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/Rain-2.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/Rain-3.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/Rain-4.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    {let level = "Levels/blank.skb";levels.insert(level.to_owned(), asset_server.load(level));}
-    
-    commands.insert_resource(Levels { levels: levels });
+    commands.insert_resource(Levels { levels: HashMap::new(), campaign: asset_server.load("Levels/main.campaign.json") });
 
     
     let mut music: HashMap<String, Handle<AudioSource>> = HashMap::new();
@@ -459,6 +336,9 @@ fn setup(
     sprites.insert("Horse".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Animals/sokobarn-Horse.png"), Vec2::new(28.0, 28.0), 4, 7, None, None)));
     sprites.insert("Goat".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Animals/sokobarn-Goat.png"), Vec2::new(28.0, 28.0), 4, 7, None, None)));
     sprites.insert("Wagon".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Animals/sokobarn-Empty-Cart.png"), Vec2::new(28.0, 28.0), 4, 7, None, None)));
+    sprites.insert("Duck".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Animals/sokobarn-Duck.png"), Vec2::new(28.0, 28.0), 4, 7, None, None)));
+    sprites.insert("Sheepdog".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Animals/sokobarn-Sheepdog.png"), Vec2::new(28.0, 28.0), 4, 7, None, None)));
+    sprites.insert("Sheep".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Animals/sokobarn-Sheep.png"), Vec2::new(28.0, 28.0), 4, 7, None, None)));
     sprites.insert("Grass".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-Grass-NEW.png"), Vec2::new(32.0, 32.0), 2, 2, None, None)));
     sprites.insert("Fence".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-Fences.png"), Vec2::new(32.0, 32.0), 5, 1, None, None)));
     sprites.insert("Rocks".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-Rocks.png"), Vec2::new(64.0, 64.0), 2, 2, None, None)));
@@ -466,16 +346,29 @@ fn setup(
     sprites.insert("MuddyRocks".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-Muddy-Rocks.png"), Vec2::new(64.0, 64.0), 2, 2, None, None)));
     sprites.insert("Ditch".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-Ditches.png"), Vec2::new(32.0, 32.0), 8, 2, None, None)));
     sprites.insert("Pens".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-Pens.png"), Vec2::new(48.0, 48.0), 5, 3, None, None)));
+    sprites.insert("DuckPen".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-DuckPen.png"), Vec2::new(48.0, 48.0), 1, 3, None, None)));
+    sprites.insert("SheepPen".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-SheepPen.png"), Vec2::new(48.0, 48.0), 1, 3, None, None)));
+    sprites.insert("Water".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-Water.png"), Vec2::new(32.0, 32.0), 2, 2, None, None)));
     sprites.insert("Food".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-Food.png"), Vec2::new(28.0, 28.0), 5, 1, None, None)));
+    sprites.insert("DuckFood".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-DuckFood.png"), Vec2::new(28.0, 28.0), 1, 1, None, None)));
     sprites.insert("Cursor".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-Cursors.png"), Vec2::new(64.0, 64.0), 5, 1, None, None)));
     sprites.insert("Rain".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-Rain.png"), Vec2::new(5.0, 5.0), 4, 1, None, None)));
     sprites.insert("MuddySplash".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-MuddySplash.png"), Vec2::new(28.0, 28.0), 4, 1, None, None)));
     sprites.insert("Disabled".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-Disabled.png"), Vec2::new(32.0, 32.0), 1, 1, None, None)));
     sprites.insert("Arrow".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-Arrow.png"), Vec2::new(32.0, 32.0), 4, 1, None, None)));
-    sprites.insert("Flags".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-Flags.png"), Vec2::new(32.0, 32.0), 4, 24, None, None)));
+    sprites.insert("Flags".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-Flags.png"), Vec2::new(32.0, 32.0), 4, 36, None, None)));
     sprites.insert("Medals".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-level-medals.png"), Vec2::new(36.0, 36.0), 4, 1, None, None)));
     sprites.insert("Working".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-working.png"), Vec2::new(28.0, 28.0), 2, 1, None, None)));
     sprites.insert("TileIcons".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-TileIcons.png"), Vec2::new(28.0, 28.0), 15, 1, None, None)));
+    sprites.insert("Ice".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-Ice.png"), Vec2::new(32.0, 32.0), 2, 2, None, None)));
+    sprites.insert("CrackedIce".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-CrackedIce.png"), Vec2::new(32.0, 32.0), 2, 2, None, None)));
+    sprites.insert("IceIcons".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-IceIcons.png"), Vec2::new(28.0, 28.0), 2, 1, None, None)));
+    sprites.insert("OneWay".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-OneWay.png"), Vec2::new(32.0, 32.0), 4, 1, None, None)));
+    sprites.insert("Conveyor".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Tiles/sokobarn-Conveyor.png"), Vec2::new(32.0, 32.0), 4, 1, None, None)));
+    sprites.insert("DirectionIcons".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-DirectionIcons.png"), Vec2::new(28.0, 28.0), 8, 1, None, None)));
+    //Water, Duck Pen
+    sprites.insert("DuckIcons".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-DuckIcons.png"), Vec2::new(28.0, 28.0), 2, 1, None, None)));
+    sprites.insert("SheepIcons".to_owned(), texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-SheepIcons.png"), Vec2::new(28.0, 28.0), 1, 1, None, None)));
 
     commands.insert_resource(Sprites { sprites: sprites });
 
@@ -504,10 +397,33 @@ fn setup(
     sounds.insert("Mud3".to_owned(), asset_server.load("Sounds/Mud3.ogg"));
     sounds.insert("Mud4".to_owned(), asset_server.load("Sounds/Mud4.ogg"));
     sounds.insert("GoatCrash".to_owned(), asset_server.load("Sounds/GoatCrash.ogg"));
+    sounds.insert("Duck1".to_owned(), asset_server.load("Sounds/Duck1.ogg"));
+    sounds.insert("Duck2".to_owned(), asset_server.load("Sounds/Duck2.ogg"));
+    sounds.insert("Duck3".to_owned(), asset_server.load("Sounds/Duck3.ogg"));
+    sounds.insert("Duck4".to_owned(), asset_server.load("Sounds/Duck4.ogg"));
+    sounds.insert("Dog1".to_owned(), asset_server.load("Sounds/Dog1.ogg"));
+    sounds.insert("Dog2".to_owned(), asset_server.load("Sounds/Dog2.ogg"));
+    sounds.insert("Dog3".to_owned(), asset_server.load("Sounds/Dog3.ogg"));
+    sounds.insert("Dog4".to_owned(), asset_server.load("Sounds/Dog4.ogg"));
+    sounds.insert("Sheep1".to_owned(), asset_server.load("Sounds/Sheep1.ogg"));
+    sounds.insert("Sheep2".to_owned(), asset_server.load("Sounds/Sheep2.ogg"));
+    sounds.insert("Sheep3".to_owned(), asset_server.load("Sounds/Sheep3.ogg"));
+    sounds.insert("Sheep4".to_owned(), asset_server.load("Sounds/Sheep4.ogg"));
+    sounds.insert("IceCrack1".to_owned(), asset_server.load("Sounds/IceCrack1.ogg"));
+    sounds.insert("IceCrack2".to_owned(), asset_server.load("Sounds/IceCrack2.ogg"));
     sounds.insert("ChickenFly1".to_owned(), asset_server.load("Sounds/ChickenFly1.ogg"));
     sounds.insert("ChickenFly2".to_owned(), asset_server.load("Sounds/ChickenFly2.ogg"));
     sounds.insert("ChickenFly3".to_owned(), asset_server.load("Sounds/ChickenFly3.ogg"));
     sounds.insert("ChickenFly4".to_owned(), asset_server.load("Sounds/ChickenFly4.ogg"));
+    sounds.insert("Thunder1".to_owned(), asset_server.load("Sounds/Thunder1.ogg"));
+    sounds.insert("Thunder2".to_owned(), asset_server.load("Sounds/Thunder2.ogg"));
+    sounds.insert("Thunder3".to_owned(), asset_server.load("Sounds/Thunder3.ogg"));
+    sounds.insert("AmbienceDay".to_owned(), asset_server.load("Sounds/Ambience/Day.ogg"));
+    sounds.insert("AmbienceWind".to_owned(), asset_server.load("Sounds/Ambience/Wind.ogg"));
+    sounds.insert("AmbienceRain".to_owned(), asset_server.load("Sounds/Ambience/Rain.ogg"));
+    sounds.insert("AmbienceNight".to_owned(), asset_server.load("Sounds/Ambience/Night.ogg"));
+    sounds.insert("AmbienceRainyNight".to_owned(), asset_server.load("Sounds/Ambience/RainyNight.ogg"));
+    sounds.insert("AmbienceStorm".to_owned(), asset_server.load("Sounds/Ambience/Storm.ogg"));
     commands.insert_resource(Sounds { sounds });
     
     
@@ -521,7 +437,7 @@ fn setup(
     }, Cursor{holding: GameObjectType::None, drag_drop: CursorState::Idle, painting: false, starting_pos: Vec2::splat(-100.0), pos: Vec2::splat(-100.0)})
     ).with_children(|parent| {
         parent.spawn((AtlasImageBundle {
-            texture_atlas: texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-Flags.png"), Vec2::new(32.0, 32.0), 4, 24, None, None)),
+            texture_atlas: texture_atlases.add(TextureAtlas::from_grid(asset_server.load("Sprites/Misc/sokobarn-Flags.png"), Vec2::new(32.0, 32.0), 4, 36, None, None)),
             texture_atlas_image: UiTextureAtlasImage{index:0,..default()},
             style: Style {
                 position_type: PositionType::Absolute,
@@ -590,32 +506,48 @@ fn resize_system(mut object_set: ParamSet<(
         Query<(&mut Transform, &GameEntity)>,
         Query<(&mut Transform, &Tile)>,
         Query<(&mut Transform, &Depth)>,
-        Query<(&mut Transform, &Scaling)>,)>,
+        Query<(&mut Transform, &Scaling)>,
+        Query<(&mut Transform, &Flag)>,)>,
     windows: Query<&Window>,
+    field: Option<Res<Field>>,
+    settings: Res<Settings>,
     mut ui_scale: ResMut<UiScale>,){
+    let view = if let Some(field) = field {field.view()} else {BoardView::default()};
     for window in &windows {
-        let size = (window.width()/ASPECT_RATIO_W).min(window.height()/ASPECT_RATIO_H)/TILE_SIZE;
+        let size = world_scale(window);
+        let board_size = size * view.zoom;
         for (mut transform, game_entity) in &mut object_set.p0().iter_mut() {
-            transform.scale = Vec3::splat(size);
+            let position = view.to_play_area(game_entity.location.x as f32, game_entity.location.y as f32);
+            transform.scale = Vec3::splat(board_size);
             transform.translation = transform.translation.lerp(Vec3{ 
-                x: (game_entity.location.x as f32 - TILE_OFFSET_X)*TILE_SIZE*size, 
-                y: (game_entity.location.y as f32 - TILE_OFFSET_Y)*TILE_SIZE*size, 
+                x: (position.x - TILE_OFFSET_X)*TILE_SIZE*size, 
+                y: (position.y - TILE_OFFSET_Y)*TILE_SIZE*size, 
                 z: -(game_entity.location.y as f32) * 4.0 + -(game_entity.location.x as f32)*0.1 + game_entity.location.z as f32 
             }, if transform.translation.z == -10000.0 {1.0} else {0.2});
         }
         for (mut transform, tile) in &mut object_set.p1().iter_mut() {
-            transform.scale = Vec3::splat(size);
+            let position = view.to_play_area(tile.location.x as f32, tile.location.y as f32);
+            transform.scale = Vec3::splat(board_size);
             transform.translation = Vec3{ 
-                x: (tile.location.x as f32 - TILE_OFFSET_X)*TILE_SIZE*size, 
-                y: (tile.location.y as f32 - TILE_OFFSET_Y)*TILE_SIZE*size, 
+                x: (position.x - TILE_OFFSET_X)*TILE_SIZE*size, 
+                y: (position.y - TILE_OFFSET_Y)*TILE_SIZE*size, 
                 z: -(tile.location.y as f32) * 4.0 + -(tile.location.x as f32)*0.1 + tile.location.z as f32 
             };
         }
+        for (mut transform, flag) in &mut object_set.p4().iter_mut() {
+            let position = view.to_play_area(flag.location.x as f32, flag.location.y as f32);
+            transform.scale = Vec3::splat(board_size);
+            transform.translation = Vec3{ 
+                x: (position.x - TILE_OFFSET_X)*TILE_SIZE*size, 
+                y: (position.y - TILE_OFFSET_Y)*TILE_SIZE*size, 
+                z: -(flag.location.y as f32) * 4.0 + -(flag.location.x as f32)*0.1 + flag.location.z as f32 
+            };
+        }
         for (mut transform, depth) in &mut object_set.p2().iter_mut() {
             transform.translation = Vec3{ 
                 x: transform.translation.x, 
                 y: transform.translation.y, 
-                z: depth.depth/size
+                z: depth.depth/board_size
             };
         }
         for (mut transform, scaling_obj) in &mut object_set.p3().iter_mut() {
@@ -626,19 +558,31 @@ fn resize_system(mut object_set: ParamSet<(
                 z: transform.translation.z
             };
         }
-        ui_scale.scale = size as f64;
+        ui_scale.scale = (size * settings.ui_scale) as f64;
     }
 }
 
+//How much the 16x9 tile screen is blown up to fit the window, the UI scale setting doesn't touch it
+pub fn world_scale(window: &Window) -> f32 {
+    return (window.width()/ASPECT_RATIO_W).min(window.height()/ASPECT_RATIO_H)/TILE_SIZE;
+}
+
 pub fn cursor(
     q_windows: Query<&Window, With<PrimaryWindow>>, 
     mut q_cursor: Query<(&mut Cursor, &mut Style, &Children)>, 
-    mut q_held_item: Query<(&CursorObj, &mut UiTextureAtlasImage, &mut Handle<TextureAtlas>, &mut Visibility)>, 
+    mut q_held_item: Query<(&CursorObj, &mut UiTextureAtlasImage, &mut Handle<TextureAtlas>, &mut Visibility, &mut BackgroundColor)>, 
     buttons: Res<Input<MouseButton>>,
     ui_scale: Res<UiScale>,
+    grid: Res<GridCursor>,
+    field: Option<Res<Field>>,
     sprites: Res<Sprites>,){
     if let Ok(window) = q_windows.get_single() {
-        if let Some(position) = window.cursor_position() {
+        //Held food follows the keyboard/gamepad cursor while it's in use
+        let position = match (&field, grid.active) {
+            (Some(field), true) => Some(grid.window_position(field, window, world_scale(window))),
+            _ => window.cursor_position(),
+        };
+        if let Some(position) = position {
             if let Ok((mut cursor, mut style, children)) = q_cursor.get_single_mut() {
                 style.left = Val::Px(position.x/(ui_scale.scale as f32)-32.0);
                 style.top = Val::Px(position.y/(ui_scale.scale as f32)-32.0);
@@ -649,7 +593,7 @@ pub fn cursor(
                 }
                 
                 for &child in children.iter() {
-                    if let Ok((obj_type, mut sprite, mut atlas, mut visible)) = q_held_item.get_mut(child) {
+                    if let Ok((obj_type, mut sprite, mut atlas, mut visible, mut tint)) = q_held_item.get_mut(child) {
                         match obj_type.index {
                             0 => {
                                 if cursor.holding == GameObjectType::None {
@@ -668,15 +612,16 @@ pub fn cursor(
                             }
                             1 => {
                                 *visible = Visibility::Visible;
+                                *tint = cursor.holding.tint().into();
                                 
                                 match cursor.holding {
                                     GameObjectType::Entity(entity) => {
                                         *atlas = entity.texture_atlas(&sprites);
                                         sprite.index = entity.texture_index();
                                     }
-                                    GameObjectType::Tile(tile) => {
-                                        *atlas = tile.icon_atlas(&sprites);
-                                        sprite.index = tile.icon_index();
+                                    GameObjectType::Tile(_) | GameObjectType::Pen(_) => {
+                                        *atlas = cursor.holding.icon_atlas(&sprites);
+                                        sprite.index = cursor.holding.icon_index();
                                     }
                                     _ => {
                                         *visible = Visibility::Hidden;
@@ -694,9 +639,17 @@ pub fn cursor(
 
 pub fn animation_system(
     mut q_entities: Query<(&mut TextureAtlasSprite, &mut AnimationTimer, &mut GameEntity)>,
+    mut q_flags: Query<(&mut TextureAtlasSprite, &mut AnimationTimer, &Flag), Without<GameEntity>>,
+    settings: Res<Settings>,
     time: Res<Time>,){
+    for (mut sprite, mut timer, flag) in &mut q_flags {
+        timer.tick(time.delta().mul_f32(settings.animation_speed));
+        if timer.just_finished() {
+            sprite.index = flag.index * 4 + (sprite.index + 1) % 4;
+        }
+    }
     for (mut sprite, mut timer, mut entity) in &mut q_entities {
-        timer.tick(time.delta());
+        timer.tick(time.delta().mul_f32(settings.animation_speed));
         if timer.just_finished() {
             let offset = 4 *
             match entity.state {
@@ -740,9 +693,10 @@ pub fn animation_system(
 pub fn effect_system(
     mut commands: Commands,
     mut q_entities: Query<(Entity, &mut TextureAtlasSprite, &mut Effect)>,
+    settings: Res<Settings>,
     time: Res<Time>,){
     for (effect, mut sprite, mut timer) in &mut q_entities {
-        timer.tick(time.delta());
+        timer.tick(time.delta().mul_f32(settings.animation_speed));
         if timer.just_finished() {
             sprite.index = sprite.index + 1;
             if sprite.index >= 4 {
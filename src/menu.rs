@@ -1,7 +1,11 @@
 use crate::*;
 
 use bevy::prelude::*;
-use bevy::audio::{Volume, VolumeLevel};
+use bevy::reflect::{TypePath, TypeUuid};
+
+use serde::Deserialize;
+
+use std::fs;
 
 
 #[derive(Component)]
@@ -18,6 +22,12 @@ pub struct ButtonDisabled {
     entity: EntityType
 }
 
+//How many more of a food the tray has left, only shown when the level hands out more than one
+#[derive(Component)]
+pub struct TrayCount {
+    entity: EntityType
+}
+
 #[derive(Component)]
 pub struct Description{
     pub part: usize
@@ -35,6 +45,14 @@ pub struct CreditsButton;
 #[derive(Component)]
 pub struct ParText;
 
+#[derive(Component)]
+pub struct SaveNameText;
+
+#[derive(Component)]
+pub struct SettingsScreen;
+
+pub static LEVELS_PER_PAGE: usize = 6;
+
 #[derive(Resource)]
 pub struct MenuData {
     pub button_entities: Vec<Entity>,
@@ -47,18 +65,34 @@ pub struct WorldList {
     pub index: usize
 }
 
+//Worlds and levels in the order the level select shows them, from Levels/main.campaign.json
+#[derive(Deserialize)]
+#[derive(TypePath)]
+#[derive(TypeUuid)]
+#[uuid = "473c64ae-542f-4d15-a37a-3664863257a4"]
+pub struct Campaign {
+    pub worlds: Vec<LevelWorld>
+}
+
+#[derive(Deserialize)]
+#[derive(Clone)]
 pub struct LevelWorld {
     pub name: String,
+    #[serde(default)]
+    pub unlock_req: usize,
     pub levels: Vec<LevelData>
 }
 
 #[derive(Default)]
 #[derive(Clone)]
+#[derive(Deserialize)]
+#[serde(default)]
 pub struct LevelData {
     pub name: String,
     pub id: String,
     pub par: usize,
     pub author_par: usize,
+    #[serde(skip)]
     pub record: usize,
     pub unlock_req: usize,
     pub weather: WeatherType,
@@ -69,7 +103,13 @@ pub struct LevelData {
 #[derive(Resource)]
 pub struct PauseMenuData {
     pub button_entities: Vec<Entity>,
-    pub mode: PauseMenuMode
+    pub mode: PauseMenuMode,
+    //Save As dialog and level list
+    pub save_name: String,
+    pub page: usize,
+    pub confirm_delete: Option<String>,
+    //Save As onto a name that's already taken waits for a second press
+    pub confirm_overwrite: Option<String>,
 }
 
 #[derive(Resource)]
@@ -77,7 +117,14 @@ pub struct PauseMenuData {
 pub struct SaveRes {
     pub saving: SaveStage,
     pub save: String,
-    pub quicksaves: Vec<(String, SimulateRes)>,
+    //Whole boards rather than saves, so every animal's state and heading comes back exactly as it was
+    pub quicksaves: Vec<(Board, SimulateRes)>,
+    //States taken off quicksaves by Undo, cleared as soon as something new happens
+    pub redos: Vec<(Board, SimulateRes)>,
+    //Named editor level being worked on, Save writes back to it
+    pub slot: Option<String>,
+    //Loaded instead of the file at save, used to start replays
+    pub load_from: Option<SaveFile>,
     pub editor_mode: Option<bool>,
     pub weather: Option<WeatherType>,
     pub song: Option<String>,
@@ -90,7 +137,9 @@ pub enum PauseMenuMode{
     Pause,
     Editor,
     Win,
-    Lose
+    Lose,
+    SaveAs,
+    Levels
 }
 
 #[derive(PartialEq)]
@@ -101,6 +150,8 @@ pub enum SaveStage{
     Loading,
     SaveUndo,
     Undo,
+    Redo,
+    Resize(usize, usize),
 }
 
 #[derive(PartialEq)]
@@ -128,23 +179,31 @@ pub enum ButtonEffect{
     Credits,
     ExitCredits,
     EditorPageLeft,
-    EditorPageRight
+    EditorPageRight,
+    SaveAs,
+    ConfirmSaveAs,
+    EditorLevels,
+    EditorMenu,
+    DuplicateLevel,
+    DeleteLevel,
+    LevelsPageUp,
+    LevelsPageDown,
+    ResizeField(i32, i32),
+    WatchReplay,
+    SaveReplay,
+    ReplaySpeed(bool),
+    Redo,
+    Rebind(InputAction),
+    ResetControls,
+    Controls,
+    ChangeSetting(SettingChange),
+    CloseSettings
 }
 
-pub fn menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, ui_images: Res<UIImages>, music: Res<GameMusic>, music_player: Query<Entity, With<MusicPlayer>>, mut keyart_q: Query<&mut Visibility, With<KeyArt>>) {
+pub fn menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, ui_images: Res<UIImages>, mut audio: ResMut<AudioManager>, mut keyart_q: Query<&mut Visibility, With<KeyArt>>) {
 
-    for player in &music_player {
-        commands.entity(player).despawn();
-    }
-    commands.spawn((AudioBundle {
-        settings: PlaybackSettings{
-            mode: PlaybackMode::Loop,
-            volume: Volume::Absolute(VolumeLevel::new(0.75)),
-            ..default()
-        },
-        source: music.songs["Song 1"].to_owned(),
-        ..default()
-    }, MusicPlayer));
+    audio.play_song("Song 1");
+    audio.play_ambience(None);
 
     if let Ok(mut visibility) = keyart_q.get_single_mut() {
         *visibility = Visibility::Visible;
@@ -229,6 +288,7 @@ pub fn menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, ui_ima
                     ));
                 });
             });
+            sign_button(parent, &image, &text_style, 160.0, ButtonEffect::Settings, None, "Settings");
             parent.spawn((ButtonBundle {
                 style: Style {
                     width: Val::Px(160.0),
@@ -337,8 +397,6 @@ pub fn level_select_setup(
     };
 
     let image = ui_images.sprites["UISign"].to_owned();
-    
-    let world = &world_data.worlds[world_data.index];
 
     let backid = 
     {
@@ -394,6 +452,13 @@ pub fn level_select_setup(
         back.id()
     };
 
+    //The campaign is still loading, it reloads this screen once it arrives
+    let Some(world) = world_data.worlds.get(world_data.index) else {
+        menu_data.button_entities = vec![backid];
+        return;
+    };
+    let medal_count = medals.total();
+
     let mut menu = commands.spawn(NodeBundle {
         style: Style {
             width: Val::Percent(100.0),
@@ -443,7 +508,7 @@ pub fn level_select_setup(
             })
             .with_children(|parent| {
                 parent.spawn(TextBundle::from_section(
-                    world.name.as_str(),
+                    if medal_count < world.unlock_req {format!("{} (Locked)", world.name)} else {world.name.to_owned()},
                     text_style.to_owned()
                 ));
             });
@@ -502,6 +567,7 @@ pub fn level_select_setup(
     });
 
     for level in &world.levels {
+        let locked = medal_count < level.unlock_req;
         menu.with_children(|parent| {
             parent.spawn((ButtonBundle {
                 style: Style {
@@ -535,19 +601,28 @@ pub fn level_select_setup(
                         align_items: AlignItems::Center,
                         ..Default::default()
                     },
-                    background_color: Color::WHITE.into(),
+                    background_color: if locked {Color::GRAY.into()} else {Color::WHITE.into()},
                     ..Default::default()
                 })
                 .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        level.name.to_owned(),
-                        text_style.to_owned()
-                    ));
-                    parent.spawn(AtlasImageBundle {
-                        texture_atlas: sprites.sprites["Medals"].to_owned(),
-                        texture_atlas_image: UiTextureAtlasImage{index:medals.medals[&level.id],..default()},
-                        ..default()
-                    });
+                    if locked {
+                        parent.spawn(TextBundle::from_section(
+                            format!("Locked: {} medals", level.unlock_req),
+                            text_style.to_owned()
+                        ));
+                    } else {
+                        parent.spawn(TextBundle::from_section(
+                            level.name.to_owned(),
+                            text_style.to_owned()
+                        ));
+                        if let Some(&medal) = medals.medals.get(&level.id) {
+                            parent.spawn(AtlasImageBundle {
+                                texture_atlas: sprites.sprites["Medals"].to_owned(),
+                                texture_atlas_image: UiTextureAtlasImage{index:medal,..default()},
+                                ..default()
+                            });
+                        }
+                    }
                 });
             });
         });
@@ -607,7 +682,9 @@ pub fn level_select_setup(
     menu_data.button_entities = vec![backid, menu.id()];
 }
 
-pub fn pause_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, ui_images: Res<UIImages>, sprites: Res<Sprites>, field: Res<Field>, simulating: Res<SimulateRes>, mut pause_menu_data: ResMut<PauseMenuData>) {
+pub fn pause_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, ui_images: Res<UIImages>, sprites: Res<Sprites>, field: Res<Field>, simulating: Res<SimulateRes>, mut pause_menu_data: ResMut<PauseMenuData>, mut reload_pause_menu: ResMut<ReloadPauseMenu>, recorder: Res<ReplayRecorder>, replay_player: Res<ReplayPlayer>) {
+    *reload_pause_menu = ReloadPauseMenu(false);
+
     let text_style = TextStyle {
         font: asset_server.load("Fonts/MessyThicc.ttf"),
         font_size: 20.0,
@@ -700,6 +777,17 @@ pub fn pause_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>,
                     });
                 });
             }
+            if pause_menu_data.mode == PauseMenuMode::Pause {
+                if let Some(description) = field.meta.as_ref().and_then(|meta| meta.describe()) {
+                    parent.spawn(TextBundle::from_section(
+                        description,
+                        text_style.to_owned()
+                    ).with_style(Style {
+                        margin: UiRect::bottom(Val::Px(10.0)),
+                        ..default()
+                    }));
+                }
+            }
             parent.spawn((ButtonBundle {
                 style: Style {
                     width: Val::Px(160.0),
@@ -885,6 +973,7 @@ pub fn pause_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>,
                         ));
                     });
                 });
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::SaveAs, None, "Save As");
                 parent.spawn((ButtonBundle {
                     style: Style {
                         width: Val::Px(160.0),
@@ -901,7 +990,7 @@ pub fn pause_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>,
                     ..default()
                 }, 
                 MenuButton{
-                    button_effect: ButtonEffect::Load,
+                    button_effect: ButtonEffect::EditorLevels,
                     level: None,
                     hovering: false, 
                     hover_time: 0.0,
@@ -925,12 +1014,136 @@ pub fn pause_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>,
                     })
                     .with_children(|parent| {
                         parent.spawn(TextBundle::from_section(
-                            "Load",
+                            "Levels",
                             text_style.to_owned()
                         ));
                     });
                 });
             }
+            if (pause_menu_data.mode == PauseMenuMode::Win || pause_menu_data.mode == PauseMenuMode::Lose) && (recorder.replay.is_some() || replay_player.replay.is_some()) {
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::WatchReplay, None, "Watch Replay");
+                if !ONLINE_BUILD {
+                    sign_button(parent, &image, &text_style, 160.0, ButtonEffect::SaveReplay, None, "Save Replay");
+                }
+            }
+            if pause_menu_data.mode == PauseMenuMode::Pause {
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::Settings, None, "Settings");
+            }
+            if pause_menu_data.mode == PauseMenuMode::Pause && replay_player.replay.is_some() {
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(10.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    sign_button(parent, &image, &text_style, 40.0, ButtonEffect::ReplaySpeed(false), None, "-");
+                    parent.spawn(TextBundle::from_section(
+                        format!("Replay speed: x{}", replay_player.speed),
+                        text_style.to_owned()
+                    ).with_style(Style {
+                        margin: UiRect::bottom(Val::Px(10.0)),
+                        ..default()
+                    }));
+                    sign_button(parent, &image, &text_style, 40.0, ButtonEffect::ReplaySpeed(true), None, "+");
+                });
+            }
+            if pause_menu_data.mode == PauseMenuMode::Editor {
+                for (label, size, change) in [("Width", field.board.width, (1, 0)), ("Height", field.board.height, (0, 1))] {
+                    parent.spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(10.0),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        sign_button(parent, &image, &text_style, 40.0, ButtonEffect::ResizeField(-change.0, -change.1), None, "-");
+                        parent.spawn(TextBundle::from_section(
+                            format!("{}: {}", label, size),
+                            text_style.to_owned()
+                        ).with_style(Style {
+                            margin: UiRect::bottom(Val::Px(10.0)),
+                            ..default()
+                        }));
+                        sign_button(parent, &image, &text_style, 40.0, ButtonEffect::ResizeField(change.0, change.1), None, "+");
+                    });
+                }
+            }
+            if pause_menu_data.mode == PauseMenuMode::SaveAs {
+                parent.spawn(TextBundle::from_section(
+                    "Level name:",
+                    text_style.to_owned()
+                ));
+                parent.spawn(ImageBundle {
+                    image: UiImage::new(image.clone()),
+                    style: Style {
+                        width: Val::Px(240.0),
+                        height: Val::Px(32.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::bottom(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                    background_color: Color::WHITE.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((TextBundle::from_section(
+                        format!("{}_", pause_menu_data.save_name),
+                        text_style.to_owned()
+                    ), SaveNameText));
+                });
+                let save_label = if pause_menu_data.confirm_overwrite.is_some() {"Overwrite?"} else {"Save"};
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::ConfirmSaveAs, None, save_label);
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::EditorMenu, None, "Back");
+            }
+            if pause_menu_data.mode == PauseMenuMode::Levels {
+                let names = user_levels();
+                if names.is_empty() {
+                    parent.spawn(TextBundle::from_section(
+                        "No saved levels yet",
+                        text_style.to_owned()
+                    ).with_style(Style {
+                        margin: UiRect::bottom(Val::Px(10.0)),
+                        ..default()
+                    }));
+                }
+                if pause_menu_data.page > 0 {
+                    sign_button(parent, &image, &text_style, 160.0, ButtonEffect::LevelsPageUp, None, "Up");
+                }
+                for name in names.iter().skip(pause_menu_data.page * LEVELS_PER_PAGE).take(LEVELS_PER_PAGE) {
+                    let level = LevelData {
+                        name: name.to_owned(),
+                        id: user_level_path(name),
+                        editor: true,
+                        ..default()
+                    };
+                    parent.spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            column_gap: Val::Px(10.0),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        sign_button(parent, &image, &text_style, 240.0, ButtonEffect::Play, Some(level.to_owned()), name);
+                        sign_button(parent, &image, &text_style, 80.0, ButtonEffect::DuplicateLevel, Some(level.to_owned()), "Copy");
+                        let delete_label = if pause_menu_data.confirm_delete.as_ref() == Some(name) {"Sure?"} else {"Delete"};
+                        sign_button(parent, &image, &text_style, 80.0, ButtonEffect::DeleteLevel, Some(level), delete_label);
+                    });
+                }
+                if (pause_menu_data.page + 1) * LEVELS_PER_PAGE < names.len() {
+                    sign_button(parent, &image, &text_style, 160.0, ButtonEffect::LevelsPageDown, None, "Down");
+                }
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::EditorMenu, None, "Back");
+            }
             if pause_menu_data.mode == PauseMenuMode::Pause || pause_menu_data.mode == PauseMenuMode::Editor {
                 parent.spawn((ButtonBundle {
                     style: Style {
@@ -981,9 +1194,51 @@ pub fn pause_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>,
         }).id()];
 }
 
-pub fn get_buttons(field: &Res<Field>) -> Vec<ButtonEffect> {
+fn sign_button(parent: &mut ChildBuilder, image: &Handle<Image>, text_style: &TextStyle, width: f32, button_effect: ButtonEffect, level: Option<LevelData>, label: &str) {
+    parent.spawn((ButtonBundle {
+        style: Style {
+            width: Val::Px(width),
+            height: Val::Px(32.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::bottom(Val::Px(10.0)),
+            ..default()
+        },
+        background_color: Color::NONE.into(),
+        ..default()
+    },
+    MenuButton{
+        button_effect,
+        level,
+        hovering: false,
+        hover_time: 0.0,
+        ..default()
+    }))
+    .with_children(|parent| {
+        parent.spawn(ImageBundle {
+            image: UiImage::new(image.clone()),
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: Color::WHITE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                text_style.to_owned()
+            ));
+        });
+    });
+}
+
+pub fn get_buttons(field: &Field) -> Vec<ButtonEffect> {
     return if field.editor_mode {
-        vec![
+        let mut buttons = vec![
             ButtonEffect::Paint(GameObjectType::Entity(EntityType::Chicken)),
             ButtonEffect::Paint(GameObjectType::Entity(EntityType::Pig)),
             ButtonEffect::Paint(GameObjectType::Entity(EntityType::Horse)),
@@ -1006,14 +1261,39 @@ pub fn get_buttons(field: &Res<Field>) -> Vec<ButtonEffect> {
             ButtonEffect::Paint(GameObjectType::Entity(EntityType::HorseFood)),
             ButtonEffect::Paint(GameObjectType::Entity(EntityType::PigFood)),
             ButtonEffect::Paint(GameObjectType::Entity(EntityType::AllFood)),
-        ]
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::Duck)),
+            ButtonEffect::Paint(GameObjectType::Tile(TileType::DuckPen)),
+            ButtonEffect::Paint(GameObjectType::Tile(TileType::Water)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::DuckFood)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::Sheepdog)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::Sheep)),
+            ButtonEffect::Paint(GameObjectType::Tile(TileType::SheepPen)),
+            ButtonEffect::Paint(GameObjectType::Tile(TileType::Ice)),
+            ButtonEffect::Paint(GameObjectType::Tile(TileType::CrackedIce)),
+            ButtonEffect::Paint(GameObjectType::Tile(TileType::OneWay(MoveDirection::Right))),
+            ButtonEffect::Paint(GameObjectType::Tile(TileType::Conveyor(MoveDirection::Right))),
+
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::FlagChicken1)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::FlagPig1)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::FlagHorse1)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::FlagGoat1)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::FlagWagon1)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::FlagDuck1)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::FlagSheep1)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::FlagSheepdog1)),
+            ButtonEffect::Paint(GameObjectType::Entity(EntityType::Flag1)),
+        ];
+        //One button and one gate per channel
+        buttons.extend((0..CHANNELS).map(|channel| ButtonEffect::Paint(GameObjectType::Entity(EntityType::Button(channel)))));
+        buttons.extend((0..CHANNELS).map(|channel| ButtonEffect::Paint(GameObjectType::Tile(TileType::Gate(channel)))));
+        //Pen numbers share the channel colours
+        buttons.extend((0..CHANNELS).map(|pen| ButtonEffect::Paint(GameObjectType::Pen(pen))));
+        buttons
     } else {
-        vec![
-            ButtonEffect::PickUp(GameObjectType::Entity(EntityType::ChickenFood), true), 
-            ButtonEffect::PickUp(GameObjectType::Entity(EntityType::HorseFood), true),
-            ButtonEffect::PickUp(GameObjectType::Entity(EntityType::PigFood), true),
-            ButtonEffect::PickUp(GameObjectType::Entity(EntityType::AllFood), true),
-        ]
+        //Only the foods this level hands out
+        let mut foods = field.board.tray.clone();
+        foods.dedup();
+        foods.into_iter().map(|food| ButtonEffect::PickUp(GameObjectType::Entity(food), true)).collect()
     };
 }
 
@@ -1211,6 +1491,18 @@ pub fn game_ui_setup(mut commands: Commands,
                                                 background_color: Color::WHITE.into(),
                                                 ..Default::default()
                                             }, ButtonDisabled { entity: entity }));
+                                            if field.as_ref().map_or(false, |field| field.board.tray.iter().filter(|food| **food == entity).count() > 1) {
+                                                parent.spawn((TextBundle {
+                                                    text: Text::from_section("", smallish_text_style.to_owned()),
+                                                    style: Style {
+                                                        position_type: PositionType::Absolute,
+                                                        right: Val::Px(2.0),
+                                                        bottom: Val::Px(0.0),
+                                                        ..Default::default()
+                                                    },
+                                                    ..Default::default()
+                                                }, TrayCount { entity: entity }));
+                                            }
                                         }
                                     }
                                 });
@@ -1243,7 +1535,7 @@ pub fn game_ui_setup(mut commands: Commands,
                                             position_type: PositionType::Absolute,
                                             ..Default::default()
                                         },
-                                        background_color: Color::WHITE.into(),
+                                        background_color: tile_type.tint().into(),
                                         ..Default::default()
                                     });
                                 });
@@ -1440,7 +1732,7 @@ pub fn game_ui_setup(mut commands: Commands,
                             width: Val::Percent(100.0),
                             height: Val::Percent(100.0),
                             display: Display::Grid,
-                            grid_template_columns: vec![GridTrack::auto(), GridTrack::auto(), GridTrack::flex(1.0), GridTrack::auto(), GridTrack::flex(1.0), GridTrack::auto()],
+                            grid_template_columns: vec![GridTrack::auto(), GridTrack::auto(), GridTrack::auto(), GridTrack::flex(1.0), GridTrack::auto(), GridTrack::flex(1.0), GridTrack::auto()],
                             justify_content: JustifyContent::Center,
                             align_items: AlignItems::Center,
                             ..Default::default()
@@ -1500,6 +1792,32 @@ pub fn game_ui_setup(mut commands: Commands,
                                 text_style.to_owned()
                             ));
                         });
+                        parent.spawn((ButtonBundle {
+                            style: Style {
+                                width: Val::Px(96.0),
+                                height: Val::Px(32.0),
+                                //border: UiRect::all(Val::Px(5.0)),
+                                // horizontally center child text
+                                justify_content: JustifyContent::Center,
+                                // vertically center child text
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::NONE.into(),
+                            ..default()
+                        }, 
+                        MenuButton{
+                            button_effect: ButtonEffect::Redo,
+                            level: None,
+                            hovering: false, 
+                            hover_time: 0.0,
+                            ..default()
+                        })).with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Redo",
+                                text_style.to_owned()
+                            ));
+                        });
                         parent.spawn(TextBundle::from_section(
                             "",
                             text_style.to_owned()
@@ -1555,24 +1873,27 @@ pub fn button_system(
         (Changed<Interaction>, With<Button>),
     >,
     mut disabler_q: Query<(&mut Visibility, &ButtonDisabled)>,
-    entity_q: Query<&GameEntity>,
+    mut tray_count_q: Query<(&mut Text, &TrayCount), Without<RoundCounter>>,
     screencover_q: Query<Entity, Or<(With<CreditsButton>, With<TutorialButton>)>>,
     time: Res<Time>,
-    resmuts: (ResMut<NextState<GameState>>, ResMut<SaveRes>, ResMut<SimulateRes>, ResMut<ReloadLevelSelect>, ResMut<MenuData>, ResMut<PauseMenuData>, ResMut<ReloadGameUI>, ResMut<Events<bevy::app::AppExit>>, ResMut<WorldList>),
+    resmuts: (ResMut<NextState<GameState>>, ResMut<SaveRes>, ResMut<SimulateRes>, ResMut<ReloadLevelSelect>, ResMut<MenuData>, ResMut<PauseMenuData>, ResMut<ReloadGameUI>, ResMut<Events<bevy::app::AppExit>>, ResMut<WorldList>, ResMut<ReloadPauseMenu>),
     fieldopt: Option<Res<Field>>,
     mut cursor_q: Query<&mut Cursor>,
     mut round_counter_q: Query<&mut Text, With<RoundCounter>>,
     asset_server: Res<AssetServer>, 
+    medals: Res<Medals>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut replay_player: ResMut<ReplayPlayer>,
+    settings_res: (ResMut<SettingsMenu>, ResMut<ReloadSettingsMenu>, ResMut<InputMap>, ResMut<Settings>, ResMut<PkvStore>),
 ) {
-    let (mut next_state, mut saving, mut simulating, mut reload_level_select, mut menu_data, mut pause_menu_data, mut reload_game_ui, mut app_exit_events, mut world_data) = resmuts;
+    let (mut settings_menu, mut reload_settings_menu, mut input_map, mut settings, mut pkv) = settings_res;
+    let (mut next_state, mut saving, mut simulating, mut reload_level_select, mut menu_data, mut pause_menu_data, mut reload_game_ui, mut app_exit_events, mut world_data, mut reload_pause_menu) = resmuts;
+    let tray = fieldopt.as_ref().map_or(vec![], |field| field.board.refill_tray());
     for (mut visibility, disabler) in &mut disabler_q {
-        *visibility = Visibility::Hidden;
-        for entity in &entity_q {
-            if entity.entity_type == disabler.entity {
-                *visibility = Visibility::Visible;
-                break;
-            }
-        }
+        *visibility = if tray.contains(&disabler.entity) {Visibility::Hidden} else {Visibility::Visible};
+    }
+    for (mut text, tray_count) in &mut tray_count_q {
+        text.sections[0].value = tray.iter().filter(|food| **food == tray_count.entity).count().to_string();
     }
     for (interaction, mut menu_button) in &mut interaction_query {
         match *interaction {
@@ -1586,22 +1907,31 @@ pub fn button_system(
                     ButtonEffect::UnPause => {next_state.set(GameState::Gameplay);}
                     ButtonEffect::LevelSelect => {next_state.set(GameState::LevelSelect);}
                     ButtonEffect::Play => {
+                        if let Some(level) = &menu_button.level {
+                            //Locked levels already say how many medals they need on the button
+                            if medals.total() < level.unlock_req {
+                                continue;
+                            }
+                        }
                         next_state.set(GameState::Gameplay);
                         saving.saving = SaveStage::Loading;
                         if let Some(level) = &menu_button.level {
                             saving.save = level.id.to_owned();
                             saving.editor_mode = Some(level.editor);
                             saving.weather = Some(level.weather);
-                            saving.song = Some(level.song.to_owned());
+                            //Editor levels have no song of their own, so whatever is playing carries on
+                            saving.song = if level.song.is_empty() {None} else {Some(level.song.to_owned())};
+                            saving.slot = user_level_name(&level.id);
                             saving.par = level.par;
                             saving.author_par = level.author_par;
                         }
                     }
                     ButtonEffect::Quit => {app_exit_events.send(bevy::app::AppExit);}
                     ButtonEffect::Start => {
-                        if simulating.simulating == false && !simulating.loss && !simulating.win {
+                        if simulating.simulating == false && !simulating.loss && !simulating.win && replay_player.replay.is_none() {
                             simulating.simulating = true;
                             simulating.rounds = simulating.rounds + 1;
+                            recorder.record(ReplayAction::Start { round: simulating.rounds });
                             simulating.loss = false;
                             simulating.win = false;
                             if let Ok(mut round_counter) = round_counter_q.get_single_mut() {
@@ -1610,8 +1940,92 @@ pub fn button_system(
                         }
                     }
                     ButtonEffect::Save => {
-                        saving.saving = SaveStage::Saving;
-                        saving.save = "level.skb".to_owned();
+                        if saving.slot.is_some() {
+                            saving.saving = SaveStage::Saving;
+                        } else {
+                            pause_menu_data.mode = PauseMenuMode::SaveAs;
+                            pause_menu_data.save_name = "".to_owned();
+                            pause_menu_data.confirm_overwrite = None;
+                            *reload_pause_menu = ReloadPauseMenu(true);
+                        }
+                    }
+                    ButtonEffect::SaveAs => {
+                        pause_menu_data.mode = PauseMenuMode::SaveAs;
+                        pause_menu_data.save_name = saving.slot.to_owned().unwrap_or_default();
+                        pause_menu_data.confirm_overwrite = None;
+                        *reload_pause_menu = ReloadPauseMenu(true);
+                    }
+                    ButtonEffect::ConfirmSaveAs => {
+                        if confirm_save_as(&mut pause_menu_data, &mut saving) {
+                            next_state.set(GameState::Gameplay);
+                        } else {
+                            *reload_pause_menu = ReloadPauseMenu(true);
+                        }
+                    }
+                    ButtonEffect::EditorLevels => {
+                        pause_menu_data.mode = PauseMenuMode::Levels;
+                        pause_menu_data.page = 0;
+                        pause_menu_data.confirm_delete = None;
+                        *reload_pause_menu = ReloadPauseMenu(true);
+                    }
+                    ButtonEffect::EditorMenu => {
+                        pause_menu_data.mode = PauseMenuMode::Editor;
+                        *reload_pause_menu = ReloadPauseMenu(true);
+                    }
+                    ButtonEffect::DuplicateLevel => {
+                        if let Some(level) = &menu_button.level {
+                            let names = user_levels();
+                            let mut copy_name = format!("{} copy", level.name);
+                            let mut copies = 1;
+                            while names.contains(&copy_name) {
+                                copies += 1;
+                                copy_name = format!("{} copy {}", level.name, copies);
+                            }
+                            if let Err(error) = fs::copy(&level.id, user_level_path(&copy_name)) {
+                                println!("Could not copy {}: {}", level.name, error);
+                            }
+                            pause_menu_data.confirm_delete = None;
+                            *reload_pause_menu = ReloadPauseMenu(true);
+                        }
+                    }
+                    ButtonEffect::DeleteLevel => {
+                        if let Some(level) = &menu_button.level {
+                            //First press asks, second press deletes
+                            if pause_menu_data.confirm_delete.as_ref() == Some(&level.name) {
+                                if let Err(error) = fs::remove_file(&level.id) {
+                                    println!("Could not delete {}: {}", level.name, error);
+                                }
+                                if saving.slot.as_ref() == Some(&level.name) {
+                                    saving.slot = None;
+                                }
+                                pause_menu_data.confirm_delete = None;
+                                if pause_menu_data.page * LEVELS_PER_PAGE >= user_levels().len() && pause_menu_data.page > 0 {
+                                    pause_menu_data.page -= 1;
+                                }
+                            } else {
+                                pause_menu_data.confirm_delete = Some(level.name.to_owned());
+                            }
+                            *reload_pause_menu = ReloadPauseMenu(true);
+                        }
+                    }
+                    ButtonEffect::ResizeField(width_change, height_change) => {
+                        if let Some(field) = &fieldopt {
+                            let width = (field.board.width as i32 + width_change).max(0) as usize;
+                            let height = (field.board.height as i32 + height_change).max(0) as usize;
+                            saving.saving = SaveStage::Resize(width, height);
+                        }
+                    }
+                    ButtonEffect::LevelsPageUp => {
+                        if pause_menu_data.page > 0 {
+                            pause_menu_data.page -= 1;
+                            *reload_pause_menu = ReloadPauseMenu(true);
+                        }
+                    }
+                    ButtonEffect::LevelsPageDown => {
+                        if (pause_menu_data.page + 1) * LEVELS_PER_PAGE < user_levels().len() {
+                            pause_menu_data.page += 1;
+                            *reload_pause_menu = ReloadPauseMenu(true);
+                        }
                     }
                     ButtonEffect::Load => {
                         saving.saving = SaveStage::Loading;
@@ -1624,22 +2038,43 @@ pub fn button_system(
                         saving.saving = SaveStage::Loading;
                     }
                     ButtonEffect::Undo => {
-                        next_state.set(GameState::Gameplay);
-                        simulating.loss = false;
-                        simulating.win = false;
-                        saving.saving = SaveStage::Undo;
+                        if replay_player.replay.is_none() {
+                            next_state.set(GameState::Gameplay);
+                            simulating.loss = false;
+                            simulating.win = false;
+                            saving.saving = SaveStage::Undo;
+                            recorder.record(ReplayAction::Undo { round: simulating.rounds });
+                        }
+                    }
+                    ButtonEffect::Redo => {
+                        if replay_player.replay.is_none() && !simulating.simulating && !saving.redos.is_empty() {
+                            saving.saving = SaveStage::Redo;
+                            recorder.record(ReplayAction::Redo { round: simulating.rounds });
+                        }
+                    }
+                    ButtonEffect::WatchReplay => {
+                        if let Some(replay) = replay_player.replay.to_owned().or(recorder.replay.to_owned()) {
+                            simulating.loss = false;
+                            simulating.win = false;
+                            replay_player.play(replay, &mut saving);
+                            next_state.set(GameState::Gameplay);
+                        }
+                    }
+                    ButtonEffect::SaveReplay => {
+                        if let Some(replay) = replay_player.replay.as_ref().or(recorder.replay.as_ref()) {
+                            save_replay(replay);
+                        }
+                    }
+                    ButtonEffect::ReplaySpeed(faster) => {
+                        replay_player.speed = if faster {(replay_player.speed * 2.0).min(8.0)} else {(replay_player.speed / 2.0).max(0.25)};
+                        *reload_pause_menu = ReloadPauseMenu(true);
                     }
                     ButtonEffect::PickUp(pickup_object, limited) => {
                         if let Ok(mut cursor) = cursor_q.get_single_mut() {
                             let mut can_pick = true;
                             if let GameObjectType::Entity(entity_type) = pickup_object {
                                 if limited {
-                                    for entity in &entity_q {
-                                        if entity.entity_type == entity_type {
-                                            can_pick = false;
-                                            break;
-                                        }
-                                    }
+                                    can_pick = tray.contains(&entity_type);
                                 }
                                 if can_pick {
                                     cursor.holding = GameObjectType::Entity(entity_type);
@@ -1658,9 +2093,38 @@ pub fn button_system(
                             cursor.painting = true;
                         }
                     }
-                    ButtonEffect::Settings => {}
+                    ButtonEffect::Settings => {
+                        settings_menu.page = SettingsPage::Settings;
+                        settings_menu.listening = None;
+                        *reload_settings_menu = ReloadSettingsMenu(true);
+                    }
+                    ButtonEffect::Controls => {
+                        settings_menu.page = SettingsPage::Controls;
+                        settings_menu.listening = None;
+                        *reload_settings_menu = ReloadSettingsMenu(true);
+                    }
+                    ButtonEffect::ChangeSetting(change) => {
+                        settings.change(change);
+                        settings.save(&mut pkv);
+                        *reload_settings_menu = ReloadSettingsMenu(true);
+                    }
+                    ButtonEffect::Rebind(action) => {
+                        settings_menu.listening = Some(action);
+                        *reload_settings_menu = ReloadSettingsMenu(true);
+                    }
+                    ButtonEffect::ResetControls => {
+                        *input_map = InputMap::default();
+                        pkv.set("controls", &input_map.to_owned()).expect("failed to store controls");
+                        settings_menu.listening = None;
+                        *reload_settings_menu = ReloadSettingsMenu(true);
+                    }
+                    ButtonEffect::CloseSettings => {
+                        settings_menu.page = SettingsPage::Closed;
+                        settings_menu.listening = None;
+                        *reload_settings_menu = ReloadSettingsMenu(true);
+                    }
                     ButtonEffect::NextWorld => {
-                        if world_data.index < world_data.worlds.len() - 1 {
+                        if world_data.index + 1 < world_data.worlds.len() {
                             world_data.index += 1;
                             reload_level_select.reloading = true;
                         }
@@ -1776,6 +2240,118 @@ pub fn button_update_system(
     }
 }
 
+pub fn campaign_system(
+    mut campaign_events: EventReader<AssetEvent<Campaign>>,
+    campaigns: Res<Assets<Campaign>>,
+    asset_server: Res<AssetServer>,
+    mut levels: ResMut<Levels>,
+    mut world_data: ResMut<WorldList>,
+    mut medals: ResMut<Medals>,
+    mut reload_level_select: ResMut<ReloadLevelSelect>,
+) {
+    for event in campaign_events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if *handle != levels.campaign {
+                    continue;
+                }
+                let Some(campaign) = campaigns.get(handle) else {
+                    continue;
+                };
+                levels.levels = HashMap::new();
+                for world in &campaign.worlds {
+                    for level in &world.levels {
+                        levels.levels.insert(level.id.to_owned(), asset_server.load(level.id.as_str()));
+                        //The editor level holds whatever puzzle is being made, so it has no par to earn medals against
+                        if level.editor {
+                            medals.medals.remove(&level.id);
+                        } else {
+                            medals.medals.entry(level.id.to_owned()).or_insert(0);
+                        }
+                    }
+                }
+                world_data.worlds = campaign.worlds.to_owned();
+                //A level can't be opened before its world is, except the editor, which is always open
+                for world in &mut world_data.worlds {
+                    for level in &mut world.levels {
+                        if level.editor {
+                            continue;
+                        }
+                        level.unlock_req = level.unlock_req.max(world.unlock_req);
+                    }
+                }
+                if world_data.index >= world_data.worlds.len() {
+                    world_data.index = 0;
+                }
+                reload_level_select.reloading = true;
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+}
+
+//Typing for the Save As dialog, Enter saves
+pub fn save_name_system(
+    mut characters: EventReader<ReceivedCharacter>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut pause_menu_data: ResMut<PauseMenuData>,
+    mut saving: ResMut<SaveRes>,
+    mut reload_pause_menu: ResMut<ReloadPauseMenu>,
+    mut name_q: Query<&mut Text, With<SaveNameText>>,
+) {
+    if pause_menu_data.mode != PauseMenuMode::SaveAs {
+        characters.clear();
+        return;
+    }
+    for character in characters.iter() {
+        match character.char {
+            '\u{8}' => {
+                pause_menu_data.save_name.pop();
+            }
+            '\r' | '\n' => {
+                if confirm_save_as(&mut pause_menu_data, &mut saving) {
+                    next_state.set(GameState::Gameplay);
+                    return;
+                }
+                *reload_pause_menu = ReloadPauseMenu(true);
+                continue;
+            }
+            letter if letter.is_alphanumeric() || letter == ' ' || letter == '-' || letter == '_' => {
+                if pause_menu_data.save_name.chars().count() < 24 {
+                    pause_menu_data.save_name.push(letter);
+                }
+            }
+            _ => {
+                continue;
+            }
+        }
+        //A new name needs asking about again
+        if pause_menu_data.confirm_overwrite.take().is_some() {
+            *reload_pause_menu = ReloadPauseMenu(true);
+        }
+    }
+    for mut text in &mut name_q {
+        text.sections[0].value = format!("{}_", pause_menu_data.save_name);
+    }
+}
+
+fn confirm_save_as(pause_menu_data: &mut PauseMenuData, saving: &mut SaveRes) -> bool {
+    let name = pause_menu_data.save_name.trim().to_owned();
+    if name.is_empty() {
+        return false;
+    }
+    //Writing over some other saved level asks first, the same as deleting one
+    if saving.slot.as_ref() != Some(&name) && user_levels().contains(&name) && pause_menu_data.confirm_overwrite.as_ref() != Some(&name) {
+        pause_menu_data.confirm_overwrite = Some(name);
+        return false;
+    }
+    pause_menu_data.confirm_overwrite = None;
+    saving.slot = Some(name);
+    saving.saving = SaveStage::Saving;
+    pause_menu_data.mode = PauseMenuMode::Editor;
+    return true;
+}
+
 pub fn menu_cleanup(
     mut commands: Commands,
     mut menu_data: ResMut<MenuData>,
@@ -1788,6 +2364,152 @@ pub fn menu_cleanup(
     menu_data.button_entities = vec![];
 }
 
+//Settings page, or every action with what it's bound to, click one and press something to rebind it
+pub fn settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, ui_images: Res<UIImages>, input_map: Res<InputMap>, settings: Res<Settings>, settings_menu: Res<SettingsMenu>, mut reload_settings_menu: ResMut<ReloadSettingsMenu>) {
+    *reload_settings_menu = ReloadSettingsMenu(false);
+    if settings_menu.page == SettingsPage::Closed {
+        return;
+    }
+
+    let text_style = TextStyle {
+        font: asset_server.load("Fonts/MessyThicc.ttf"),
+        font_size: 20.0,
+        ..default()
+    };
+    let small_text_style = TextStyle {
+        font: asset_server.load("Fonts/MessyThicc.ttf"),
+        font_size: 14.0,
+        ..default()
+    };
+    let image = ui_images.sprites["UISign"].to_owned();
+
+    commands.spawn((NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            flex_direction: FlexDirection::Column,
+            position_type: PositionType::Absolute,
+            ..default()
+        },
+        focus_policy: bevy::ui::FocusPolicy::Block,
+        z_index: ZIndex::Global(10),
+        background_color: Color::rgba(0.2, 0.2, 0.22, 0.98).into(),
+        ..default()
+    }, SettingsScreen)).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            if settings_menu.page == SettingsPage::Controls {"Controls"} else {"Settings"},
+            text_style.to_owned()
+        ).with_style(Style {
+            margin: UiRect::bottom(Val::Px(10.0)),
+            ..default()
+        }));
+        parent.spawn(NodeBundle {
+            style: Style {
+                display: Display::Grid,
+                grid_template_columns: vec![GridTrack::auto(), GridTrack::auto()],
+                column_gap: Val::Px(20.0),
+                row_gap: Val::Px(4.0),
+                margin: UiRect::bottom(Val::Px(10.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            if settings_menu.page == SettingsPage::Settings {
+                let rows = [
+                    (format!("Music: {}%", (settings.music_volume * 100.0).round()), SettingChange::MusicVolume(false), SettingChange::MusicVolume(true)),
+                    (format!("Sound: {}%", (settings.sfx_volume * 100.0).round()), SettingChange::SfxVolume(false), SettingChange::SfxVolume(true)),
+                    (format!("Ambience: {}%", (settings.ambience_volume * 100.0).round()), SettingChange::AmbienceVolume(false), SettingChange::AmbienceVolume(true)),
+                    (format!("Tick speed: x{}", settings.tick_speed), SettingChange::TickSpeed(false), SettingChange::TickSpeed(true)),
+                    (format!("Animation: x{}", settings.animation_speed), SettingChange::AnimationSpeed(false), SettingChange::AnimationSpeed(true)),
+                    (format!("UI scale: {}%", (settings.ui_scale * 100.0).round()), SettingChange::UiScale(false), SettingChange::UiScale(true)),
+                ];
+                for (label, down, up) in rows {
+                    parent.spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(10.0),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        sign_button(parent, &image, &text_style, 40.0, ButtonEffect::ChangeSetting(down), None, "-");
+                        parent.spawn(TextBundle::from_section(
+                            label,
+                            small_text_style.to_owned()
+                        ).with_style(Style {
+                            width: Val::Px(110.0),
+                            margin: UiRect::bottom(Val::Px(10.0)),
+                            ..default()
+                        }));
+                        sign_button(parent, &image, &text_style, 40.0, ButtonEffect::ChangeSetting(up), None, "+");
+                    });
+                }
+                if !ONLINE_BUILD {
+                    sign_button(parent, &image, &text_style, 220.0, ButtonEffect::ChangeSetting(SettingChange::Fullscreen), None, if settings.fullscreen {"Fullscreen: On"} else {"Fullscreen: Off"});
+                }
+                return;
+            }
+            for action in InputAction::all() {
+                let label = if settings_menu.listening == Some(action) {
+                    format!("{}: press something (Esc cancels)", action.label())
+                } else {
+                    format!("{}: {}", action.label(), input_map.describe(action))
+                };
+                parent.spawn((ButtonBundle {
+                    style: Style {
+                        height: Val::Px(18.0),
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::NONE.into(),
+                    ..default()
+                },
+                MenuButton{
+                    button_effect: ButtonEffect::Rebind(action),
+                    level: None,
+                    hovering: false,
+                    hover_time: 0.0,
+                    ..default()
+                }))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        label,
+                        small_text_style.to_owned()
+                    ));
+                });
+            }
+        });
+        parent.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                column_gap: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            if settings_menu.page == SettingsPage::Controls {
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::ResetControls, None, "Reset");
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::Settings, None, "Back");
+            } else {
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::Controls, None, "Controls");
+                sign_button(parent, &image, &text_style, 160.0, ButtonEffect::CloseSettings, None, "Back");
+            }
+        });
+    });
+}
+
+pub fn settings_menu_cleanup(mut commands: Commands, settings_q: Query<Entity, With<SettingsScreen>>) {
+    for screen in &settings_q {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
 pub fn pause_menu_cleanup(
     mut commands: Commands,
     mut pause_menu_data: ResMut<PauseMenuData>
@@ -1805,9 +2527,11 @@ pub fn game_cleanup(
     mut simulating: ResMut<SimulateRes>,
     mut q_cursor: Query<&mut Cursor>, 
     rain_q: Query<Entity, With<Raindrop>>,
+    cloud_q: Query<Entity, With<CloudShadow>>,
     mut sprite_q: Query<&mut Sprite>,
-    weather: Res<Weather>,
+    mut weather: ResMut<Weather>,
 ) {
+    weather.reset();
     if let Some(overlay_id) = weather.overlay {
         if let Ok(mut overlay) = sprite_q.get_mut(overlay_id) {
             overlay.color = Color::rgba(0.05, 0.05, 0.25, 0.0);
@@ -1816,6 +2540,9 @@ pub fn game_cleanup(
     for raindrop in &rain_q {
         commands.entity(raindrop).despawn();
     }
+    for cloud in &cloud_q {
+        commands.entity(cloud).despawn_recursive();
+    }
     simulating.win = false;
     simulating.loss = false;
     simulating.simulating = false;
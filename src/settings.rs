@@ -0,0 +1,106 @@
+
+use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowMode};
+use bevy_pkv::PkvStore;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use crate::*;
+
+//Saved in the PkvStore under "settings", next to the medals and controls
+#[derive(Resource)]
+#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub ambience_volume: f32,
+    //Multipliers on TICK_SPEED and ANIMATION_SPEED, higher is faster
+    pub tick_speed: f32,
+    pub animation_speed: f32,
+    pub fullscreen: bool,
+    //On top of the scale that fits the UI to the window
+    pub ui_scale: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        return Settings { music_volume: 0.75, sfx_volume: 1.0, ambience_volume: 0.75, tick_speed: 1.0, animation_speed: 1.0, fullscreen: false, ui_scale: 1.0 };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Default)]
+pub enum SettingsPage {
+    #[default] Closed,
+    Settings,
+    Controls,
+}
+
+#[derive(Resource)]
+#[derive(Default)]
+pub struct SettingsMenu {
+    pub page: SettingsPage,
+    pub listening: Option<InputAction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingChange {
+    MusicVolume(bool),
+    SfxVolume(bool),
+    AmbienceVolume(bool),
+    TickSpeed(bool),
+    AnimationSpeed(bool),
+    Fullscreen,
+    UiScale(bool),
+}
+
+impl Settings {
+    pub fn load(pkv: &PkvStore) -> Self {
+        return pkv.get::<Settings>("settings").unwrap_or_default();
+    }
+
+    pub fn save(&self, pkv: &mut PkvStore) {
+        if let Err(error) = pkv.set("settings", self) {
+            println!("Could not save settings: {}", error);
+        }
+    }
+
+    pub fn tick(&self) -> Duration {
+        return Duration::from_secs_f32(TICK_SPEED / self.tick_speed);
+    }
+
+    pub fn change(&mut self, change: SettingChange) {
+        let step = |value: f32, up: bool, size: f32, min: f32, max: f32| ((value / size).round() + if up {1.0} else {-1.0}).clamp(min / size, max / size) * size;
+        match change {
+            SettingChange::MusicVolume(up) => {self.music_volume = step(self.music_volume, up, 0.05, 0.0, 1.0);}
+            SettingChange::SfxVolume(up) => {self.sfx_volume = step(self.sfx_volume, up, 0.05, 0.0, 1.0);}
+            SettingChange::AmbienceVolume(up) => {self.ambience_volume = step(self.ambience_volume, up, 0.05, 0.0, 1.0);}
+            SettingChange::TickSpeed(up) => {self.tick_speed = if up {(self.tick_speed * 2.0).min(4.0)} else {(self.tick_speed / 2.0).max(0.25)};}
+            SettingChange::AnimationSpeed(up) => {self.animation_speed = if up {(self.animation_speed * 2.0).min(4.0)} else {(self.animation_speed / 2.0).max(0.25)};}
+            SettingChange::Fullscreen => {self.fullscreen = !self.fullscreen;}
+            SettingChange::UiScale(up) => {self.ui_scale = step(self.ui_scale, up, 0.25, 0.5, 1.5);}
+        }
+    }
+}
+
+//Pushes changed settings out to the window and the running level, audio_system picks up the volumes itself
+pub fn apply_settings(
+    settings: Res<Settings>,
+    mut q_windows: Query<&mut Window, With<PrimaryWindow>>,
+    field: Option<ResMut<Field>>,
+    replay_player: Res<ReplayPlayer>,){
+    if !settings.is_changed() {
+        return;
+    }
+    if let Ok(mut window) = q_windows.get_single_mut() {
+        let mode = if settings.fullscreen {WindowMode::BorderlessFullscreen} else {WindowMode::Windowed};
+        if window.mode != mode {
+            window.mode = mode;
+        }
+    }
+    //Replays keep setting their own speed
+    if let (Some(mut field), None) = (field, &replay_player.replay) {
+        field.simulate_timer.set_duration(settings.tick());
+    }
+}
@@ -8,7 +8,7 @@ pub fn simulate(
     mut commands: Commands, 
     mut field: ResMut<Field>,
     time: Res<Time>,
-    sounds: Res<Sounds>,
+    mut audio: ResMut<AudioManager>,
     sprites: Res<Sprites>,
     mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
     mut simulating: ResMut<SimulateRes>,
@@ -18,8 +18,9 @@ pub fn simulate(
     mut pause_menu_data: ResMut<PauseMenuData>,
     mut pkv: ResMut<PkvStore>,
     mut medals: ResMut<Medals>,
-    mut working_q: Query<(&mut TextureAtlasSprite, &mut Visibility, &mut AnimationTimer)>,
-    tile_q: Query<&Tile>,){
+    replay_player: Res<ReplayPlayer>,
+    mut weather: ResMut<Weather>,
+    mut working_q: Query<(&mut TextureAtlasSprite, &mut Visibility, &mut AnimationTimer)>,){
     if let Some(indicator) = simulating.indicator {
         if let Ok((mut tex, mut visible, mut timer)) = working_q.get_mut(indicator){
             if simulating.simulating {
@@ -57,91 +58,88 @@ pub fn simulate(
         next_state.set(GameState::Pause);
         return;
     }
+    //Redoing back into a won round puts the win screen back up
+    if simulating.win && !simulating.simulating && saving.saving == SaveStage::Idle {
+        pause_menu_data.mode = PauseMenuMode::Win;
+        next_state.set(GameState::Pause);
+        return;
+    }
     if simulating.simulating && !simulating.loss && !simulating.win {
         field.simulate_timer.tick(time.delta());
         if field.simulate_timer.just_finished() {
-            let mut full_simulation = false;
-            if simulating.simulation_step == EntityType::None {
-                simulating.simulation_step = EntityType::Goat;
-                full_simulation = true;
-            }
             println!("Simulation Tick!");
-            let mut has_simulated = false;
-            while simulating.simulation_step != EntityType::None && has_simulated != true {
-                for entity in field.get_entities(&entity_q.to_readonly()) {
-                    if entity.entity_type != simulating.simulation_step {
-                        continue;
-                    }
-                    let mut state = entity.state;
-                    if entity.state == EntityState::Special {
-                        state = match entity.entity_type {
-                            EntityType::Chicken => {EntityState::Sliding}
-                            EntityType::Pig => {EntityState::Idle}
-                            EntityType::Horse => {EntityState::Idle}
-                            EntityType::Goat => {EntityState::Eating}
-                            _ => {entity.state}
-                        };
-                    }
-                    match entity.entity_type {
-                        EntityType::Chicken | EntityType::Pig | EntityType::Horse | EntityType::Goat | EntityType::Wagon => {
-                            match state {
-                                EntityState::Eating => {
-                                    if field.can_get_tile(entity.location.x, entity.location.y) {
-                                        if let Some(entity) = field.tiles[entity.location.x][entity.location.y].3 {
-                                            if let Ok(mut entity) = entity_q.get_mut(entity) {
-                                                entity.state = EntityState::Idle;
-                                                simulating.simulating = true;
-                                                has_simulated = true;
-                                            }
-                                        }
-                                    }
-                                }
-                                EntityState::Idle => {
-                                    let target_location = field.can_see_food(entity, &entity_q.to_readonly(), &tile_q);
-                                    if target_location.x != entity.location.x || target_location.y != entity.location.y {
-                                        if !field.move_entity(&mut commands, &mut entity_q, &tile_q, &sounds, &sprites, &mut rng, entity, target_location) {
-                                            simulating.simulating = false;
-                                            simulating.loss = true;
-                                            println!("FAIL STATE");
-                                            return;
-                                        }
-                                        simulating.simulating = true;
-                                        has_simulated = true;
-                                        println!("{}", simulating.simulating);
-                                    }
-                                }
-                                EntityState::Walking => {
-                                    if !field.move_entity(&mut commands, &mut entity_q, &tile_q, &sounds, &sprites, &mut rng, entity, entity.target_location) {
-                                        simulating.simulating = false;
-                                        simulating.loss = true;
-                                        println!("FAIL STATE");
-                                        return;
-                                    }
-                                    simulating.simulating = true;
-                                    has_simulated = true;
-                                }
-                                EntityState::Sliding => {
-                                    if entity.last_direction != MoveDirection::None {
-                                        if !field.slide_entity(&mut commands, &mut entity_q, &tile_q, &sounds, &sprites, &mut rng, entity, entity.last_direction) {
-                                            simulating.simulating = false;
-                                            simulating.loss = true;
-                                            println!("FAIL STATE");
-                                            return;
-                                        }
-                                        simulating.simulating = true;
-                                        has_simulated = true;
-                                    }
-                                }
-                                _ => {}
+            let before: HashMap<usize, Piece> = field.board.pieces().into_iter().map(|piece| (piece.id, piece)).collect();
+            //Rain and thunder get their go before the first step of each round
+            let mut result = StepResult { events: vec![], outcome: StepOutcome::Running };
+            if field.board.round != simulating.rounds {
+                result = field.board.start_round(simulating.rounds);
+            }
+            //If the weather didn't do anything there's no need to spend a tick on it
+            if result.events.is_empty() && result.outcome == StepOutcome::Running {
+                result = field.board.step(&mut simulating.simulation_step);
+            }
+            for event in &result.events {
+                audio.on_board_event(event, &mut *rng);
+                match *event {
+                    BoardEvent::Moved { id, state, .. } => {
+                        if state == EntityState::Sliding {
+                            if let Some(entity) = field.pieces.get(&id) {
+                                let mud_effect = commands.spawn((SpriteSheetBundle {
+                                        texture_atlas: sprites.sprites["MuddySplash"].clone(),
+                                        sprite: TextureAtlasSprite::new(0),
+                                        transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                                        ..default()
+                                    }, Effect(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating)))).id();
+                                commands.entity(*entity).push_children(&[mud_effect]);
                             }
                         }
-                        _ => {}
                     }
+                    BoardEvent::Slammed { .. } => {
+                        println!("SLAM");
+                    }
+                    BoardEvent::Ate { food, .. } => {
+                        field.despawn_piece(&mut commands, food);
+                    }
+                    BoardEvent::ReachedFlag { flag, .. } => {
+                        field.spawn_flag(&mut commands, &sprites, flag.location.x, flag.location.y);
+                    }
+                    BoardEvent::Toggled { at, tile_type } => {
+                        field.set_tile(&mut commands, &sprites, tile_type, at.x, at.y);
+                    }
+                    BoardEvent::Cracked { at, tile_type } => {
+                        field.set_tile(&mut commands, &sprites, tile_type, at.x, at.y);
+                    }
+                    BoardEvent::Muddied { at } => {
+                        field.set_tile(&mut commands, &sprites, TileType::Mud, at.x, at.y);
+                    }
+                    BoardEvent::Spooked { .. } => {
+                        weather.flash = 1.0;
+                    }
+                    _ => {}
                 }
-                if field.check_win(&mut entity_q, &tile_q) {
-                    for mut entity in &mut entity_q {
-                        entity.state = EntityState::Celebrating;
+            }
+            //Only touch sprites whose piece changed, so animations that are still playing keep their state
+            for piece in field.board.pieces() {
+                if before.get(&piece.id) != Some(&piece) {
+                    if let Some(entity) = field.pieces.get(&piece.id) {
+                        if let Ok(mut entity) = entity_q.get_mut(*entity) {
+                            entity.location = piece.location;
+                            entity.target_location = piece.target_location;
+                            entity.state = piece.state;
+                            entity.last_direction = piece.last_direction;
+                        }
                     }
+                }
+            }
+            match result.outcome {
+                StepOutcome::Lost => {
+                    simulating.simulating = false;
+                    simulating.loss = true;
+                    audio.duck(LOSS_DUCK_TIME);
+                    println!("FAIL STATE");
+                    return;
+                }
+                StepOutcome::Won => {
                     pause_menu_data.mode = PauseMenuMode::Win;
                     let mut earned_medal = 1;
                     if simulating.rounds <= field.par {
@@ -150,24 +148,22 @@ pub fn simulate(
                     if simulating.rounds <= field.author_par {
                         earned_medal = 3;
                     }
-                    if medals.medals[&field.level_id] < earned_medal {
-                        *medals.medals.get_mut(&field.level_id).unwrap() = earned_medal;
-                        pkv.set("save", &medals.to_owned()).expect("failed to store medals");
+                    //Only campaign levels hand out medals, editor levels can't unlock anything
+                    //Watching someone else's replay doesn't count either
+                    if let (Some(&best), None) = (medals.medals.get(&field.level_id), &replay_player.replay) {
+                        if best < earned_medal {
+                            medals.medals.insert(field.level_id.to_owned(), earned_medal);
+                            pkv.set("save", &medals.to_owned()).expect("failed to store medals");
+                        }
                     }
-                    saving.saving = SaveStage::SaveUndo;
+                    simulating.simulating = false;
+                    simulating.win = true;
                     next_state.set(GameState::Pause);
                 }
-                simulating.simulation_step = match simulating.simulation_step {
-                    EntityType::Goat => {EntityType::Horse}
-                    EntityType::Horse => {EntityType::Pig}
-                    EntityType::Pig => {EntityType::Chicken}
-                    EntityType::Chicken => {EntityType::Wagon}
-                    EntityType::Wagon => {EntityType::None}
-                    _ => {EntityType::None}
-                };
-                if simulating.simulation_step == EntityType::None && !has_simulated && full_simulation {
+                StepOutcome::Settled => {
                     simulating.simulating = false;
                 }
+                StepOutcome::Running => {}
             }
         }
         if !simulating.simulating {
@@ -0,0 +1,117 @@
+//Finds the fewest rounds a level can be beaten in, for checking pars. If --seconds runs out first it says how many
+//rounds it takes at least and the quickest win it found. --beam N only keeps N fields at each step, which gets through
+//big levels quicker but can miss the best way, so what it finds is only an upper bound on the par.
+//Usage: sokobarn-solve <level.skb> [--foods N] [--max-rounds N] [--max-states N] [--seconds N] [--beam N]
+
+use sokobarn::board::*;
+use sokobarn::level::*;
+use sokobarn::solver::*;
+
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let mut solver = Solver::default();
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--foods" => {
+                solver.foods_per_round = parse_count(args.next(), "--foods");
+            }
+            "--max-rounds" => {
+                solver.max_rounds = parse_count(args.next(), "--max-rounds");
+            }
+            "--max-states" => {
+                solver.max_states = parse_count(args.next(), "--max-states");
+            }
+            "--seconds" => {
+                solver.seconds = parse_count(args.next(), "--seconds") as u64;
+            }
+            "--beam" => {
+                //--beam 0 is the same as leaving it out
+                solver.beam = Some(parse_count(args.next(), "--beam")).filter(|beam| *beam > 0);
+            }
+            _ => {
+                path = Some(arg);
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("Usage: sokobarn-solve <level.skb> [--foods N] [--max-rounds N] [--max-states N] [--seconds N] [--beam N]");
+        process::exit(2);
+    };
+
+    let save = match fs::read_to_string(&path) {
+        Ok(save_string) => match serde_json::from_str::<SaveFile>(&save_string) {
+            Ok(save) => save,
+            Err(error) => {
+                eprintln!("Level Loading Failed! Error: {}", error);
+                process::exit(1);
+            }
+        },
+        Err(error) => {
+            eprintln!("Could not read {}: {}", path, error);
+            process::exit(1);
+        }
+    };
+    let mut board = Board::new(save.width, save.height);
+    board.load(&save);
+    board.rules = save.meta.as_ref().and_then(|meta| meta.rules).unwrap_or_default();
+    board.stock_tray(save.meta.as_ref().and_then(|meta| meta.food.as_ref()));
+
+    match solver.solve(&board) {
+        SolveResult::Solved(solution) => {
+            if solution.fewest {
+                println!("{}: solved in {} rounds, and nothing takes fewer", path, solution.rounds);
+            } else {
+                println!("{}: beaten in {} rounds, so par shouldn't be any higher. Leave out --beam for the fewest.", path, solution.rounds);
+            }
+            print_moves(&solution);
+        }
+        SolveResult::Unsolvable => {
+            println!("{}: no solution", path);
+            process::exit(1);
+        }
+        SolveResult::TooManyRounds => {
+            println!("{}: none within {} rounds, try a bigger --max-rounds", path, solver.max_rounds);
+            process::exit(1);
+        }
+        SolveResult::GaveUp { at_least, best: Some(solution) } => {
+            println!("{}: takes between {} and {} rounds, try a bigger --seconds to narrow it down", path, at_least, solution.rounds);
+            print_moves(&solution);
+            process::exit(1);
+        }
+        SolveResult::GaveUp { at_least, best: None } => {
+            println!("{}: gave up without a solution, it takes at least {} rounds. Try a bigger --seconds.", path, at_least);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_moves(solution: &Solution) {
+    for (round, placements) in solution.moves.iter().enumerate() {
+        let placements: Vec<String> = placements.iter().map(|placement| match (placement.from, placement.to) {
+            (Some((from_x, from_y)), Some((x, y))) => format!("{:?} from ({}, {}) to ({}, {})", placement.entity_type, from_x, from_y, x, y),
+            (None, Some((x, y))) => format!("{:?} at ({}, {})", placement.entity_type, x, y),
+            (Some((x, y)), None) => format!("{:?} at ({}, {}) back on the tray", placement.entity_type, x, y),
+            (None, None) => format!("{:?}", placement.entity_type),
+        }).collect();
+        if placements.is_empty() {
+            println!("Round {}: just press Start", round + 1);
+        } else {
+            println!("Round {}: {}", round + 1, placements.join(", "));
+        }
+    }
+}
+
+fn parse_count(arg: Option<String>, flag: &str) -> usize {
+    match arg.map(|arg| arg.parse::<usize>()) {
+        Some(Ok(count)) => count,
+        _ => {
+            eprintln!("{} needs a number", flag);
+            process::exit(2);
+        }
+    }
+}
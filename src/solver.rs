@@ -0,0 +1,781 @@
+use crate::board::*;
+use crate::level::*;
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+//Everyone a pen can be for
+static RESIDENTS: [EntityType; 7] = [EntityType::Chicken, EntityType::Horse, EntityType::Pig, EntityType::Goat, EntityType::Wagon, EntityType::Duck, EntityType::Sheep];
+
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq)]
+pub struct Placement {
+    pub entity_type: EntityType,
+    //Where the food was picked up from, or None if it came from the tray
+    pub from: Option<(usize, usize)>,
+    //Where it was put down, or None if it went back on the tray
+    pub to: Option<(usize, usize)>,
+}
+
+pub struct Solution {
+    pub rounds: usize,
+    //Whether nothing wins in fewer rounds, or this is just the best the beam found
+    pub fewest: bool,
+    //What to move before each press of Start
+    pub moves: Vec<Vec<Placement>>,
+}
+
+pub enum SolveResult {
+    Solved(Solution),
+    //Nothing wins however many rounds it gets. Without the exact search that's only said when a pen can't be got to.
+    Unsolvable,
+    //Nothing wins within max_rounds, but it might in more. Only the exact search can tell.
+    TooManyRounds,
+    //Ran out of time first. Nothing wins in fewer than at_least rounds, and best is the quickest win found on the way.
+    GaveUp { at_least: usize, best: Option<Solution> },
+}
+
+pub struct Solver {
+    //Most foods moved or put down in a single round
+    pub foods_per_round: usize,
+    pub max_rounds: usize,
+    //Rounds that take longer than this are treated as never settling
+    pub max_steps: usize,
+    //Most fields kept in memory before giving up
+    pub max_states: usize,
+    //How long to look before giving up and saying how close it got
+    pub seconds: u64,
+    //None tries everything that could make a difference, so the first win found takes the fewest rounds there are.
+    //Some keeps only that many fields at each step, which gets through bigger levels but only gives an upper bound.
+    pub beam: Option<usize>,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Solver { foods_per_round: usize::MAX, max_rounds: 60, max_steps: 2000, max_states: 250_000, seconds: 60, beam: None }
+    }
+}
+
+//Everything that can change during a level. The only tiles that do are gates, ice and mud when it rains, flags only go away.
+#[derive(Clone)]
+#[derive(PartialEq, Eq, Hash)]
+struct Snapshot {
+    animals: Vec<Piece>,
+    flying: Vec<Piece>,
+    foods: Vec<Piece>,
+    flags: Vec<Flag>,
+    buttons: Vec<Piece>,
+    tiles: Vec<Tile>,
+    //Rounds this far into the weather's cycle have the same rain and thunder still to come
+    phase: usize,
+    //Thunder sends everyone back the way they came, so then that matters for every animal
+    spooks: bool,
+}
+
+type StateKey = Vec<Fact>;
+
+//One thing that's true of a field, like an animal standing somewhere or a gate being open
+type Fact = (EntityType, Option<usize>, Location, EntityState, MoveDirection);
+
+type PenDistances = Vec<(EntityType, Option<usize>, Vec<Vec<usize>>)>;
+
+//How the field ends up after pressing Start with some foods down
+struct Node {
+    snapshot: Snapshot,
+    //The node whose field the round started from
+    round_start: Option<usize>,
+    //Everything put down or moved before Start, in order
+    placements: Vec<Placement>,
+    //Tray foods not used yet this round
+    tray: Vec<EntityType>,
+    rounds: usize,
+    won: bool,
+}
+
+impl Solver {
+    pub fn solve(&self, board: &Board) -> SolveResult {
+        let deadline = Instant::now() + Duration::from_secs(self.seconds);
+        return match self.beam {
+            Some(beam) => {
+                let Some(at_least) = rounds_left(&pen_distances(board), &snapshot(board)) else {
+                    return SolveResult::Unsolvable;
+                };
+                self.search(board, Some(beam), self.max_rounds, at_least.max(1), deadline)
+            }
+            None => self.solve_exactly(board, deadline),
+        };
+    }
+
+    //Lets the beam find a win first, widening it each time it doesn't, then plays every food one at a time without
+    //cutting anything to see if fewer rounds than that will do
+    fn solve_exactly(&self, board: &Board, deadline: Instant) -> SolveResult {
+        let Some(at_least) = rounds_left(&pen_distances(board), &snapshot(board)) else {
+            return SolveResult::Unsolvable;
+        };
+        //The beam gets half the time
+        let beam_deadline = Instant::now() + deadline.saturating_duration_since(Instant::now()) / 2;
+        let mut best = None;
+        for beam in [200, 1000] {
+            if let SolveResult::Solved(solution) = self.search(board, Some(beam), self.max_rounds, at_least.max(1), beam_deadline) {
+                best = Some(solution);
+                break;
+            }
+        }
+        let rounds = best.as_ref().map_or(self.max_rounds, |solution| solution.rounds - 1);
+        return match (self.search(board, None, rounds, at_least.max(1), deadline), best) {
+            (SolveResult::Solved(solution), _) => SolveResult::Solved(solution),
+            (SolveResult::GaveUp { at_least, .. }, best) => SolveResult::GaveUp { at_least, best },
+            //Nothing wins in fewer rounds than the beam's
+            (_, Some(mut solution)) => {
+                solution.fewest = true;
+                SolveResult::Solved(solution)
+            }
+            (other, None) => other,
+        };
+    }
+
+    //Puts down one food at a time and plays the round out after each, looking at fields by how many rounds they took.
+    //With a beam it keeps only the fields closest to winning at each step, so a win it finds is real but only an upper
+    //bound, and at_least is what it says when it gives up since it can't rule anything out itself. Without one the
+    //first win is the fewest rounds there are, for as far as the foods worth putting down go.
+    fn search(&self, board: &Board, beam: Option<usize>, max_rounds: usize, at_least: usize, deadline: Instant) -> SolveResult {
+        let base = bare_field(board);
+        let start = snapshot(board);
+        let pens = pen_distances(board);
+        //For each layout of the field, the (rounds, tray) pairs that aren't beaten by another one
+        let mut best: HashMap<StateKey, Vec<(usize, Vec<EntityType>)>> = HashMap::new();
+        let mut states = 1;
+        best.insert(state_key(&start, &board.tray), vec![(0, vec![])]);
+        let mut nodes = vec![Node { snapshot: start, round_start: None, placements: vec![], tray: vec![], rounds: 0, won: false }];
+        //Nodes still to look at, by rounds and then by how many foods are down this round
+        let mut layers: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::from([((0, 0), vec![0])]);
+        //What the fields carried on so far have had true, by how many goals they had left
+        let mut seen: HashSet<(usize, Fact)> = HashSet::new();
+        //The foods already played from each round start, since putting the same ones down in another order ends the same
+        let mut tried: HashSet<(usize, Vec<(EntityType, Option<(usize, usize)>, Option<(usize, usize)>)>)> = HashSet::new();
+        //Whether any field could have gone on for more rounds than it was let
+        let mut cut_short = false;
+        while let Some(((rounds, _), mut layer)) = layers.pop_first() {
+            //Every field that took fewer rounds has been played out, unless the beam cut it
+            let at_least = if beam.is_none() {at_least.max(rounds)} else {at_least};
+            if let Some(&index) = layer.iter().find(|index| nodes[**index].won) {
+                let mut moves = vec![];
+                let mut node = index;
+                while let Some(round_start) = nodes[node].round_start {
+                    moves.push(nodes[node].placements.clone());
+                    node = round_start;
+                }
+                moves.reverse();
+                return SolveResult::Solved(Solution { rounds: moves.len(), fewest: beam.is_none(), moves });
+            }
+            layer.retain(|index| best.get(&state_key(&nodes[*index].snapshot, &board.tray)).map_or(false, |front| {
+                front.iter().any(|(rounds, tray)| *rounds == nodes[*index].rounds && *tray == nodes[*index].tray)
+            }));
+            if let Some(beam) = beam.filter(|beam| layer.len() > *beam) {
+                //Dropped fields can be come back to in a later round
+                for index in narrow(&mut layer, beam, &pens, &nodes, &mut seen, &board.tray) {
+                    if let Some(front) = best.get_mut(&state_key(&nodes[index].snapshot, &board.tray)) {
+                        front.retain(|(rounds, tray)| *rounds != nodes[index].rounds || *tray != nodes[index].tray);
+                    }
+                }
+            }
+            for index in layer {
+                if Instant::now() > deadline {
+                    return SolveResult::GaveUp { at_least, best: None };
+                }
+                let mut edges = vec![];
+                if let Some(round_start) = nodes[index].round_start {
+                    if nodes[index].placements.len() < self.foods_per_round {
+                        edges.push((round_start, nodes[index].placements.clone(), nodes[index].tray.clone(), nodes[index].rounds));
+                    }
+                }
+                if nodes[index].rounds < max_rounds {
+                    let round_start = restore(&base, &nodes[index].snapshot);
+                    edges.push((index, vec![], round_start.refill_tray(), nodes[index].rounds + 1));
+                } else {
+                    cut_short = true;
+                }
+                for (round_start, placements, tray, rounds) in edges {
+                    let mut setup = restore(&base, &nodes[round_start].snapshot);
+                    let mut movable: Vec<(usize, usize)> = nodes[round_start].snapshot.foods.iter().map(|food| (food.location.x, food.location.y)).collect();
+                    for placement in &placements {
+                        if let Some((from_x, from_y)) = placement.from {
+                            setup.take_food(from_x, from_y);
+                            movable.retain(|location| *location != (from_x, from_y));
+                        }
+                        if let Some((x, y)) = placement.to {
+                            setup.set_entity(placement.entity_type, x, y);
+                        }
+                    }
+                    let mut view = restore(&base, &nodes[index].snapshot);
+                    //The first food of a round goes down before the weather, but the animals only look for it after
+                    if nodes[index].rounds < rounds {
+                        view.start_round(rounds);
+                    }
+                    let mut options = vec![];
+                    //The level might start with an animal already looking at food
+                    if nodes[round_start].round_start.is_none() && placements.is_empty() {
+                        options.push((None, vec![]));
+                    }
+                    for (from, x, y, foods) in self.relevant_placements(&setup, &view, &tray, &movable) {
+                        options.push((Some((from, x, y)), foods));
+                    }
+                    for (option, foods) in options {
+                        if let Some((from, x, y)) = option {
+                            let mut layout: Vec<(EntityType, Option<(usize, usize)>, Option<(usize, usize)>)> = placements.iter().map(|placement| (placement.entity_type, placement.from, placement.to)).collect();
+                            layout.push((foods[0], from, Some((x, y))));
+                            layout.sort();
+                            if !tried.insert((round_start, layout)) {
+                                continue;
+                            }
+                        }
+                        let mut next = setup.clone();
+                        let mut placed = None;
+                        if let Some((from, x, y)) = option {
+                            if let Some((from_x, from_y)) = from {
+                                next.take_food(from_x, from_y);
+                            }
+                            next.set_entity(foods[0], x, y);
+                            placed = next.cells[x][y].food.map(|food| food.id);
+                        }
+                        let outcome = self.play_round(&mut next, rounds);
+                        if outcome != StepOutcome::Won && outcome != StepOutcome::Settled {
+                            continue;
+                        }
+                        let played = snapshot(&next);
+                        let mut choices = vec![];
+                        if let Some((from, x, y)) = option {
+                            //Foods the same animals like play out the same way, so only the leftover's type differs.
+                            //Conveyors can carry it off, so if it's still there it's found by id rather than where it went down.
+                            for food in foods {
+                                let mut placements = placements.clone();
+                                placements.push(Placement { entity_type: food, from, to: Some((x, y)) });
+                                let mut tray = tray.clone();
+                                if from.is_none() {
+                                    if let Some(index) = tray.iter().position(|tray_food| *tray_food == food) {
+                                        tray.remove(index);
+                                    }
+                                }
+                                let mut played = played.clone();
+                                for piece in played.foods.iter_mut().filter(|piece| Some(piece.id) == placed) {
+                                    piece.entity_type = food;
+                                }
+                                choices.push((placements, tray, played));
+                            }
+                        } else {
+                            choices.push((placements.clone(), tray.clone(), played));
+                        }
+                        for (placements, tray, played) in choices {
+                            let won = outcome == StepOutcome::Won;
+                            if !won {
+                                let front = best.entry(state_key(&played, &board.tray)).or_default();
+                                if front.iter().any(|(best_rounds, best_tray)| *best_rounds <= rounds && holds_all(best_tray, &tray)) {
+                                    continue;
+                                }
+                                if states >= self.max_states {
+                                    return SolveResult::GaveUp { at_least, best: None };
+                                }
+                                states -= front.len();
+                                front.retain(|(best_rounds, best_tray)| !(rounds <= *best_rounds && holds_all(&tray, best_tray)));
+                                front.push((rounds, tray.clone()));
+                                states += front.len();
+                            }
+                            layers.entry((rounds, placements.len())).or_default().push(nodes.len());
+                            nodes.push(Node { snapshot: played, round_start: Some(round_start), placements, tray, rounds, won });
+                        }
+                    }
+                }
+            }
+        }
+        if beam.is_none() {
+            return if cut_short {SolveResult::TooManyRounds} else {SolveResult::Unsolvable};
+        }
+        //Not finding anything only means the beam didn't, so nothing is ruled out
+        return SolveResult::GaveUp { at_least, best: None };
+    }
+
+    //Presses Start and runs the simulation until everyone stops, the weather going first like in the game
+    pub fn play_round(&self, board: &mut Board, round: usize) -> StepOutcome {
+        let outcome = board.start_round(round).outcome;
+        if outcome != StepOutcome::Running {
+            return outcome;
+        }
+        let mut simulation_step = EntityType::None;
+        for _ in 0..self.max_steps {
+            let result = board.step(&mut simulation_step);
+            if result.outcome != StepOutcome::Running {
+                return result.outcome;
+            }
+        }
+        return StepOutcome::Running;
+    }
+
+    //Foods worth adding to a round: free tiles where an idle animal would go for one once the round so far
+    //has played out (view), with tray foods grouped by which animals they'd draw. Foods that started the round
+    //on the field can also be moved somewhere out of the way.
+    fn relevant_placements(&self, setup: &Board, view: &Board, tray: &Vec<EntityType>, movable: &Vec<(usize, usize)>) -> Vec<(Option<(usize, usize)>, usize, usize, Vec<EntityType>)> {
+        //Where from, where to, which foods and which animals would like them
+        let mut ret_val: Vec<(Option<(usize, usize)>, usize, usize, Vec<EntityType>, Vec<bool>)> = vec![];
+        let everyone = view.get_entities();
+        let lookouts: Vec<Piece> = everyone.iter().chain(setup.get_entities().iter()).copied().collect();
+        //A second seed off the tray could only go the same places as the first
+        let mut sources: Vec<(Option<(usize, usize)>, EntityType)> = tray.iter().map(|food| (None, *food)).collect();
+        sources.dedup();
+        for (x, y) in movable {
+            if let Some(food) = setup.cells[*x][*y].food {
+                sources.push((Some((*x, *y)), food.entity_type));
+            }
+        }
+        let mut view = view.clone();
+        for (from, food) in sources {
+            let mut moved = None;
+            if let Some((from_x, from_y)) = from {
+                moved = view.take_food(from_x, from_y);
+            }
+            let mut out_of_the_way = None;
+            for x in 0..view.width {
+                for y in 0..view.height {
+                    if !setup.can_place_food(x, y) || !view.can_place_food(x, y) {
+                        continue;
+                    }
+                    //Animals only look along their row and column
+                    if !lookouts.iter().any(|animal| animal.location.x == x || animal.location.y == y) {
+                        if out_of_the_way.is_none() {
+                            out_of_the_way = Some((x, y));
+                        }
+                        continue;
+                    }
+                    view.set_entity(food, x, y);
+                    let drawn = everyone.iter().any(|animal| {
+                        let target = view.can_see_food(*animal);
+                        animal.state == EntityState::Idle && target.x == x && target.y == y
+                    });
+                    let liked_by: Vec<bool> = everyone.iter().map(|animal| view.likes_food_on_tile(*animal, x, y)).collect();
+                    view.take_food(x, y);
+                    if !drawn {
+                        continue;
+                    }
+                    //Goats pick Mixed Food over a tie, so it never shares a group
+                    let same = ret_val.iter_mut().find(|(other_from, other_x, other_y, other_foods, other_liked_by)| {
+                        from.is_none() && other_from.is_none() && *other_x == x && *other_y == y && *other_liked_by == liked_by
+                            && food != EntityType::AllFood && !other_foods.contains(&EntityType::AllFood)
+                    });
+                    match same {
+                        Some((_, _, _, other_foods, _)) => other_foods.push(food),
+                        None => ret_val.push((from, x, y, vec![food], liked_by)),
+                    }
+                }
+            }
+            if let Some((from_x, from_y)) = from {
+                view.cells[from_x][from_y].food = moved;
+                if let Some((x, y)) = out_of_the_way {
+                    ret_val.push((from, x, y, vec![food], vec![]));
+                }
+            }
+        }
+        return ret_val.into_iter().map(|(from, x, y, foods, _)| (from, x, y, foods)).collect();
+    }
+}
+
+//The field with every piece and flag taken off, for restore to put a snapshot back onto
+fn bare_field(board: &Board) -> Board {
+    let mut ret_val = board.clone();
+    for column in ret_val.cells.iter_mut() {
+        for cell in column.iter_mut() {
+            cell.animal = None;
+            cell.flying = None;
+            cell.food = None;
+        }
+    }
+    for flag in board.flags() {
+        ret_val.take_flag(flag.location.x, flag.location.y);
+    }
+    return ret_val;
+}
+
+fn snapshot(board: &Board) -> Snapshot {
+    let mut ret_val = Snapshot { animals: vec![], flying: vec![], foods: vec![], flags: vec![], buttons: vec![], tiles: vec![], phase: board.round % board.rules.cycle(), spooks: board.rules.spook_every.map_or(false, |every| every > 0) };
+    let rains = board.rules.mud_every.map_or(false, |every| every > 0);
+    for column in &board.cells {
+        for cell in column {
+            ret_val.animals.extend(cell.animal);
+            ret_val.flying.extend(cell.flying);
+            ret_val.foods.extend(cell.food);
+            ret_val.flags.extend(cell.flag);
+            ret_val.buttons.extend(cell.button);
+            //Water too, since it might have been ice when the level started
+            if let TileType::Gate(_) | TileType::OpenGate(_) | TileType::Ice | TileType::CrackedIce | TileType::Water = cell.tile.tile_type {
+                ret_val.tiles.push(cell.tile);
+            } else if rains && cell.tile.tile_type == TileType::Mud {
+                ret_val.tiles.push(cell.tile);
+            }
+        }
+    }
+    return ret_val;
+}
+
+fn restore(base: &Board, snapshot: &Snapshot) -> Board {
+    let mut board = base.clone();
+    for animal in &snapshot.animals {
+        board.cells[animal.location.x][animal.location.y].animal = Some(*animal);
+    }
+    for animal in &snapshot.flying {
+        board.cells[animal.location.x][animal.location.y].flying = Some(*animal);
+    }
+    for food in &snapshot.foods {
+        board.cells[food.location.x][food.location.y].food = Some(*food);
+    }
+    for flag in &snapshot.flags {
+        board.set_flag(flag.index, flag.location.x, flag.location.y);
+    }
+    for button in &snapshot.buttons {
+        board.cells[button.location.x][button.location.y].button = Some(*button);
+    }
+    for tile in &snapshot.tiles {
+        board.cells[tile.location.x][tile.location.y].tile = *tile;
+    }
+    return board;
+}
+
+//For every pen tile, how many tiles its animal would have to go to get there from anywhere on the field. Gates
+//count as open and animals as out of the way, so a pen is only really out of reach if it can't be got to here.
+fn pen_distances(board: &Board) -> PenDistances {
+    //Goats slam animals over ditches, and Horses pull Wagons through one-way gates whichever way they turn
+    let slams = board.get_entities().iter().any(|animal| animal.entity_type == EntityType::Goat);
+    let pulls = board.get_entities().iter().any(|animal| animal.entity_type == EntityType::Horse);
+    let mut ret_val = vec![];
+    for column in &board.cells {
+        for cell in column {
+            let Some(resident) = RESIDENTS.iter().find(|resident| cell.tile.tile_type.is_pen_for(**resident)) else {
+                continue;
+            };
+            let mut distances = vec![vec![usize::MAX; board.height]; board.width];
+            distances[cell.tile.location.x][cell.tile.location.y] = 0;
+            let mut queue = VecDeque::from([(cell.tile.location.x, cell.tile.location.y)]);
+            //Backwards from the pen, so each step is onto (x, y) from the tile before it
+            while let Some((x, y)) = queue.pop_front() {
+                let tile_type = board.cells[x][y].tile.tile_type;
+                //Chickens fly over ditches and Ducks swim them
+                if tile_type == TileType::Ditch && !slams && *resident != EntityType::Chicken && *resident != EntityType::Duck {
+                    continue;
+                }
+                for direction in [MoveDirection::Left, MoveDirection::Right, MoveDirection::Up, MoveDirection::Down] {
+                    let Some((next_x, next_y)) = board.next_tile(x, y, direction.opposite()) else {
+                        continue;
+                    };
+                    let pulled = pulls && *resident == EntityType::Wagon && matches!(tile_type, TileType::OneWay(_));
+                    if (tile_type.blocks_from(*resident, direction) && !pulled) || distances[next_x][next_y] != usize::MAX {
+                        continue;
+                    }
+                    distances[next_x][next_y] = distances[x][y] + 1;
+                    queue.push_back((next_x, next_y));
+                }
+            }
+            ret_val.push((*resident, cell.tile.pen, distances));
+        }
+    }
+    return ret_val;
+}
+
+//How far a field is from being won, lowest first: flags and pen tiles still to go, then for each pen tile how far
+//the closest animal that fits it is. Animals that can't get to a pen at all count as being the whole field away.
+fn distance_left(pens: &PenDistances, snapshot: &Snapshot) -> (usize, usize) {
+    let mut goals = snapshot.flags.len();
+    let mut distance = 0;
+    for (resident, pen, distances) in pens {
+        let closest = snapshot.animals.iter().chain(snapshot.flying.iter())
+            .filter(|animal| animal.entity_type == *resident && animal.pen == *pen)
+            .map(|animal| distances[animal.location.x][animal.location.y])
+            .min().unwrap_or(usize::MAX);
+        if closest > 0 {
+            goals += 1;
+        }
+        distance += closest.min(distances.len() * distances[0].len());
+    }
+    return (goals, distance);
+}
+
+//The fewest rounds the field could still be won in going by the pens: one while any pen tile is missing its animal,
+//and None if one can't get there at all
+fn rounds_left(pens: &PenDistances, snapshot: &Snapshot) -> Option<usize> {
+    let mut ret_val = if snapshot.flags.is_empty() {0} else {1};
+    for (resident, pen, distances) in pens {
+        let closest = snapshot.animals.iter().chain(snapshot.flying.iter())
+            .filter(|animal| animal.entity_type == *resident && animal.pen == *pen)
+            .map(|animal| distances[animal.location.x][animal.location.y])
+            .min().unwrap_or(usize::MAX);
+        if closest == usize::MAX {
+            return None;
+        }
+        if closest > 0 {
+            ret_val = 1;
+        }
+    }
+    return Some(ret_val);
+}
+
+//Cuts a layer down to the beam. Half of it goes to the fields closest to winning, the rest to the closest of the
+//others that have something true no field kept with as many goals left has had yet, so the search doesn't spend
+//the whole beam on one idea that gets stuck. Returns the fields that were cut.
+fn narrow(layer: &mut Vec<usize>, beam: usize, pens: &PenDistances, nodes: &Vec<Node>, seen: &mut HashSet<(usize, Fact)>, tray: &Vec<EntityType>) -> Vec<usize> {
+    let mut scored: Vec<((usize, usize), usize)> = layer.iter().map(|index| (distance_left(pens, &nodes[*index].snapshot), *index)).collect();
+    scored.sort();
+    let mut kept = vec![];
+    let mut ret_val = vec![];
+    for (position, ((goals, _), index)) in scored.into_iter().enumerate() {
+        let facts = state_key(&nodes[index].snapshot, tray);
+        if kept.len() < beam && (position < beam / 2 || facts.iter().any(|fact| !seen.contains(&(goals, *fact)))) {
+            seen.extend(facts.into_iter().map(|fact| (goals, fact)));
+            kept.push(index);
+        } else {
+            ret_val.push(index);
+        }
+    }
+    //Anything that wasn't new fills up whatever room is left
+    let room = beam - kept.len();
+    let rest = ret_val.split_off(room.min(ret_val.len()));
+    kept.extend(ret_val);
+    *layer = kept;
+    return rest;
+}
+
+//Where leftover foods are isn't part of it. They can be moved next round anyway, and keeping that made the search
+//blow up on fields that only differ by where an uneaten food is. Foods the level started with on top of the tray
+//are in it though, since they can still be used up and the tray won't hand them out again.
+fn state_key(snapshot: &Snapshot, tray: &Vec<EntityType>) -> StateKey {
+    //Only sliding animals care which way they last moved, unless there's thunder
+    let mut ret_val: StateKey = snapshot.animals.iter().chain(snapshot.flying.iter()).map(|piece| {
+        let direction = if piece.state == EntityState::Sliding || snapshot.spooks {piece.last_direction} else {MoveDirection::None};
+        (piece.entity_type, piece.pen, piece.location, piece.state, direction)
+    }).collect();
+    //Flags go away as the animals reach them
+    ret_val.extend(snapshot.flags.iter().map(|flag| (EntityType::None, None, flag.location, EntityState::Idle, MoveDirection::None)));
+    //A button only matters while it's held down, so it can't be pressed again yet
+    ret_val.extend(snapshot.buttons.iter().filter(|button| button.state == EntityState::Special).map(|button| (button.entity_type, None, button.location, button.state, MoveDirection::None)));
+    ret_val.extend(snapshot.tiles.iter().filter(|tile| matches!(tile.tile_type, TileType::OpenGate(_))).map(|gate| (EntityType::None, None, gate.location, EntityState::Walking, MoveDirection::None)));
+    //Ice by how far it has cracked
+    ret_val.extend(snapshot.tiles.iter().filter(|tile| matches!(tile.tile_type, TileType::CrackedIce | TileType::Water)).map(|ice| (EntityType::None, None, ice.location, if ice.tile_type == TileType::Water {EntityState::Special} else {EntityState::Sliding}, MoveDirection::None)));
+    //Mud the rain has spread so far
+    ret_val.extend(snapshot.tiles.iter().filter(|tile| tile.tile_type == TileType::Mud).map(|mud| (EntityType::None, None, mud.location, EntityState::Eating, MoveDirection::None)));
+    let mut stock = tray.clone();
+    let mut extra: Vec<EntityType> = vec![];
+    for food in &snapshot.foods {
+        match stock.iter().position(|tray_food| *tray_food == food.entity_type) {
+            Some(index) => {stock.remove(index);}
+            None => extra.push(food.entity_type),
+        }
+    }
+    extra.sort();
+    ret_val.extend(extra.into_iter().map(|food| (food, None, Location::default(), EntityState::Idle, MoveDirection::None)));
+    //The same field with different weather to come plays out differently
+    ret_val.push((EntityType::None, Some(snapshot.phase), Location::default(), EntityState::Idle, MoveDirection::None));
+    return ret_val;
+}
+
+//Whether every food in `tray` is also in `more`, both in TRAY_FOODS order
+fn holds_all(more: &Vec<EntityType>, tray: &Vec<EntityType>) -> bool {
+    let mut more = more.iter().peekable();
+    for food in tray {
+        while more.peek().map_or(false, |other| *other != food) {
+            more.next();
+        }
+        if more.next() != Some(food) {
+            return false;
+        }
+    }
+    return true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(board: &mut Board, tile_type: TileType, x: usize, y: usize) {
+        board.set_tile(Tile { tile_type, location: Location { x, y, z: 0 }, pen: None });
+    }
+
+    fn solve(board: &Board) -> Solution {
+        return match Solver::default().solve(board) {
+            SolveResult::Solved(solution) => solution,
+            SolveResult::Unsolvable => panic!("came back unsolvable"),
+            SolveResult::TooManyRounds => panic!("came back with nothing within {} rounds", Solver::default().max_rounds),
+            SolveResult::GaveUp { at_least, .. } => panic!("gave up at {} rounds", at_least),
+        };
+    }
+
+    //Makes the moves the way a player would and presses Start after each round, returning how the last one ended
+    fn replay(board: &Board, solution: &Solution) -> StepOutcome {
+        let mut board = board.clone();
+        let mut outcome = StepOutcome::Settled;
+        for (round, placements) in solution.moves.iter().enumerate() {
+            assert_eq!(outcome, StepOutcome::Settled);
+            for placement in placements {
+                if let Some((x, y)) = placement.from {
+                    assert_eq!(board.take_food(x, y).map(|food| food.entity_type), Some(placement.entity_type));
+                }
+                if let Some((x, y)) = placement.to {
+                    assert!(board.refill_tray().contains(&placement.entity_type) || placement.from.is_some());
+                    board.set_entity(placement.entity_type, x, y);
+                }
+            }
+            outcome = board.start_round(round + 1).outcome;
+            let mut simulation_step = EntityType::None;
+            for _ in 0..100 {
+                if outcome != StepOutcome::Running {
+                    break;
+                }
+                outcome = board.step(&mut simulation_step).outcome;
+            }
+        }
+        return outcome;
+    }
+
+    #[test]
+    fn one_food_in_line_takes_one_round() {
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::PigPen, 3, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.tray = vec![EntityType::PigFood];
+        let solution = solve(&board);
+        assert_eq!(solution.rounds, 1);
+        assert!(solution.fewest);
+        assert_eq!(replay(&board, &solution), StepOutcome::Won);
+    }
+
+    #[test]
+    fn pen_around_a_corner_takes_two_rounds() {
+        let mut board = Board::new(4, 2);
+        tile(&mut board, TileType::PigPen, 3, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.tray = vec![EntityType::PigFood];
+        let solution = solve(&board);
+        assert_eq!(solution.rounds, 2);
+        assert!(solution.fewest);
+        assert_eq!(solution.moves.len(), 2);
+        assert_eq!(replay(&board, &solution), StepOutcome::Won);
+    }
+
+    #[test]
+    fn fenced_off_pen_is_unsolvable() {
+        let mut board = Board::new(4, 1);
+        tile(&mut board, TileType::Fence, 1, 0);
+        tile(&mut board, TileType::PigPen, 3, 0);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.tray = vec![EntityType::PigFood];
+        assert!(matches!(Solver::default().solve(&board), SolveResult::Unsolvable));
+    }
+
+    #[test]
+    fn food_the_level_starts_with_can_be_used_up() {
+        //One of the foods is the tray's, which only gets the Pig round the corner in two rounds. The other is extra,
+        //and both together do it in one.
+        let mut board = Board::new(4, 2);
+        tile(&mut board, TileType::PigPen, 3, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.set_entity(EntityType::PigFood, 1, 1);
+        board.set_entity(EntityType::PigFood, 2, 1);
+        board.tray = vec![EntityType::PigFood];
+        let solution = solve(&board);
+        assert_eq!(solution.rounds, 1);
+        assert!(solution.fewest);
+        assert_eq!(solution.moves[0].iter().filter(|placement| placement.from.is_some() && placement.to.is_some()).count(), 2);
+        assert_eq!(replay(&board, &solution), StepOutcome::Won);
+    }
+
+    #[test]
+    fn state_key_keeps_foods_the_tray_doesnt_hand_out() {
+        let mut board = Board::new(4, 2);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.tray = vec![EntityType::PigFood];
+        let bare = state_key(&snapshot(&board), &board.tray);
+        //One food is the tray's, whichever it is and wherever it was left
+        board.set_entity(EntityType::PigFood, 1, 1);
+        let one = state_key(&snapshot(&board), &board.tray);
+        assert!(one == bare);
+        //The second is extra, and can still be used wherever it is
+        board.set_entity(EntityType::PigFood, 2, 0);
+        let two = state_key(&snapshot(&board), &board.tray);
+        assert!(two != bare);
+        board.take_food(2, 0);
+        board.set_entity(EntityType::PigFood, 3, 1);
+        assert!(state_key(&snapshot(&board), &board.tray) == two);
+    }
+
+    #[test]
+    fn too_few_rounds_allowed_is_not_unsolvable() {
+        let mut board = Board::new(4, 2);
+        tile(&mut board, TileType::PigPen, 3, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.tray = vec![EntityType::PigFood];
+        let solver = Solver { max_rounds: 1, ..Solver::default() };
+        assert!(matches!(solver.solve(&board), SolveResult::TooManyRounds));
+    }
+
+    #[test]
+    fn foods_nobody_tells_apart_come_off_the_tray_as_themselves() {
+        let mut board = Board::new(4, 2);
+        tile(&mut board, TileType::PigPen, 3, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.tray = vec![EntityType::ChickenFood, EntityType::HorseFood, EntityType::PigFood];
+        let solution = solve(&board);
+        assert_eq!(solution.rounds, 2);
+        assert!(solution.fewest);
+        assert_eq!(replay(&board, &solution), StepOutcome::Won);
+    }
+
+    #[test]
+    fn out_of_time_still_says_how_many_rounds_at_least() {
+        let mut board = Board::new(4, 2);
+        tile(&mut board, TileType::PigPen, 3, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.tray = vec![EntityType::PigFood];
+        let solver = Solver { seconds: 0, ..Solver::default() };
+        assert!(matches!(solver.solve(&board), SolveResult::GaveUp { at_least: 1, best: None }));
+    }
+
+    #[test]
+    fn goats_slam_wagons_over_ditches() {
+        let mut board = Board::new(1, 4);
+        tile(&mut board, TileType::Ditch, 0, 2);
+        tile(&mut board, TileType::Corral, 0, 3);
+        board.set_entity(EntityType::Wagon, 0, 1);
+        let pens = pen_distances(&board);
+        assert_eq!(rounds_left(&pens, &snapshot(&board)), None);
+        board.set_entity(EntityType::Goat, 0, 0);
+        let pens = pen_distances(&board);
+        assert_eq!(rounds_left(&pens, &snapshot(&board)), Some(1));
+    }
+
+    #[test]
+    fn beam_only_claims_an_upper_bound() {
+        let mut board = Board::new(4, 2);
+        tile(&mut board, TileType::PigPen, 3, 1);
+        board.set_entity(EntityType::Pig, 0, 0);
+        board.tray = vec![EntityType::PigFood];
+        let solver = Solver { beam: Some(10), ..Solver::default() };
+        let SolveResult::Solved(solution) = solver.solve(&board) else {
+            panic!("beam found nothing");
+        };
+        assert!(!solution.fewest);
+        assert!(solution.rounds >= 2);
+        assert_eq!(replay(&board, &solution), StepOutcome::Won);
+    }
+
+    #[test]
+    fn beats_the_first_goat_tutorial_within_the_default_time() {
+        let save: SaveFile = serde_json::from_str(include_str!("../assets/Levels/goat-tutorial-1.skb")).unwrap();
+        let mut board = Board::new(save.width, save.height);
+        board.load(&save);
+        board.rules = save.meta.as_ref().and_then(|meta| meta.rules).unwrap_or_default();
+        board.stock_tray(save.meta.as_ref().and_then(|meta| meta.food.as_ref()));
+        //Ruling out fewer rounds takes longer than that, but the beam's win still comes back
+        let solution = match Solver::default().solve(&board) {
+            SolveResult::Solved(solution) => solution,
+            SolveResult::GaveUp { best: Some(solution), .. } => solution,
+            _ => panic!("found no way to beat it"),
+        };
+        //The author's par
+        assert!(solution.rounds <= 4);
+        assert_eq!(replay(&board, &solution), StepOutcome::Won);
+    }
+}
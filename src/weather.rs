@@ -8,128 +8,183 @@ pub struct Weather {
     pub weather: WeatherType,
     pub raindrops: u128,
     pub raindrop_count: u128,
-    pub overlay: Option<Entity>
+    pub overlay: Option<Entity>,
+    //Seconds until the next cloud drifts in or lightning strikes
+    pub next_cloud: f32,
+    pub next_strike: f32,
+    //Brightness of the current lightning flash, fades back to 0
+    pub flash: f32,
 }
 
+impl Weather {
+    //The looping sound bed that goes under each kind of weather
+    pub fn ambience(&self) -> &'static str {
+        return match self.weather {
+            WeatherType::Sunny => "AmbienceDay",
+            WeatherType::Cloudy => "AmbienceWind",
+            WeatherType::Raining => "AmbienceRain",
+            WeatherType::Night => "AmbienceNight",
+            WeatherType::RainyNight => "AmbienceRainyNight",
+            WeatherType::Thunder => "AmbienceStorm",
+        };
+    }
 
-#[derive(PartialEq)]
-#[derive(Clone, Copy)]
-#[derive(Default)]
-#[allow(dead_code)]
-pub enum WeatherType {
-    #[default] Sunny,
-    Cloudy,
-    Raining,
-    Night,
-    RainyNight,
-    Thunder
+    //Clears out the effects so the next level starts from a clean sky, the first strike waits a moment
+    pub fn reset(&mut self) {
+        self.next_cloud = 0.0;
+        self.next_strike = 3.0;
+        self.flash = 0.0;
+    }
 }
 
 #[derive(Component, Deref, DerefMut)]
 pub struct Raindrop(Timer);
 
+//A patch of shade that drifts across the board, made of a few overlapping blobs
+#[derive(Component)]
+pub struct CloudShadow {
+    pub speed: f32,
+}
+
+fn rain(commands: &mut Commands, 
+    sprites: &Sprites,
+    time: &Time,
+    rng: &mut GlobalEntropy<ChaCha8Rng>,
+    rain_q: &mut Query<(Entity, &mut TextureAtlasSprite, &mut Scaling, &mut Raindrop), Without<CloudShadow>>,
+    weather: &mut Weather,) {
+    weather.raindrops += time.delta().as_micros();
+    let mut raindrops = weather.raindrops / weather.raindrop_count;
+    weather.raindrops = weather.raindrops % weather.raindrop_count;
+    while raindrops > 0 {
+        commands.spawn((SpriteSheetBundle {
+            texture_atlas: sprites.sprites["Rain"].clone(),
+            sprite: TextureAtlasSprite::new(0),
+            transform: Transform::from_xyz(0.0, 0.0, 110.0),
+            ..default()
+        }, Raindrop(Timer::from_seconds(((rng.next_u32() % 600) as f32) / 100.0, TimerMode::Once)), 
+        Scaling { position: Vec2{ x: ((rng.next_u32() % 1600) as f32) / 100.0, y: 10.0 + ((((rng.next_u32() % 200) as i32) - 100) as f32) / 100.0 } }));
+        raindrops -= 1;
+    }
+
+    for (raindrop, mut raindrop_sprite, mut raindrop_position, mut raindrop_timer) in rain_q.iter_mut() {
+        if raindrop_sprite.index == 0 {
+            raindrop_position.position += Vec2 { x:-0.02, y:-0.15 };
+        }
+        raindrop_timer.tick(time.delta());
+        if raindrop_timer.just_finished() {
+            if raindrop_sprite.index == 0 {
+                *raindrop_timer = Raindrop(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating));
+            }
+            raindrop_sprite.index += 1;
+            if raindrop_sprite.index >= 4 {
+                commands.entity(raindrop).despawn();
+            }
+        }
+    }
+}
+
+fn clouds(commands: &mut Commands, 
+    time: &Time,
+    rng: &mut GlobalEntropy<ChaCha8Rng>,
+    cloud_q: &mut Query<(Entity, &mut Scaling, &CloudShadow), Without<Raindrop>>,
+    weather: &mut Weather,) {
+    weather.next_cloud -= time.delta_seconds();
+    if weather.next_cloud <= 0.0 {
+        weather.next_cloud = 3.0 + ((rng.next_u32() % 500) as f32) / 100.0;
+        let blobs = 3 + rng.next_u32() % 3;
+        //The first cloud of a level starts out over the board instead of drifting in from the edge
+        let x = if cloud_q.is_empty() {((rng.next_u32() % 1400) as f32) / 100.0} else {-4.0};
+        commands.spawn((SpatialBundle {
+            transform: Transform::from_xyz(0.0, 0.0, 95.0),
+            ..default()
+        }, CloudShadow { speed: 0.3 + ((rng.next_u32() % 40) as f32) / 100.0 }, 
+        Scaling { position: Vec2{ x, y: ((rng.next_u32() % 800) as f32) / 100.0 } })).with_children(|parent| {
+            for blob in 0..blobs {
+                let size = 48.0 + (rng.next_u32() % 48) as f32;
+                parent.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(0.0, 0.0, 0.1, 0.12),
+                        custom_size: Some(Vec2::new(size * 1.5, size)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(blob as f32 * 28.0, (((rng.next_u32() % 32) as i32) - 16) as f32, blob as f32 * 0.01),
+                    ..default()
+                });
+            }
+        });
+    }
+
+    for (cloud, mut cloud_position, cloud_shadow) in cloud_q.iter_mut() {
+        cloud_position.position.x += cloud_shadow.speed * time.delta_seconds();
+        if cloud_position.position.x > 18.0 {
+            commands.entity(cloud).despawn_recursive();
+        }
+    }
+}
+
 pub fn weather_system(mut commands: Commands, 
     sprites: Res<Sprites>,
     time: Res<Time>,
     mut rng: ResMut<GlobalEntropy<ChaCha8Rng>>,
-    mut rain_q: Query<(Entity, &mut TextureAtlasSprite, &mut Scaling, &mut Raindrop)>,
+    mut rain_q: Query<(Entity, &mut TextureAtlasSprite, &mut Scaling, &mut Raindrop), Without<CloudShadow>>,
+    mut cloud_q: Query<(Entity, &mut Scaling, &CloudShadow), Without<Raindrop>>,
     mut sprite_q: Query<&mut Sprite>,
+    mut audio: ResMut<AudioManager>,
     mut weather: ResMut<Weather>,) {
+    let overlay_color = match weather.weather {
+        WeatherType::Sunny => Color::rgba(0.05, 0.05, 0.25, 0.0),
+        WeatherType::Cloudy => Color::rgba(0.05, 0.05, 0.25, 0.1),
+        WeatherType::Raining => Color::rgba(0.05, 0.05, 0.25, 0.25),
+        WeatherType::Night => Color::rgba(0.05, 0.05, 0.25, 0.6),
+        WeatherType::RainyNight => Color::rgba(0.025, 0.025, 0.15, 0.75),
+        WeatherType::Thunder => Color::rgba(0.025, 0.025, 0.15, 0.5),
+    };
     match weather.weather {
-        WeatherType::Raining => {
-            if let Some(overlay_id) = weather.overlay {
-                if let Ok(mut overlay) = sprite_q.get_mut(overlay_id) {
-                    overlay.color = Color::rgba(0.05, 0.05, 0.25, 0.25);
-                }
-            }
-            weather.raindrops += time.delta().as_micros();
-            let mut raindrops = weather.raindrops / weather.raindrop_count;
-            weather.raindrops = weather.raindrops % weather.raindrop_count;
-            while raindrops > 0 {
-                commands.spawn((SpriteSheetBundle {
-                    texture_atlas: sprites.sprites["Rain"].clone(),
-                    sprite: TextureAtlasSprite::new(0),
-                    transform: Transform::from_xyz(0.0, 0.0, 110.0),
-                    ..default()
-                }, Raindrop(Timer::from_seconds(((rng.next_u32() % 600) as f32) / 100.0, TimerMode::Once)), 
-                Scaling { position: Vec2{ x: ((rng.next_u32() % 1600) as f32) / 100.0, y: 10.0 + ((((rng.next_u32() % 200) as i32) - 100) as f32) / 100.0 } }));
-                raindrops -= 1;
-            }
-
-            for (raindrop, mut raindrop_sprite, mut raindrop_position, mut raindrop_timer) in &mut rain_q {
-                if raindrop_sprite.index == 0 {
-                    raindrop_position.position += Vec2 { x:-0.02, y:-0.15 };
-                }
-                raindrop_timer.tick(time.delta());
-                if raindrop_timer.just_finished() {
-                    if raindrop_sprite.index == 0 {
-                        *raindrop_timer = Raindrop(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating));
-                    }
-                    raindrop_sprite.index += 1;
-                    if raindrop_sprite.index >= 4 {
-                        commands.entity(raindrop).despawn();
-                    }
-                }
-            }
+        WeatherType::Raining | WeatherType::RainyNight | WeatherType::Thunder => {
+            rain(&mut commands, &sprites, &time, &mut rng, &mut rain_q, &mut weather);
         }
-        WeatherType::Night => {
-            if let Some(overlay_id) = weather.overlay {
-                if let Ok(mut overlay) = sprite_q.get_mut(overlay_id) {
-                    overlay.color = Color::rgba(0.05, 0.05, 0.25, 0.6);
-                }
-            }
-
+        _ => {
             for (raindrop, _, _, _) in &rain_q {
                 commands.entity(raindrop).despawn();
             }
         }
-        WeatherType::RainyNight => {
-            if let Some(overlay_id) = weather.overlay {
-                if let Ok(mut overlay) = sprite_q.get_mut(overlay_id) {
-                    overlay.color = Color::rgba(0.025, 0.025, 0.15, 0.75);
-                }
-            }
-            
-            weather.raindrops += time.delta().as_micros();
-            let mut raindrops = weather.raindrops / weather.raindrop_count;
-            weather.raindrops = weather.raindrops % weather.raindrop_count;
-            while raindrops > 0 {
-                commands.spawn((SpriteSheetBundle {
-                    texture_atlas: sprites.sprites["Rain"].clone(),
-                    sprite: TextureAtlasSprite::new(0),
-                    transform: Transform::from_xyz(0.0, 0.0, 110.0),
-                    ..default()
-                }, Raindrop(Timer::from_seconds(((rng.next_u32() % 600) as f32) / 100.0, TimerMode::Once)), 
-                Scaling { position: Vec2{ x: ((rng.next_u32() % 1600) as f32) / 100.0, y: 10.0 + ((((rng.next_u32() % 200) as i32) - 100) as f32) / 100.0 } }));
-                raindrops -= 1;
-            }
-
-            for (raindrop, mut raindrop_sprite, mut raindrop_position, mut raindrop_timer) in &mut rain_q {
-                if raindrop_sprite.index == 0 {
-                    raindrop_position.position += Vec2 { x:-0.02, y:-0.15 };
-                }
-                raindrop_timer.tick(time.delta());
-                if raindrop_timer.just_finished() {
-                    if raindrop_sprite.index == 0 {
-                        *raindrop_timer = Raindrop(Timer::from_seconds(ANIMATION_SPEED, TimerMode::Repeating));
-                    }
-                    raindrop_sprite.index += 1;
-                    if raindrop_sprite.index >= 4 {
-                        commands.entity(raindrop).despawn();
-                    }
-                }
-            }
+    }
+    match weather.weather {
+        WeatherType::Cloudy | WeatherType::Thunder => {
+            clouds(&mut commands, &time, &mut rng, &mut cloud_q, &mut weather);
         }
         _ => {
-            if let Some(overlay_id) = weather.overlay {
-                if let Ok(mut overlay) = sprite_q.get_mut(overlay_id) {
-                    overlay.color = Color::rgba(0.05, 0.05, 0.25, 0.0);
-                }
+            for (cloud, _, _) in &cloud_q {
+                commands.entity(cloud).despawn_recursive();
             }
+        }
+    }
+    if weather.weather == WeatherType::Thunder {
+        weather.next_strike -= time.delta_seconds();
+        if weather.next_strike <= 0.0 {
+            weather.flash = 1.0;
+            audio.play_sfx(&format!("Thunder{}", (rng.next_u32() % 3) + 1));
+            audio.duck(SLAM_DUCK_TIME);
+            weather.next_strike = 4.0 + ((rng.next_u32() % 800) as f32) / 100.0;
+        }
+    }
+    weather.flash = (weather.flash - time.delta_seconds() * 2.5).max(0.0);
 
-            for (raindrop, _, _, _) in &rain_q {
-                commands.entity(raindrop).despawn();
-            }
+    let ambience = weather.ambience();
+    if audio.ambience.as_deref() != Some(ambience) {
+        audio.play_ambience(Some(ambience));
+    }
+
+    if let Some(overlay_id) = weather.overlay {
+        if let Ok(mut overlay) = sprite_q.get_mut(overlay_id) {
+            //Lightning washes the tint out towards a pale white
+            let flash = weather.flash;
+            overlay.color = Color::rgba(
+                overlay_color.r() + (0.9 - overlay_color.r()) * flash,
+                overlay_color.g() + (0.9 - overlay_color.g()) * flash,
+                overlay_color.b() + (1.0 - overlay_color.b()) * flash,
+                overlay_color.a() + (0.45 - overlay_color.a()) * flash,
+            );
         }
     }
     if weather.overlay.is_none() {
@@ -149,4 +204,4 @@ pub fn weather_system(mut commands: Commands,
             }
         )).id());
     }
-}
\ No newline at end of file
+}